@@ -1,35 +1,70 @@
-use std::marker::PhantomData;
+use anyhow::{anyhow, ensure};
 use ark_bn254::Fr;
-use ark_ff::{BigInteger, One, PrimeField, Zero};
+use ark_ff::{Field as _, One, PrimeField, Zero};
+use num::{BigUint, Integer, ToPrimitive};
 use plonky2::{
     field::extension::Extendable,
+    gates::{lookup::LookupGate, lookup_table::LookupTable},
     hash::hash_types::RichField,
-    iop::target::Target,
-    plonk::circuit_builder::CircuitBuilder,
+    iop::{
+        generator::{GeneratedValues, SimpleGenerator},
+        target::{BoolTarget, Target},
+        witness::{PartitionWitness, Witness, WitnessWrite},
+    },
+    plonk::{circuit_builder::CircuitBuilder, circuit_data::CommonCircuitData},
+    util::serialization::{Buffer, IoError, IoResult, Read, Write},
 };
+use std::{
+    marker::PhantomData,
+    sync::{Arc, OnceLock},
+};
+
+/// Number of bits held by each limb of an `FrTarget`.
+pub const LIMB_BITS: usize = 16;
+/// Number of limbs used to represent a BN254 scalar (16 * 16 = 256 bits).
+pub const NUM_LIMBS: usize = 16;
 
+pub(crate) const LIMB_BASE: u64 = 1 << LIMB_BITS;
+const LIMB_MASK: u64 = LIMB_BASE - 1;
+
+/// A BN254 scalar field element represented by little-endian 16-bit limbs.
+///
+/// Every `FrTarget` handed out by this module has range-checked limbs and a
+/// canonical value, i.e. strictly less than the BN254 scalar modulus.
 #[derive(Debug)]
 pub struct FrTarget<F: RichField + Extendable<D>, const D: usize> {
-    pub target: Target,
+    pub limbs: [Target; NUM_LIMBS],
     _phantom: PhantomData<F>,
 }
 
 impl<F: RichField + Extendable<D>, const D: usize> FrTarget<F, D> {
     pub fn new(builder: &mut CircuitBuilder<F, D>) -> Self {
-        let target = builder.add_virtual_target();
-        Self { 
-            target,
+        let target = Self::new_unchecked(builder);
+        range_check_limbs(builder, &target.limbs);
+        assert_canonical(builder, &target.limbs);
+        target
+    }
+
+    /// Wraps existing limb targets without adding any constraints.
+    ///
+    /// The caller must already have range-checked every limb to `LIMB_BITS`
+    /// bits and constrained the value to be canonical; `is_equal` and
+    /// `is_zero` are unsound otherwise.
+    pub(crate) fn from_limbs(limbs: [Target; NUM_LIMBS]) -> Self {
+        Self {
+            limbs,
             _phantom: PhantomData,
         }
     }
 
+    fn new_unchecked(builder: &mut CircuitBuilder<F, D>) -> Self {
+        let limbs = builder.add_virtual_targets(NUM_LIMBS);
+        Self::from_limbs(limbs.try_into().unwrap())
+    }
+
     pub fn constant(value: &Fr, builder: &mut CircuitBuilder<F, D>) -> Self {
-        let bytes = value.into_bigint().to_bytes_le();
-        let target = builder.constant(F::from_canonical_u64(u64::from_le_bytes(bytes[0..8].try_into().unwrap())));
-        Self { 
-            target,
-            _phantom: PhantomData,
-        }
+        let limbs = fr_to_limbs(value).map(|limb| builder.constant(F::from_canonical_u64(limb)));
+        Self::from_limbs(limbs)
     }
 
     pub fn zero(builder: &mut CircuitBuilder<F, D>) -> Self {
@@ -41,27 +76,37 @@ impl<F: RichField + Extendable<D>, const D: usize> FrTarget<F, D> {
     }
 
     pub fn add(&self, other: &Self, builder: &mut CircuitBuilder<F, D>) -> Self {
-        let sum = builder.add(self.target, other.target);
-        Self { 
-            target: sum,
-            _phantom: PhantomData,
-        }
+        reduce(builder, &[], &[*self, *other], &[], &Fr::zero())
     }
 
     pub fn sub(&self, other: &Self, builder: &mut CircuitBuilder<F, D>) -> Self {
-        let diff = builder.sub(self.target, other.target);
-        Self { 
-            target: diff,
-            _phantom: PhantomData,
-        }
+        reduce(builder, &[], &[*self], &[*other], &Fr::zero())
     }
 
     pub fn mul(&self, other: &Self, builder: &mut CircuitBuilder<F, D>) -> Self {
-        let product = builder.mul(self.target, other.target);
-        Self { 
-            target: product,
-            _phantom: PhantomData,
+        reduce(builder, &[(*self, *other)], &[], &[], &Fr::zero())
+    }
+
+    pub fn add_const(&self, constant: &Fr, builder: &mut CircuitBuilder<F, D>) -> Self {
+        reduce(builder, &[], &[*self], &[], constant)
+    }
+
+    /// Returns `Σ cᵢ·xᵢ + constant` using a single reduction.
+    pub fn linear_combination(
+        terms: &[(Fr, Self)],
+        constant: &Fr,
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> Self {
+        let mut products = Vec::new();
+        let mut addends = Vec::new();
+        for (coefficient, x) in terms {
+            if coefficient.is_one() {
+                addends.push(*x);
+            } else if !coefficient.is_zero() {
+                products.push((Self::constant(coefficient, builder), *x));
+            }
         }
+        reduce(builder, &products, &addends, &[], constant)
     }
 
     pub fn exp_u64(&self, power: u64, builder: &mut CircuitBuilder<F, D>) -> Self {
@@ -80,9 +125,123 @@ impl<F: RichField + Extendable<D>, const D: usize> FrTarget<F, D> {
         result
     }
 
+    /// Divides by `other`, which is constrained to be non-zero.
+    ///
+    /// The quotient is computed by a witness generator and checked with a single multiplication.
+    pub fn div(&self, other: &Self, builder: &mut CircuitBuilder<F, D>) -> Self {
+        if let (Some(x), Some(y)) = (self.try_to_native(builder), other.try_to_native(builder)) {
+            assert!(!y.is_zero(), "division by zero");
+            return Self::constant(&(x / y), builder);
+        }
+
+        let quotient = Self::new(builder);
+        builder.add_simple_generator(FrDivGenerator {
+            numerator: *self,
+            denominator: *other,
+            quotient,
+        });
+        let is_zero = other.is_zero(builder);
+        builder.assert_zero(is_zero.target);
+        quotient.mul(other, builder).assert_equal(self, builder);
+        quotient
+    }
+
+    /// Returns the multiplicative inverse, constraining `self` to be non-zero.
+    pub fn inverse(&self, builder: &mut CircuitBuilder<F, D>) -> Self {
+        Self::one(builder).div(self, builder)
+    }
+
+    /// Constrains both targets to hold the same value.
+    pub fn assert_equal(&self, other: &Self, builder: &mut CircuitBuilder<F, D>) {
+        // Both sides are canonical, so equal values have equal limbs.
+        for (&a, &b) in self.limbs.iter().zip(&other.limbs) {
+            builder.connect(a, b);
+        }
+    }
+
+    pub fn is_equal(&self, other: &Self, builder: &mut CircuitBuilder<F, D>) -> BoolTarget {
+        // Limbs are below 2^16, so the sum of the squared limb differences is below 2^36 and
+        // cannot wrap around the Goldilocks modulus: it is zero iff every limb matches.
+        let mut acc = builder.zero();
+        for (&a, &b) in self.limbs.iter().zip(&other.limbs) {
+            let diff = builder.sub(a, b);
+            acc = builder.mul_add(diff, diff, acc);
+        }
+        let zero = builder.zero();
+        builder.is_equal(acc, zero)
+    }
+
+    pub fn is_zero(&self, builder: &mut CircuitBuilder<F, D>) -> BoolTarget {
+        // Limbs are below 2^16, so their sum cannot wrap around and is zero iff every limb is.
+        let sum = builder.add_many(self.limbs);
+        let zero = builder.zero();
+        builder.is_equal(sum, zero)
+    }
+
+    /// Returns `x` if `condition` is true and `y` otherwise.
+    pub fn select(
+        condition: BoolTarget,
+        x: &Self,
+        y: &Self,
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> Self {
+        let limbs = std::array::from_fn(|i| builder.select(condition, x.limbs[i], y.limbs[i]));
+        Self::from_limbs(limbs)
+    }
+
+    /// Returns `(y, x)` if `condition` is true and `(x, y)` otherwise, e.g. to order two
+    /// Merkle siblings by a path bit.
+    pub fn swap(
+        condition: BoolTarget,
+        x: &Self,
+        y: &Self,
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> (Self, Self) {
+        let first = Self::select(condition, y, x, builder);
+        let second = Self::select(condition, x, y, builder);
+        (first, second)
+    }
+
+    /// Whether `self < other` as integers in `[0, p)`, which is well defined since every
+    /// `FrTarget` holds the canonical representative of its value.
+    pub fn less_than(&self, other: &Self, builder: &mut CircuitBuilder<F, D>) -> BoolTarget {
+        borrow_out(builder, &self.limbs, &other.limbs)
+    }
+
+    /// Returns the value of a constant `FrTarget`, or `None` if any limb is not a constant.
+    pub fn try_to_native(&self, builder: &CircuitBuilder<F, D>) -> Option<Fr> {
+        let limbs = self
+            .limbs
+            .iter()
+            .map(|&limb| {
+                builder
+                    .target_as_constant(limb)
+                    .map(|v| v.to_canonical_u64())
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(limbs_to_fr(&limbs))
+    }
+
     pub fn to_native(&self, builder: &CircuitBuilder<F, D>) -> Fr {
-        let value = builder.target_as_constant(self.target).unwrap();
-        Fr::from(value.to_canonical_u64())
+        self.try_to_native(builder).unwrap()
+    }
+
+    /// Assigns `value` to the limbs of this target, typically in a `PartialWitness`.
+    pub fn set_witness<W: WitnessWrite<F>>(
+        &self,
+        witness: &mut W,
+        value: &Fr,
+    ) -> anyhow::Result<()> {
+        for (&limb, limb_value) in self.limbs.iter().zip(fr_to_limbs(value)) {
+            witness.set_target(limb, F::from_canonical_u64(limb_value))?;
+        }
+        Ok(())
+    }
+
+    /// Reads the value of this target back out of a witness, e.g. the `PartitionWitness`
+    /// returned by `generate_partial_witness`.
+    pub fn get_witness<W: Witness<F>>(&self, witness: &W) -> Fr {
+        limbs_to_fr(&self.limbs.map(|limb| witness.get_target(limb).to_canonical_u64()))
     }
 }
 
@@ -93,3 +252,880 @@ impl<F: RichField + Extendable<D>, const D: usize> Clone for FrTarget<F, D> {
         *self
     }
 }
+
+/// The BN254 scalar field modulus.
+pub fn modulus() -> BigUint {
+    BigUint::from(Fr::MODULUS)
+}
+
+/// Splits a BN254 scalar into its little-endian 16-bit limbs.
+pub fn fr_to_limbs(value: &Fr) -> [u64; NUM_LIMBS] {
+    biguint_to_limbs(&BigUint::from(value.into_bigint()), NUM_LIMBS)
+        .try_into()
+        .unwrap()
+}
+
+/// Recombines little-endian 16-bit limbs into a BN254 scalar, reducing modulo the field order.
+pub fn limbs_to_fr(limbs: &[u64]) -> Fr {
+    Fr::from(limbs_to_biguint(limbs))
+}
+
+/// Registers the limbs of `value` as public inputs, least significant limb first.
+///
+/// Each call appends `NUM_LIMBS` public inputs; `public_inputs_to_fr` reads them back.
+pub fn register_public_fr<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    value: &FrTarget<F, D>,
+) {
+    builder.register_public_inputs(&value.limbs);
+}
+
+/// Decodes BN254 scalars registered with `register_public_fr` from the public inputs of a proof,
+/// e.g. `&proof.public_inputs[start..]`.
+///
+/// Fails unless the slice holds a whole number of scalars with in-range limbs and canonical
+/// values, which a proof of a circuit built from `FrTarget`s always satisfies.
+pub fn public_inputs_to_fr<F: RichField>(public_inputs: &[F]) -> anyhow::Result<Vec<Fr>> {
+    ensure!(
+        public_inputs.len().is_multiple_of(NUM_LIMBS),
+        "expected a multiple of {NUM_LIMBS} public inputs, got {}",
+        public_inputs.len()
+    );
+    public_inputs
+        .chunks(NUM_LIMBS)
+        .map(|chunk| {
+            let limbs = chunk.iter().map(|x| x.to_canonical_u64()).collect::<Vec<_>>();
+            ensure!(
+                limbs.iter().all(|&limb| limb < LIMB_BASE),
+                "public input limb out of range: {limbs:?}"
+            );
+            let value = limbs_to_biguint(&limbs);
+            ensure!(value < modulus(), "non-canonical BN254 scalar {value}");
+            Ok(Fr::from(value))
+        })
+        .collect()
+}
+
+pub(crate) fn biguint_to_limbs(value: &BigUint, num_limbs: usize) -> Vec<u64> {
+    let mut limbs = value
+        .to_u64_digits()
+        .into_iter()
+        .flat_map(|digit| (0..64 / LIMB_BITS).map(move |i| (digit >> (i * LIMB_BITS)) & LIMB_MASK))
+        .collect::<Vec<_>>();
+    assert!(
+        limbs[num_limbs.min(limbs.len())..].iter().all(|&l| l == 0),
+        "value does not fit in {num_limbs} limbs"
+    );
+    limbs.resize(num_limbs, 0);
+    limbs
+}
+
+pub(crate) fn limbs_to_biguint(limbs: &[u64]) -> BigUint {
+    limbs.iter().rev().fold(BigUint::zero(), |acc, &limb| {
+        (acc << LIMB_BITS) + BigUint::from(limb)
+    })
+}
+
+fn num_limbs_for(value: &BigUint) -> usize {
+    (value.bits() as usize).div_ceil(LIMB_BITS)
+}
+
+fn field_from_i128<F: RichField>(value: i128) -> F {
+    if value >= 0 {
+        F::from_noncanonical_u128(value as u128)
+    } else {
+        -F::from_noncanonical_u128(value.unsigned_abs())
+    }
+}
+
+/// The identity table on `0..2^LIMB_BITS`: a limb is in range iff it can be looked up in it.
+///
+/// The table is shared so that `CircuitBuilder::is_stored` finds it by pointer comparison.
+fn limb_table() -> LookupTable {
+    static TABLE: OnceLock<LookupTable> = OnceLock::new();
+    TABLE
+        .get_or_init(|| Arc::new((0..=LIMB_MASK as u16).map(|x| (x, x)).collect()))
+        .clone()
+}
+
+/// Switches the limb range checks of every `FrTarget` created afterwards in `builder` from bit
+/// decomposition to lookups into a `2^LIMB_BITS`-entry table.
+///
+/// The table itself costs a fixed number of `LookupTableGate` rows, after which each limb
+/// costs a `LookupGate` slot instead of a `BaseSumGate`. This pays off for circuits with many
+/// limbs, e.g. a few hashes or more; `gate_count` reports both modes. Carries of the generic
+/// arithmetic are still range-checked by bit decomposition. `mul_with_gate` and
+/// `affine_with_gate` always enable the table, as every check on their rows is a lookup.
+pub fn enable_limb_lookups<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+) {
+    if builder.is_stored(limb_table()).is_none() {
+        builder.add_lookup_table_from_pairs(limb_table());
+    }
+}
+
+/// Returns the number of rows of `builder` so far, including the `LookupGate` rows holding its
+/// lookups, which `build` only adds at the end, but not the `LookupTableGate` rows of the tables
+/// themselves.
+pub fn num_rows<F: RichField + Extendable<D>, const D: usize>(
+    builder: &CircuitBuilder<F, D>,
+) -> usize {
+    let slots = LookupGate::num_slots(&builder.config);
+    let lookup_rows = (0..builder.num_luts())
+        .map(|lut| builder.get_lut_lookups(lut).len().div_ceil(slots))
+        .sum::<usize>();
+    builder.num_gates() + lookup_rows
+}
+
+/// Whether `enable_limb_lookups` has been called on `builder`.
+pub fn limb_lookups_enabled<F: RichField + Extendable<D>, const D: usize>(
+    builder: &CircuitBuilder<F, D>,
+) -> bool {
+    builder.is_stored(limb_table()).is_some()
+}
+
+pub(crate) fn range_check_limbs<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    limbs: &[Target],
+) {
+    match builder.is_stored(limb_table()) {
+        Some(table) => {
+            for &limb in limbs {
+                builder.add_lookup_from_index(limb, table);
+            }
+        }
+        None => {
+            for &limb in limbs {
+                builder.range_check(limb, LIMB_BITS);
+            }
+        }
+    }
+}
+
+/// Constrains range-checked `limbs` to encode a value strictly below the BN254 modulus, i.e.
+/// at most `p - 1`.
+pub(crate) fn assert_canonical<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    limbs: &[Target; NUM_LIMBS],
+) {
+    let max = biguint_to_limbs(&(modulus() - 1u32), NUM_LIMBS)
+        .into_iter()
+        .map(|limb| builder.constant(F::from_canonical_u64(limb)))
+        .collect::<Vec<_>>();
+    let borrow = borrow_out(builder, &max, limbs);
+    builder.assert_zero(borrow.target);
+}
+
+/// Returns whether `a < b` for range-checked little-endian limbs of the same length.
+///
+/// Computes `a - b` limb by limb; each step is shifted by `2^16` so it can be decomposed into
+/// 17 bits, the top bit signalling that no borrow occurred. The final borrow is the result.
+fn borrow_out<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    a: &[Target],
+    b: &[Target],
+) -> BoolTarget {
+    assert_eq!(a.len(), b.len());
+    let mut borrow = builder._false();
+    for (&a, &b) in a.iter().zip(b) {
+        let shifted = builder.add_const(a, F::from_canonical_u64(LIMB_BASE));
+        let diff = builder.sub(shifted, b);
+        let diff = builder.sub(diff, borrow.target);
+        let bits = builder.split_le(diff, LIMB_BITS + 1);
+        borrow = builder.not(bits[LIMB_BITS]);
+    }
+    borrow
+}
+
+/// Constrains the integer relation
+///
+/// `Σ xᵢ·yᵢ + Σ aᵢ − Σ sᵢ + c = q·p + r`
+///
+/// for a witnessed quotient `q` and a canonical remainder `r`, which is returned.
+///
+/// The relation is checked column by column over 16-bit limbs with signed carries, so every
+/// intermediate value stays far below the Goldilocks modulus and no wraparound can occur.
+fn reduce<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    products: &[(FrTarget<F, D>, FrTarget<F, D>)],
+    addends: &[FrTarget<F, D>],
+    subtrahends: &[FrTarget<F, D>],
+    constant: &Fr,
+) -> FrTarget<F, D> {
+    if let Some(value) = fold_constants(builder, products, addends, subtrahends, constant) {
+        return FrTarget::constant(&value, builder);
+    }
+
+    // Offsetting by one modulus per subtrahend keeps the left-hand side non-negative.
+    let p = modulus();
+    let constant = BigUint::from(constant.into_bigint()) + &p * subtrahends.len();
+    let max_value =
+        (&p - 1u32) * (&p - 1u32) * products.len() + (&p - 1u32) * addends.len() + &constant;
+    let num_quotient_limbs = num_limbs_for(&(max_value / &p));
+    let constant = biguint_to_limbs(&constant, num_limbs_for(&constant));
+
+    let num_columns = [
+        if products.is_empty() {
+            0
+        } else {
+            2 * NUM_LIMBS - 1
+        },
+        NUM_LIMBS,
+        constant.len(),
+        (num_quotient_limbs + NUM_LIMBS).saturating_sub(1),
+    ]
+    .into_iter()
+    .max()
+    .unwrap();
+
+    // Bound on the magnitude of any single column, used to size the carries.
+    let max_limb = LIMB_MASK as u128;
+    let column_bound = (products.len() + 1) as u128 * NUM_LIMBS as u128 * max_limb * max_limb
+        + (addends.len() + subtrahends.len() + 2) as u128 * LIMB_BASE as u128;
+    let carry_bits = (128 - (column_bound / max_limb).leading_zeros()) as usize + 1;
+    let carry_offset = 1u64 << carry_bits;
+
+    let quotient = builder.add_virtual_targets(num_quotient_limbs);
+    let remainder = FrTarget::new_unchecked(builder);
+    let carries = builder.add_virtual_targets(num_columns - 1);
+
+    builder.add_simple_generator(FrReduceGenerator {
+        products: products.to_vec(),
+        addends: addends.to_vec(),
+        subtrahends: subtrahends.to_vec(),
+        constant: constant.clone(),
+        quotient: quotient.clone(),
+        remainder,
+        carries: carries.clone(),
+        carry_offset,
+    });
+
+    range_check_limbs(builder, &quotient);
+    range_check_limbs(builder, &remainder.limbs);
+    assert_canonical(builder, &remainder.limbs);
+    for &carry in &carries {
+        builder.range_check(carry, carry_bits + 1);
+    }
+
+    let p = biguint_to_limbs(&p, NUM_LIMBS);
+    for k in 0..num_columns {
+        // Column constant, including the carry offsets folded out of `carry_{k-1} - offset`
+        // and `-2^16 * (carry_k - offset)`.
+        let mut column_constant = *constant.get(k).unwrap_or(&0) as i128;
+        if k > 0 {
+            column_constant -= carry_offset as i128;
+        }
+        if k < num_columns - 1 {
+            column_constant += (carry_offset as i128) << LIMB_BITS;
+        }
+        let mut acc = builder.constant(field_from_i128(column_constant));
+
+        for (x, y) in products {
+            for i in k.saturating_sub(NUM_LIMBS - 1)..NUM_LIMBS.min(k + 1) {
+                acc = mul_add_limbs(builder, x.limbs[i], y.limbs[k - i], acc);
+            }
+        }
+        if k < NUM_LIMBS {
+            for a in addends {
+                acc = builder.add(acc, a.limbs[k]);
+            }
+            for s in subtrahends {
+                acc = builder.sub(acc, s.limbs[k]);
+            }
+            acc = builder.sub(acc, remainder.limbs[k]);
+        }
+        for (i, &q) in quotient.iter().enumerate() {
+            if let Some(&p_limb) = k.checked_sub(i).and_then(|j| p.get(j)) {
+                if p_limb != 0 {
+                    acc = builder.mul_const_add(-F::from_canonical_u64(p_limb), q, acc);
+                }
+            }
+        }
+        if k > 0 {
+            acc = builder.add(acc, carries[k - 1]);
+        }
+        if k < num_columns - 1 {
+            acc = builder.mul_const_add(-F::from_canonical_u64(LIMB_BASE), carries[k], acc);
+        }
+        builder.assert_zero(acc);
+    }
+
+    remainder
+}
+
+/// Returns `x * y + acc`, using a constant multiplication when either limb is known.
+fn mul_add_limbs<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    x: Target,
+    y: Target,
+    acc: Target,
+) -> Target {
+    match (builder.target_as_constant(x), builder.target_as_constant(y)) {
+        (Some(c), _) if c.is_zero() => acc,
+        (_, Some(c)) if c.is_zero() => acc,
+        (Some(c), _) => builder.mul_const_add(c, y, acc),
+        (_, Some(c)) => builder.mul_const_add(c, x, acc),
+        (None, None) => builder.mul_add(x, y, acc),
+    }
+}
+
+fn fold_constants<F: RichField + Extendable<D>, const D: usize>(
+    builder: &CircuitBuilder<F, D>,
+    products: &[(FrTarget<F, D>, FrTarget<F, D>)],
+    addends: &[FrTarget<F, D>],
+    subtrahends: &[FrTarget<F, D>],
+    constant: &Fr,
+) -> Option<Fr> {
+    let mut value = *constant;
+    for (x, y) in products {
+        value += x.try_to_native(builder)? * y.try_to_native(builder)?;
+    }
+    for a in addends {
+        value += a.try_to_native(builder)?;
+    }
+    for s in subtrahends {
+        value -= s.try_to_native(builder)?;
+    }
+    Some(value)
+}
+
+/// Witness generator for the quotient, remainder and carries of a `reduce` relation.
+#[derive(Debug)]
+pub struct FrReduceGenerator<F: RichField + Extendable<D>, const D: usize> {
+    products: Vec<(FrTarget<F, D>, FrTarget<F, D>)>,
+    addends: Vec<FrTarget<F, D>>,
+    subtrahends: Vec<FrTarget<F, D>>,
+    constant: Vec<u64>,
+    quotient: Vec<Target>,
+    remainder: FrTarget<F, D>,
+    carries: Vec<Target>,
+    carry_offset: u64,
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> FrReduceGenerator<F, D> {
+    fn inputs(&self) -> impl Iterator<Item = &FrTarget<F, D>> {
+        self.products
+            .iter()
+            .flat_map(|(x, y)| [x, y])
+            .chain(&self.addends)
+            .chain(&self.subtrahends)
+    }
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> SimpleGenerator<F, D>
+    for FrReduceGenerator<F, D>
+{
+    fn id(&self) -> String {
+        "FrReduceGenerator".to_string()
+    }
+
+    fn dependencies(&self) -> Vec<Target> {
+        self.inputs().flat_map(|x| x.limbs).collect()
+    }
+
+    fn run_once(
+        &self,
+        witness: &PartitionWitness<F>,
+        out_buffer: &mut GeneratedValues<F>,
+    ) -> anyhow::Result<()> {
+        let read = |x: &FrTarget<F, D>| {
+            x.limbs
+                .map(|limb| witness.get_target(limb).to_canonical_u64())
+        };
+        let products = self
+            .products
+            .iter()
+            .map(|(x, y)| (read(x), read(y)))
+            .collect::<Vec<_>>();
+        let addends = self.addends.iter().map(read).collect::<Vec<_>>();
+        let subtrahends = self.subtrahends.iter().map(read).collect::<Vec<_>>();
+
+        let witness = reduce_witness(
+            &products,
+            &addends,
+            &subtrahends,
+            &self.constant,
+            self.quotient.len(),
+            self.carries.len() + 1,
+            self.carry_offset,
+        )?;
+
+        for (&target, &value) in self.quotient.iter().zip(&witness.quotient) {
+            out_buffer.set_target(target, F::from_canonical_u64(value))?;
+        }
+        for (&target, &value) in self.remainder.limbs.iter().zip(&witness.remainder) {
+            out_buffer.set_target(target, F::from_canonical_u64(value))?;
+        }
+        for (&target, &value) in self.carries.iter().zip(&witness.carries) {
+            out_buffer.set_target(target, F::from_canonical_u64(value))?;
+        }
+        Ok(())
+    }
+
+    fn serialize(&self, dst: &mut Vec<u8>, _common_data: &CommonCircuitData<F, D>) -> IoResult<()> {
+        dst.write_usize(self.products.len())?;
+        for (x, y) in &self.products {
+            write_fr_target(dst, x)?;
+            write_fr_target(dst, y)?;
+        }
+        write_fr_target_vec(dst, &self.addends)?;
+        write_fr_target_vec(dst, &self.subtrahends)?;
+        dst.write_usize(self.constant.len())?;
+        for &limb in &self.constant {
+            dst.write_usize(limb as usize)?;
+        }
+        dst.write_target_vec(&self.quotient)?;
+        write_fr_target(dst, &self.remainder)?;
+        dst.write_target_vec(&self.carries)?;
+        dst.write_usize(self.carry_offset as usize)
+    }
+
+    fn deserialize(src: &mut Buffer, _common_data: &CommonCircuitData<F, D>) -> IoResult<Self> {
+        let num_products = src.read_usize()?;
+        let products = (0..num_products)
+            .map(|_| Ok((read_fr_target(src)?, read_fr_target(src)?)))
+            .collect::<IoResult<Vec<_>>>()?;
+        let addends = read_fr_target_vec(src)?;
+        let subtrahends = read_fr_target_vec(src)?;
+        let num_constant_limbs = src.read_usize()?;
+        let constant = (0..num_constant_limbs)
+            .map(|_| Ok(src.read_usize()? as u64))
+            .collect::<IoResult<Vec<_>>>()?;
+        let quotient = src.read_target_vec()?;
+        let remainder = read_fr_target(src)?;
+        let carries = src.read_target_vec()?;
+        let carry_offset = src.read_usize()? as u64;
+        Ok(Self {
+            products,
+            addends,
+            subtrahends,
+            constant,
+            quotient,
+            remainder,
+            carries,
+            carry_offset,
+        })
+    }
+}
+
+/// Witness generator for the quotient computed by `FrTarget::div`.
+#[derive(Debug)]
+pub struct FrDivGenerator<F: RichField + Extendable<D>, const D: usize> {
+    numerator: FrTarget<F, D>,
+    denominator: FrTarget<F, D>,
+    quotient: FrTarget<F, D>,
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> SimpleGenerator<F, D>
+    for FrDivGenerator<F, D>
+{
+    fn id(&self) -> String {
+        "FrDivGenerator".to_string()
+    }
+
+    fn dependencies(&self) -> Vec<Target> {
+        self.numerator
+            .limbs
+            .into_iter()
+            .chain(self.denominator.limbs)
+            .collect()
+    }
+
+    fn run_once(
+        &self,
+        witness: &PartitionWitness<F>,
+        out_buffer: &mut GeneratedValues<F>,
+    ) -> anyhow::Result<()> {
+        let numerator = self.numerator.get_witness(witness);
+        let denominator = self.denominator.get_witness(witness);
+        let inverse = denominator
+            .inverse()
+            .ok_or_else(|| anyhow!("division by zero in FrTarget::div"))?;
+        self.quotient.set_witness(out_buffer, &(numerator * inverse))
+    }
+
+    fn serialize(&self, dst: &mut Vec<u8>, _common_data: &CommonCircuitData<F, D>) -> IoResult<()> {
+        write_fr_target(dst, &self.numerator)?;
+        write_fr_target(dst, &self.denominator)?;
+        write_fr_target(dst, &self.quotient)
+    }
+
+    fn deserialize(src: &mut Buffer, _common_data: &CommonCircuitData<F, D>) -> IoResult<Self> {
+        Ok(Self {
+            numerator: read_fr_target(src)?,
+            denominator: read_fr_target(src)?,
+            quotient: read_fr_target(src)?,
+        })
+    }
+}
+
+struct ReduceWitness {
+    quotient: Vec<u64>,
+    remainder: Vec<u64>,
+    carries: Vec<u64>,
+}
+
+/// Native counterpart of the `reduce` constraints: computes the quotient, remainder and
+/// offset carries from the limb values of the operands.
+fn reduce_witness(
+    products: &[([u64; NUM_LIMBS], [u64; NUM_LIMBS])],
+    addends: &[[u64; NUM_LIMBS]],
+    subtrahends: &[[u64; NUM_LIMBS]],
+    constant: &[u64],
+    num_quotient_limbs: usize,
+    num_columns: usize,
+    carry_offset: u64,
+) -> anyhow::Result<ReduceWitness> {
+    let mut positive = limbs_to_biguint(constant);
+    for (x, y) in products {
+        positive += limbs_to_biguint(x) * limbs_to_biguint(y);
+    }
+    for a in addends {
+        positive += limbs_to_biguint(a);
+    }
+    let negative = subtrahends
+        .iter()
+        .fold(BigUint::zero(), |acc, s| acc + limbs_to_biguint(s));
+    ensure!(
+        positive >= negative,
+        "non-canonical subtrahend in FrTarget reduction"
+    );
+
+    let (quotient, remainder) = (positive - negative).div_rem(&modulus());
+    ensure!(
+        num_limbs_for(&quotient) <= num_quotient_limbs,
+        "quotient does not fit in {num_quotient_limbs} limbs"
+    );
+    let quotient = biguint_to_limbs(&quotient, num_quotient_limbs);
+    let remainder = biguint_to_limbs(&remainder, NUM_LIMBS);
+    let p = biguint_to_limbs(&modulus(), NUM_LIMBS);
+
+    let mut columns = vec![0i128; num_columns];
+    for (x, y) in products {
+        for i in 0..NUM_LIMBS {
+            for j in 0..NUM_LIMBS {
+                columns[i + j] += (x[i] * y[j]) as i128;
+            }
+        }
+    }
+    for (k, &c) in constant.iter().enumerate() {
+        columns[k] += c as i128;
+    }
+    for k in 0..NUM_LIMBS {
+        columns[k] += addends.iter().map(|a| a[k] as i128).sum::<i128>();
+        columns[k] -= subtrahends.iter().map(|s| s[k] as i128).sum::<i128>();
+        columns[k] -= remainder[k] as i128;
+    }
+    for (i, &q) in quotient.iter().enumerate() {
+        for (j, &p) in p.iter().enumerate() {
+            columns[i + j] -= (q * p) as i128;
+        }
+    }
+
+    let mut carry = 0i128;
+    let mut carries = Vec::with_capacity(num_columns - 1);
+    for &column in &columns[..num_columns - 1] {
+        let total = column + carry;
+        ensure!(
+            total % LIMB_BASE as i128 == 0,
+            "inexact carry in FrTarget reduction"
+        );
+        carry = total / LIMB_BASE as i128;
+        carries.push(
+            (carry + carry_offset as i128)
+                .to_u64()
+                .ok_or_else(|| anyhow!("carry out of range in FrTarget reduction"))?,
+        );
+    }
+    ensure!(
+        columns[num_columns - 1] + carry == 0,
+        "non-zero final carry in FrTarget reduction"
+    );
+
+    Ok(ReduceWitness {
+        quotient,
+        remainder,
+        carries,
+    })
+}
+
+pub(crate) fn write_fr_target<F: RichField + Extendable<D>, const D: usize>(
+    dst: &mut Vec<u8>,
+    x: &FrTarget<F, D>,
+) -> IoResult<()> {
+    dst.write_target_vec(&x.limbs)
+}
+
+pub(crate) fn read_fr_target<F: RichField + Extendable<D>, const D: usize>(
+    src: &mut Buffer,
+) -> IoResult<FrTarget<F, D>> {
+    let limbs = src.read_target_vec()?;
+    Ok(FrTarget::from_limbs(limbs.try_into().map_err(|_| IoError)?))
+}
+
+pub(crate) fn write_fr_target_vec<F: RichField + Extendable<D>, const D: usize>(
+    dst: &mut Vec<u8>,
+    xs: &[FrTarget<F, D>],
+) -> IoResult<()> {
+    dst.write_usize(xs.len())?;
+    for x in xs {
+        write_fr_target(dst, x)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn read_fr_target_vec<F: RichField + Extendable<D>, const D: usize>(
+    src: &mut Buffer,
+) -> IoResult<Vec<FrTarget<F, D>>> {
+    let len = src.read_usize()?;
+    (0..len).map(|_| read_fr_target(src)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ark_std::UniformRand;
+    use plonky2::{
+        field::types::Field,
+        iop::{generator::generate_partial_witness, witness::PartialWitness},
+        plonk::{
+            circuit_data::{CircuitConfig, CircuitData},
+            config::{GenericConfig, PoseidonGoldilocksConfig},
+        },
+    };
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    /// Witness generation either rejects the witness or yields a proof that does not verify.
+    fn assert_unsatisfiable(data: &CircuitData<F, C, D>, pw: PartialWitness<F>) {
+        if let Ok(Ok(proof)) = catch_unwind(AssertUnwindSafe(|| data.prove(pw))) {
+            assert!(data.verify(proof).is_err());
+        }
+    }
+
+    #[test]
+    fn test_limbs_round_trip() {
+        let mut rng = ark_std::test_rng();
+        for value in [Fr::zero(), -Fr::one(), Fr::rand(&mut rng)] {
+            assert_eq!(limbs_to_fr(&fr_to_limbs(&value)), value);
+        }
+    }
+
+    #[test]
+    fn test_fr_arithmetic() {
+        let mut rng = ark_std::test_rng();
+        let cases = [
+            (Fr::rand(&mut rng), Fr::rand(&mut rng)),
+            (-Fr::one(), -Fr::one()),
+            (Fr::zero(), -Fr::from(2u64)),
+        ];
+
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let mut inputs = Vec::new();
+        for (x_value, y_value) in cases {
+            let x = FrTarget::new(&mut builder);
+            let y = FrTarget::new(&mut builder);
+            let outputs = [
+                (x.add(&y, &mut builder), x_value + y_value),
+                (x.sub(&y, &mut builder), x_value - y_value),
+                (x.mul(&y, &mut builder), x_value * y_value),
+                (x.exp_u64(5, &mut builder), x_value.pow([5u64])),
+            ];
+            for (output, expected) in outputs {
+                let expected = FrTarget::constant(&expected, &mut builder);
+                for (a, b) in output.limbs.into_iter().zip(expected.limbs) {
+                    builder.connect(a, b);
+                }
+            }
+            inputs.push((x, x_value, y, y_value));
+        }
+
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        for (x, x_value, y, y_value) in &inputs {
+            x.set_witness(&mut pw, x_value).unwrap();
+            y.set_witness(&mut pw, y_value).unwrap();
+        }
+        let proof = data.prove(pw).unwrap();
+        data.verify(proof).unwrap();
+    }
+
+    #[test]
+    fn test_witness_round_trip() {
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let x = FrTarget::new(&mut builder);
+        let y = x.mul(&x, &mut builder);
+        let data = builder.build::<C>();
+
+        // The same circuit is reused for several private inputs.
+        let mut rng = ark_std::test_rng();
+        for value in [Fr::rand(&mut rng), Fr::rand(&mut rng), -Fr::one()] {
+            let mut pw = PartialWitness::new();
+            x.set_witness(&mut pw, &value).unwrap();
+            let witness = generate_partial_witness(pw, &data.prover_only, &data.common).unwrap();
+            assert_eq!(x.get_witness(&witness), value);
+            assert_eq!(y.get_witness(&witness), value * value);
+        }
+    }
+
+    #[test]
+    fn test_public_fr_round_trip() {
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let x = FrTarget::new(&mut builder);
+        let y = x.mul(&x, &mut builder);
+        register_public_fr(&mut builder, &x);
+        register_public_fr(&mut builder, &y);
+        let data = builder.build::<C>();
+
+        let value = -Fr::from(3u64);
+        let mut pw = PartialWitness::new();
+        x.set_witness(&mut pw, &value).unwrap();
+        let proof = data.prove(pw).unwrap();
+        let public = public_inputs_to_fr(&proof.public_inputs).unwrap();
+        assert_eq!(public, [value, value * value]);
+        let tail = public_inputs_to_fr(&proof.public_inputs[NUM_LIMBS..]).unwrap();
+        assert_eq!(tail, [value * value]);
+        data.verify(proof).unwrap();
+
+        // Truncated, out-of-range and non-canonical inputs are rejected.
+        assert!(public_inputs_to_fr(&[F::ONE; NUM_LIMBS - 1]).is_err());
+        assert!(public_inputs_to_fr(&[F::from_canonical_u64(LIMB_BASE); NUM_LIMBS]).is_err());
+        let limbs = biguint_to_limbs(&modulus(), NUM_LIMBS)
+            .into_iter()
+            .map(F::from_canonical_u64)
+            .collect::<Vec<_>>();
+        assert!(public_inputs_to_fr(&limbs).is_err());
+    }
+
+    #[test]
+    fn test_comparison_selection_and_division() {
+        let mut rng = ark_std::test_rng();
+        let random = Fr::rand(&mut rng);
+        let cases = [
+            (Fr::rand(&mut rng), Fr::rand(&mut rng), true),
+            (random, random, false),
+            (Fr::zero(), random, true),
+            (-Fr::one(), Fr::one(), false),
+            (Fr::from(1u64 << 16), Fr::from(2u64), true),
+        ];
+
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let mut inputs = Vec::new();
+        for (x_value, y_value, condition_value) in cases {
+            let x = FrTarget::new(&mut builder);
+            let y = FrTarget::new(&mut builder);
+            let condition = builder.add_virtual_bool_target_safe();
+
+            let bools = [
+                (x.is_equal(&y, &mut builder), x_value == y_value),
+                (x.is_zero(&mut builder), x_value.is_zero()),
+                (y.is_zero(&mut builder), false),
+                (x.less_than(&y, &mut builder), x_value.into_bigint() < y_value.into_bigint()),
+                (y.less_than(&x, &mut builder), y_value.into_bigint() < x_value.into_bigint()),
+            ];
+            for (output, expected) in bools {
+                let expected = builder.constant_bool(expected);
+                builder.connect(output.target, expected.target);
+            }
+
+            let (first, second) = FrTarget::swap(condition, &x, &y, &mut builder);
+            let (first_value, second_value) = if condition_value {
+                (y_value, x_value)
+            } else {
+                (x_value, y_value)
+            };
+            let outputs = [
+                (FrTarget::select(condition, &x, &y, &mut builder), second_value),
+                (first, first_value),
+                (second, second_value),
+                (x.div(&y, &mut builder), x_value / y_value),
+                (y.inverse(&mut builder), y_value.inverse().unwrap()),
+            ];
+            for (output, expected) in outputs {
+                let expected = FrTarget::constant(&expected, &mut builder);
+                output.assert_equal(&expected, &mut builder);
+            }
+            inputs.push((x, x_value, y, y_value, condition, condition_value));
+        }
+
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        for (x, x_value, y, y_value, condition, condition_value) in &inputs {
+            x.set_witness(&mut pw, x_value).unwrap();
+            y.set_witness(&mut pw, y_value).unwrap();
+            pw.set_bool_target(*condition, *condition_value).unwrap();
+        }
+        let proof = data.prove(pw).unwrap();
+        data.verify(proof).unwrap();
+    }
+
+    #[test]
+    fn test_inverse_of_zero_rejected() {
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let x = FrTarget::new(&mut builder);
+        x.inverse(&mut builder);
+        let data = builder.build::<C>();
+
+        let mut pw = PartialWitness::new();
+        x.set_witness(&mut pw, &Fr::zero()).unwrap();
+        assert_unsatisfiable(&data, pw);
+    }
+
+    #[test]
+    fn test_non_canonical_input_rejected() {
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let x = FrTarget::new(&mut builder);
+        let data = builder.build::<C>();
+
+        // The modulus itself has valid 16-bit limbs but is not a canonical scalar.
+        let mut pw = PartialWitness::new();
+        for (&limb, value) in x.limbs.iter().zip(biguint_to_limbs(&modulus(), NUM_LIMBS)) {
+            pw.set_target(limb, F::from_canonical_u64(value)).unwrap();
+        }
+        assert_unsatisfiable(&data, pw);
+    }
+
+    #[test]
+    fn test_limb_lookups() {
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        assert!(!limb_lookups_enabled(&builder));
+        enable_limb_lookups(&mut builder);
+        assert!(limb_lookups_enabled(&builder));
+        let x = FrTarget::new(&mut builder);
+        let y = FrTarget::new(&mut builder);
+        let z = x.mul(&y, &mut builder);
+        let data = builder.build::<C>();
+
+        let mut rng = ark_std::test_rng();
+        let (x_value, y_value) = (Fr::rand(&mut rng), Fr::rand(&mut rng));
+        let mut pw = PartialWitness::new();
+        x.set_witness(&mut pw, &x_value).unwrap();
+        y.set_witness(&mut pw, &y_value).unwrap();
+        let witness =
+            generate_partial_witness(pw.clone(), &data.prover_only, &data.common).unwrap();
+        assert_eq!(z.get_witness(&witness), x_value * y_value);
+        let proof = data.prove(pw).unwrap();
+        data.verify(proof).unwrap();
+
+        // Limbs [2^16, 0, ..] encode the canonical value 2^16, but the first one is not in the
+        // table.
+        let mut limbs = [F::ZERO; NUM_LIMBS];
+        limbs[0] = F::from_canonical_u64(LIMB_BASE);
+        let mut pw = PartialWitness::new();
+        for (&limb, value) in x.limbs.iter().zip(limbs) {
+            pw.set_target(limb, value).unwrap();
+        }
+        y.set_witness(&mut pw, &y_value).unwrap();
+        assert_unsatisfiable(&data, pw);
+    }
+}
@@ -0,0 +1,118 @@
+use plonky2::plonk::{
+    circuit_builder::CircuitBuilder,
+    circuit_data::CircuitConfig,
+    config::{GenericConfig, PoseidonGoldilocksConfig},
+};
+use plonky2_bn254_poseidon::{
+    arithmetic::{enable_limb_lookups, num_rows, FrTarget},
+    gate::MAX_AFFINE_TERMS,
+    params::PoseidonParams,
+    poseidon::{PermutationCircuit, PoseidonCircuit},
+    poseidon2::Poseidon2Circuit,
+};
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+
+/// Modes compared for each hash: (`Bn254MulGate` and `Bn254AffineGate`, limb lookups). The
+/// custom gates always use limb lookups.
+const MODES: [(bool, bool); 3] = [(true, true), (false, false), (false, true)];
+
+/// Builds a single `hash_fr` over `rate()` private inputs and returns the number of rows added
+/// by the hash and the degree bits of the built circuit.
+///
+/// The row count includes `LookupGate` rows but not the `LookupTableGate` rows of the limb
+/// table, which `build` adds once per circuit; the degree bits include them.
+fn hash_rows(mut circuit: impl PermutationCircuit<F, D>, lookups: bool) -> (usize, usize) {
+    if lookups {
+        enable_limb_lookups(circuit.builder());
+    }
+    let input = (0..circuit.rate())
+        .map(|_| FrTarget::new(circuit.builder()))
+        .collect::<Vec<_>>();
+    circuit.hash_fr(&input);
+    let rows = num_rows(circuit.builder());
+    let empty = CircuitBuilder::new(CircuitConfig::standard_recursion_config());
+    let data = std::mem::replace(circuit.builder(), empty).build::<C>();
+    (rows, data.common.degree_bits())
+}
+
+/// Returns the number of rows added by round `round` of a Poseidon permutation over private
+/// inputs, including `LookupGate` rows.
+fn round_rows(width: usize, round: usize, gates: bool, lookups: bool) -> usize {
+    let mut circuit = PoseidonCircuit::<F, D>::with_width(width).with_gates(gates);
+    if lookups {
+        enable_limb_lookups(&mut circuit.builder);
+    }
+    let mut state = (0..width)
+        .map(|_| FrTarget::new(&mut circuit.builder))
+        .collect::<Vec<_>>();
+    let rows = num_rows(&circuit.builder);
+    circuit.round(&mut state, round);
+    num_rows(&circuit.builder) - rows
+}
+
+/// Prints the rows of a full and a partial Poseidon round with and without the custom gates.
+///
+/// `gate rows` is the number of `Bn254MulGate`, `Bn254AffineGate` and `Bn254CheckGate` rows
+/// alone; the rest of a round with the gates are the `LookupGate` rows of their limbs. The
+/// generic arithmetic is shown with bit decomposition (`generic`) and limb lookups
+/// (`generic+lookup`), and `saved` compares the gates against the former.
+fn compare_rounds() {
+    println!(
+        "{:>10} {:>5} {:>10} {:>10} {:>10} {:>15} {:>10}",
+        "round", "width", "gate rows", "gates", "generic", "generic+lookup", "saved"
+    );
+    for width in 2..=MAX_AFFINE_TERMS {
+        let params = PoseidonParams::new(width);
+        for round in [0, params.full_rounds / 2] {
+            let (kind, sboxes) = if params.is_full_round(round) {
+                ("full", width)
+            } else {
+                ("partial", 1)
+            };
+            let gate_rows = 2 * (3 * sboxes + width);
+            let gates = round_rows(width, round, true, true);
+            let generic = round_rows(width, round, false, false);
+            let lookup = round_rows(width, round, false, true);
+            let saved = 100 * (generic - gates) / generic;
+            println!(
+                "{kind:>10} {width:>5} {gate_rows:>10} {gates:>10} {generic:>10} {lookup:>15} {saved:>9}%"
+            );
+        }
+    }
+    println!();
+}
+
+fn print_row(hash: &str, width: usize, counts: [(usize, usize); 3]) {
+    let cells = counts.map(|(rows, degree_bits)| format!("{rows} (2^{degree_bits})"));
+    println!(
+        "{hash:>10} {width:>5} {:>16} {:>16} {:>16}",
+        cells[0], cells[1], cells[2]
+    );
+}
+
+/// Compares single Poseidon rounds with and without the custom gates, then the number of rows
+/// of a single BN254 hash built with `Bn254MulGate` and `Bn254AffineGate` against the generic
+/// `FrTarget` arithmetic, with limbs range-checked by bit decomposition or by lookups, for
+/// Poseidon at a few state widths and Poseidon2.
+fn main() {
+    compare_rounds();
+    println!(
+        "{:>10} {:>5} {:>16} {:>16} {:>16}",
+        "hash", "width", "gates", "generic", "generic+lookup"
+    );
+    for width in [2, 3, 5, 9, 17] {
+        let counts = MODES.map(|(gates, lookups)| {
+            hash_rows(PoseidonCircuit::with_width(width).with_gates(gates), lookups)
+        });
+        print_row("Poseidon", width, counts);
+    }
+    for width in [3, 4] {
+        let counts = MODES.map(|(gates, lookups)| {
+            hash_rows(Poseidon2Circuit::with_width(width).with_gates(gates), lookups)
+        });
+        print_row("Poseidon2", width, counts);
+    }
+}
@@ -1,27 +1,188 @@
+use anyhow::{anyhow, bail, ensure, Context};
 use ark_bn254::Fr;
-use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+use ark_ff::PrimeField;
+use num::BigUint;
+use plonky2::{
+    iop::witness::PartialWitness,
+    plonk::config::{GenericConfig, PoseidonGoldilocksConfig},
+};
 use plonky2_bn254_poseidon::{
-    arithmetic::FrTarget,
-    poseidon::PoseidonCircuit,
+    arithmetic::{enable_limb_lookups, public_inputs_to_fr, register_public_fr, FrTarget},
+    params::{MAX_WIDTH, MIN_WIDTH},
+    poseidon::{PermutationCircuit, PoseidonCircuit},
 };
+use serde_json::Value;
+use std::{fs, path::PathBuf, str::FromStr, time::Instant};
+
+const D: usize = 2;
+type C = PoseidonGoldilocksConfig;
+type F = <C as GenericConfig<D>>::F;
+
+const USAGE: &str = "\
+Proves knowledge of BN254 Poseidon preimages, circomlib style: poseidon(inputs) with a state
+of width inputs.len() + 1.
+
+Usage: permute [OPTIONS] [INPUT]...
+
+Inputs are decimal BN254 scalars. Without any inputs, [1, 2, 3] is hashed.
+
+Options:
+  --json <FILE>    Read inputs from a JSON array, or an object with an \"inputs\" array
+  --out <DIR>      Write the proof and verifier data to DIR as JSON
+  --no-gates       Use generic FrTarget arithmetic instead of Bn254MulGate and Bn254AffineGate
+  --lookups        Range-check limbs with a lookup table instead of bit decomposition
+  -h, --help       Print this message";
 
-fn main() {
-    const D: usize = 2;
-    type C = PoseidonGoldilocksConfig;
-    type F = <C as GenericConfig<D>>::F;
-
-    let x_value = Fr::from(1u64);
-    let y_value = Fr::from(2u64);
-    let z_value = Fr::from(3u64);
-
-    let mut circuit = PoseidonCircuit::<F, D>::new();
-    let input = vec![
-        FrTarget::<F, D>::constant(&x_value, &mut circuit.builder),
-        FrTarget::<F, D>::constant(&y_value, &mut circuit.builder),
-        FrTarget::<F, D>::constant(&z_value, &mut circuit.builder),
-    ];
+struct Args {
+    inputs: Vec<Fr>,
+    out: Option<PathBuf>,
+    gates: bool,
+    lookups: bool,
+}
+
+fn parse_fr(value: &str) -> anyhow::Result<Fr> {
+    Fr::from_str(value).map_err(|_| anyhow!("invalid BN254 scalar: {value:?}"))
+}
+
+fn decimal(value: &Fr) -> String {
+    BigUint::from(value.into_bigint()).to_string()
+}
+
+fn read_json_inputs(path: &str) -> anyhow::Result<Vec<Fr>> {
+    let contents = fs::read_to_string(path).with_context(|| format!("reading {path}"))?;
+    let json: Value = serde_json::from_str(&contents).with_context(|| format!("parsing {path}"))?;
+    let inputs = match &json {
+        Value::Array(inputs) => inputs,
+        Value::Object(object) => object
+            .get("inputs")
+            .and_then(Value::as_array)
+            .ok_or_else(|| anyhow!("{path}: expected an \"inputs\" array"))?,
+        _ => bail!("{path}: expected an array of inputs"),
+    };
+    inputs
+        .iter()
+        .map(|input| match input {
+            Value::String(s) => parse_fr(s),
+            Value::Number(n) => parse_fr(&n.to_string()),
+            _ => bail!("{path}: inputs must be decimal strings or numbers, got {input}"),
+        })
+        .collect()
+}
+
+fn parse_args() -> anyhow::Result<Option<Args>> {
+    let mut inputs = Vec::new();
+    let mut out = None;
+    let mut gates = true;
+    let mut lookups = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--json" => {
+                let path = args.next().ok_or_else(|| anyhow!("--json needs a file"))?;
+                inputs.extend(read_json_inputs(&path)?);
+            }
+            "--out" => {
+                let dir = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--out needs a directory"))?;
+                out = Some(PathBuf::from(dir));
+            }
+            "--no-gates" => gates = false,
+            "--lookups" => lookups = true,
+            _ if arg.starts_with('-') => bail!("unknown option {arg}\n\n{USAGE}"),
+            _ => inputs.push(parse_fr(&arg)?),
+        }
+    }
+    if inputs.is_empty() {
+        inputs = (1..=3u64).map(Fr::from).collect();
+    }
+    Ok(Some(Args {
+        inputs,
+        out,
+        gates,
+        lookups,
+    }))
+}
+
+fn main() -> anyhow::Result<()> {
+    let Some(args) = parse_args()? else {
+        println!("{USAGE}");
+        return Ok(());
+    };
+    let width = args.inputs.len() + 1;
+    ensure!(
+        (MIN_WIDTH..=MAX_WIDTH).contains(&width),
+        "expected between {} and {} inputs, got {}",
+        MIN_WIDTH - 1,
+        MAX_WIDTH - 1,
+        args.inputs.len()
+    );
+
+    // Inputs are private witness values; only the hash is public.
+    let mut circuit = PoseidonCircuit::<F, D>::with_width(width).with_gates(args.gates);
+    if args.lookups {
+        enable_limb_lookups(&mut circuit.builder);
+    }
+    let input = args
+        .inputs
+        .iter()
+        .map(|_| FrTarget::new(&mut circuit.builder))
+        .collect::<Vec<_>>();
     let output = circuit.hash_fr(&input);
+    register_public_fr(&mut circuit.builder, &output);
+    let num_gates = circuit.builder.num_gates();
+
+    let start = Instant::now();
+    let data = circuit.builder.build::<C>();
+    let build_time = start.elapsed();
+
+    let mut pw = PartialWitness::new();
+    for (x, value) in input.iter().zip(&args.inputs) {
+        x.set_witness(&mut pw, value)?;
+    }
+    let start = Instant::now();
+    let proof = data.prove(pw)?;
+    let proving_time = start.elapsed();
+
+    let [hash] = public_inputs_to_fr(&proof.public_inputs)?[..] else {
+        bail!("expected a single public hash");
+    };
+    let proof_size = proof.to_bytes().len();
+    data.verify(proof.clone())?;
+
+    let inputs = args.inputs.iter().map(decimal).collect::<Vec<_>>();
+    println!("Inputs:         {}", inputs.join(", "));
+    println!("Hash:           {}", decimal(&hash));
+    println!("Width:          {width}");
+    println!("Gates:          {num_gates}");
+    println!("Degree bits:    {}", data.common.degree_bits());
+    println!("Build time:     {build_time:?}");
+    println!("Proving time:   {proving_time:?}");
+    println!("Proof size:     {proof_size} bytes");
+    println!("Proof verified");
 
-    println!("Input values: {:?}, {:?}, {:?}", x_value, y_value, z_value);
-    println!("Output value: {:?}", output.to_native(&circuit.builder));
+    if let Some(dir) = args.out {
+        fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+        let files = [
+            (
+                "common_circuit_data.json",
+                serde_json::to_string_pretty(&data.common)?,
+            ),
+            (
+                "verifier_only_circuit_data.json",
+                serde_json::to_string_pretty(&data.verifier_only)?,
+            ),
+            (
+                "proof_with_public_inputs.json",
+                serde_json::to_string_pretty(&proof)?,
+            ),
+        ];
+        for (name, contents) in files {
+            let path = dir.join(name);
+            fs::write(&path, contents).with_context(|| format!("writing {}", path.display()))?;
+        }
+        println!("Wrote proof and verifier data to {}", dir.display());
+    }
+    Ok(())
 }
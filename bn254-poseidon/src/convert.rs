@@ -0,0 +1,281 @@
+//! Conversions between BN254 scalars and Goldilocks elements, bits and bytes, both in-circuit
+//! on `FrTarget` and natively on `Fr`.
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, One, PrimeField, Zero};
+use num::BigUint;
+use plonky2::{
+    field::{
+        extension::Extendable,
+        types::{Field, PrimeField64},
+    },
+    hash::hash_types::{HashOut, HashOutTarget, RichField},
+    iop::target::{BoolTarget, Target},
+    plonk::circuit_builder::CircuitBuilder,
+};
+
+use super::arithmetic::{assert_canonical, fr_to_limbs, FrTarget, LIMB_BASE, LIMB_BITS, NUM_LIMBS};
+
+/// Number of bits of a Goldilocks element packed into an `Fr` by `FrTarget::from_goldilocks`.
+pub const GOLDILOCKS_BITS: usize = 64;
+/// At most this many Goldilocks elements fit in an `Fr` without reduction modulo `p`.
+pub const MAX_INJECTIVE_GOLDILOCKS: usize = 3;
+/// Number of 32-bit Goldilocks limbs produced by `FrTarget::to_goldilocks_limbs`.
+pub const NUM_GOLDILOCKS_LIMBS: usize = 8;
+/// Number of bytes produced by `FrTarget::to_bytes_le`.
+pub const NUM_BYTES: usize = 32;
+
+const LIMBS_PER_GOLDILOCKS: usize = GOLDILOCKS_BITS / LIMB_BITS;
+
+impl<F: RichField + Extendable<D>, const D: usize> FrTarget<F, D> {
+    /// Packs up to four Goldilocks elements as `Σ eᵢ·2^(64i) mod p`.
+    ///
+    /// Each element is decomposed into its canonical 64-bit value, so the packing is injective
+    /// for up to `MAX_INJECTIVE_GOLDILOCKS` elements. A fourth element overflows 254 bits and
+    /// the result is reduced modulo `p`, which is how a `HashOut` is usually committed to on BN254.
+    pub fn from_goldilocks(elements: &[Target], builder: &mut CircuitBuilder<F, D>) -> Self {
+        assert!(
+            elements.len() <= MAX_INJECTIVE_GOLDILOCKS + 1,
+            "at most {} Goldilocks elements fit in an Fr, got {}",
+            MAX_INJECTIVE_GOLDILOCKS + 1,
+            elements.len()
+        );
+        let limbs = elements
+            .iter()
+            .flat_map(|&element| split_goldilocks(builder, element))
+            .collect::<Vec<_>>();
+        let (low, high) = limbs.split_at(
+            limbs
+                .len()
+                .min(MAX_INJECTIVE_GOLDILOCKS * LIMBS_PER_GOLDILOCKS),
+        );
+        // Both halves are below 2^192, so they are canonical as they are.
+        let low = limbs_to_fr_target(builder, low);
+        if high.is_empty() {
+            return low;
+        }
+        let high = limbs_to_fr_target(builder, high);
+        let shift = Fr::from(BigUint::one() << (MAX_INJECTIVE_GOLDILOCKS * GOLDILOCKS_BITS));
+        Self::linear_combination(&[(Fr::one(), low), (shift, high)], &Fr::zero(), builder)
+    }
+
+    /// Packs the four elements of a Goldilocks Poseidon digest, see `from_goldilocks`.
+    pub fn from_hash_out(hash: HashOutTarget, builder: &mut CircuitBuilder<F, D>) -> Self {
+        Self::from_goldilocks(&hash.elements, builder)
+    }
+
+    /// Splits the value into `Fr::MODULUS_BIT_SIZE` little-endian bits.
+    pub fn to_bits_le(&self, builder: &mut CircuitBuilder<F, D>) -> Vec<BoolTarget> {
+        let mut bits = self
+            .limbs
+            .iter()
+            .flat_map(|&limb| builder.split_le(limb, LIMB_BITS))
+            .collect::<Vec<_>>();
+        // The value is canonical, so the top bits of the last limb are zero.
+        bits.truncate(Fr::MODULUS_BIT_SIZE as usize);
+        bits
+    }
+
+    /// Recombines at most 256 little-endian bits, constraining them to encode a value below `p`.
+    pub fn from_bits_le(bits: &[BoolTarget], builder: &mut CircuitBuilder<F, D>) -> Self {
+        assert!(
+            bits.len() <= NUM_LIMBS * LIMB_BITS,
+            "too many bits for an Fr: {}",
+            bits.len()
+        );
+        let limbs = bits
+            .chunks(LIMB_BITS)
+            .map(|chunk| builder.le_sum(chunk.iter()))
+            .collect::<Vec<_>>();
+        let target = limbs_to_fr_target(builder, &limbs);
+        if bits.len() >= Fr::MODULUS_BIT_SIZE as usize {
+            assert_canonical(builder, &target.limbs);
+        }
+        target
+    }
+
+    /// Splits the value into 32 little-endian bytes, each range-checked to 8 bits.
+    pub fn to_bytes_le(&self, builder: &mut CircuitBuilder<F, D>) -> Vec<Target> {
+        self.limbs
+            .iter()
+            .flat_map(|&limb| {
+                let (low, high) = builder.split_low_high(limb, 8, LIMB_BITS);
+                [low, high]
+            })
+            .collect()
+    }
+
+    /// Splits the value into eight little-endian 32-bit limbs, each a canonical Goldilocks
+    /// element.
+    pub fn to_goldilocks_limbs(&self, builder: &mut CircuitBuilder<F, D>) -> Vec<Target> {
+        self.limbs
+            .chunks(2)
+            .map(|pair| builder.mul_const_add(F::from_canonical_u64(LIMB_BASE), pair[1], pair[0]))
+            .collect()
+    }
+}
+
+/// Splits a Goldilocks element into the four 16-bit limbs of its canonical 64-bit value.
+fn split_goldilocks<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    element: Target,
+) -> Vec<Target> {
+    let bits = builder.split_le(element, GOLDILOCKS_BITS);
+    let limbs = bits
+        .chunks(LIMB_BITS)
+        .map(|chunk| builder.le_sum(chunk.iter()))
+        .collect::<Vec<_>>();
+
+    // A 64-bit decomposition could also encode `element + p_goldilocks`. Values are below
+    // `2^64 - 2^32 + 1` iff the low half is zero whenever the high half is all ones.
+    let base = F::from_canonical_u64(LIMB_BASE);
+    let low = builder.mul_const_add(base, limbs[1], limbs[0]);
+    let high = builder.mul_const_add(base, limbs[3], limbs[2]);
+    let max_high = builder.constant(F::from_canonical_u32(u32::MAX));
+    let high_is_max = builder.is_equal(high, max_high);
+    let overflow = builder.mul(high_is_max.target, low);
+    builder.assert_zero(overflow);
+    limbs
+}
+
+/// Wraps at most `NUM_LIMBS` range-checked limbs, padding the top with zeros.
+fn limbs_to_fr_target<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    limbs: &[Target],
+) -> FrTarget<F, D> {
+    let zero = builder.zero();
+    let mut padded = [zero; NUM_LIMBS];
+    padded[..limbs.len()].copy_from_slice(limbs);
+    FrTarget::from_limbs(padded)
+}
+
+/// Native counterpart of `FrTarget::from_goldilocks`.
+pub fn goldilocks_to_fr<F: PrimeField64>(elements: &[F]) -> Fr {
+    let value = elements.iter().rev().fold(BigUint::zero(), |acc, element| {
+        (acc << GOLDILOCKS_BITS) + element.to_canonical_u64()
+    });
+    Fr::from(value)
+}
+
+/// Native counterpart of `FrTarget::from_hash_out`.
+pub fn hash_out_to_fr<F: RichField>(hash: &HashOut<F>) -> Fr {
+    goldilocks_to_fr(&hash.elements)
+}
+
+/// Native counterpart of `FrTarget::to_bits_le`.
+pub fn fr_to_bits_le(value: &Fr) -> Vec<bool> {
+    let mut bits = value.into_bigint().to_bits_le();
+    bits.truncate(Fr::MODULUS_BIT_SIZE as usize);
+    bits
+}
+
+/// Native counterpart of `FrTarget::to_bytes_le`.
+pub fn fr_to_bytes_le(value: &Fr) -> [u8; NUM_BYTES] {
+    value.into_bigint().to_bytes_le().try_into().unwrap()
+}
+
+/// Native counterpart of `FrTarget::to_goldilocks_limbs`.
+pub fn fr_to_goldilocks_limbs<F: Field>(value: &Fr) -> [F; NUM_GOLDILOCKS_LIMBS] {
+    let limbs = fr_to_limbs(value);
+    std::array::from_fn(|i| F::from_canonical_u64(limbs[2 * i] + (limbs[2 * i + 1] << LIMB_BITS)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ark_std::UniformRand;
+    use plonky2::{
+        field::types::Sample,
+        iop::witness::{PartialWitness, WitnessWrite},
+        plonk::{
+            circuit_data::CircuitConfig,
+            config::{GenericConfig, PoseidonGoldilocksConfig},
+        },
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn test_native_conversions() {
+        let mut rng = ark_std::test_rng();
+        let value = Fr::rand(&mut rng);
+        let bits = fr_to_bits_le(&value);
+        let from_bits = bits
+            .iter()
+            .rev()
+            .fold(BigUint::zero(), |acc, &bit| (acc << 1) + u32::from(bit));
+        assert_eq!(Fr::from(from_bits), value);
+
+        let bytes = fr_to_bytes_le(&value);
+        assert_eq!(Fr::from_le_bytes_mod_order(&bytes), value);
+
+        let limbs = fr_to_goldilocks_limbs::<F>(&value);
+        let from_limbs = limbs.iter().rev().fold(BigUint::zero(), |acc, limb| {
+            (acc << 32) + limb.to_canonical_u64()
+        });
+        assert_eq!(Fr::from(from_limbs), value);
+
+        assert_eq!(
+            goldilocks_to_fr(&[F::ONE, F::TWO]),
+            Fr::from((2u128 << 64) + 1)
+        );
+    }
+
+    #[test]
+    fn test_conversion_gadgets() {
+        let mut rng = ark_std::test_rng();
+        let hashes = [HashOut::<F>::rand(), HashOut::from_vec(vec![F::NEG_ONE; 4])];
+        let values = [Fr::rand(&mut rng), -Fr::one()];
+
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let mut hash_inputs = Vec::new();
+        for hash in &hashes {
+            let target = builder.add_virtual_hash();
+            let packed = FrTarget::from_hash_out(target, &mut builder);
+            let expected = FrTarget::constant(&hash_out_to_fr(hash), &mut builder);
+            packed.assert_equal(&expected, &mut builder);
+
+            let packed = FrTarget::from_goldilocks(&target.elements[..3], &mut builder);
+            let expected = FrTarget::constant(&goldilocks_to_fr(&hash.elements[..3]), &mut builder);
+            packed.assert_equal(&expected, &mut builder);
+            hash_inputs.push(target);
+        }
+
+        let mut fr_inputs = Vec::new();
+        for value in &values {
+            let x = FrTarget::new(&mut builder);
+            let bits = x.to_bits_le(&mut builder);
+            for (bit, expected) in bits.iter().zip(fr_to_bits_le(value)) {
+                let expected = builder.constant_bool(expected);
+                builder.connect(bit.target, expected.target);
+            }
+            FrTarget::from_bits_le(&bits, &mut builder).assert_equal(&x, &mut builder);
+
+            let bytes = x.to_bytes_le(&mut builder);
+            for (&byte, expected) in bytes.iter().zip(fr_to_bytes_le(value)) {
+                let expected = builder.constant(F::from_canonical_u8(expected));
+                builder.connect(byte, expected);
+            }
+
+            let limbs = x.to_goldilocks_limbs(&mut builder);
+            for (&limb, expected) in limbs.iter().zip(fr_to_goldilocks_limbs(value)) {
+                let expected = builder.constant(expected);
+                builder.connect(limb, expected);
+            }
+            fr_inputs.push(x);
+        }
+
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        for (target, hash) in hash_inputs.iter().zip(&hashes) {
+            pw.set_hash_target(*target, *hash).unwrap();
+        }
+        for (x, value) in fr_inputs.iter().zip(&values) {
+            x.set_witness(&mut pw, value).unwrap();
+        }
+        let proof = data.prove(pw).unwrap();
+        data.verify(proof).unwrap();
+    }
+}
@@ -0,0 +1,1326 @@
+use anyhow::{anyhow, ensure};
+use ark_bn254::Fr;
+use ark_ff::{PrimeField, Zero};
+use num::{BigUint, Integer, ToPrimitive};
+use plonky2::{
+    field::{extension::Extendable, types::Field},
+    gates::{gate::Gate, util::StridedConstraintConsumer},
+    hash::hash_types::RichField,
+    iop::{
+        ext_target::ExtensionTarget,
+        generator::{GeneratedValues, SimpleGenerator, WitnessGeneratorRef},
+        target::Target,
+        witness::{PartitionWitness, Witness, WitnessWrite},
+    },
+    plonk::{
+        circuit_builder::CircuitBuilder,
+        circuit_data::{CircuitConfig, CommonCircuitData},
+        vars::{EvaluationTargets, EvaluationVars, EvaluationVarsBase},
+    },
+    util::serialization::{Buffer, IoError, IoResult, Read, Write},
+};
+use std::marker::PhantomData;
+
+use super::arithmetic::{
+    biguint_to_limbs, enable_limb_lookups, fr_to_limbs, limbs_to_biguint, modulus,
+    range_check_limbs, FrTarget, LIMB_BASE, LIMB_BITS, NUM_LIMBS,
+};
+
+/// Number of schoolbook columns of a 16x16 limb product (the last one is always empty).
+const NUM_COLUMNS: usize = 2 * NUM_LIMBS;
+/// Columns are checked two at a time, so carries are taken modulo `2^32`.
+const NUM_PAIRS: usize = NUM_COLUMNS / 2;
+const NUM_CARRIES: usize = NUM_PAIRS - 1;
+const PAIR_BASE: u64 = LIMB_BASE * LIMB_BASE;
+
+/// Each column is below `2^36` in magnitude, so a pair is below `2^53` and carries below `2^21`.
+///
+/// A dishonest prover may pick any carry the `Bn254CheckGate` row lets through, i.e. below
+/// `2^24`. Every constraint is then still below `2^57` in magnitude, far below the Goldilocks
+/// modulus, so the constraints hold over the integers.
+const CARRY_BITS: usize = 21;
+const CARRY_OFFSET: u64 = 1 << CARRY_BITS;
+/// Operands, quotient, remainder and carries, all routed.
+const MUL_WIRES: usize = 4 * NUM_LIMBS + NUM_CARRIES;
+
+/// A gate constraining one BN254 scalar multiplication `a * b = q * p + r` over 16-bit limbs.
+///
+/// This is the S-box step of a BN254 Poseidon round: a whole multiplication fits in a single
+/// row of degree 2, instead of the dozens of arithmetic gates the generic `FrTarget::mul` needs
+/// for the same column sums. Adjacent columns are checked together, which halves the number
+/// of carries and lets all operands, the quotient, the remainder and the carries be routed
+/// (79 wires), so they can be range-checked and wired to the rest of the circuit.
+///
+/// The gate only checks the integer identity given its wires. `mul_with_gate` adds a
+/// `Bn254CheckGate` row for the canonicity of `r` and the size of the carries, and looks up the
+/// limbs of `q`, `r` and that row in the limb table: 78 lookups, which fill about two
+/// `LookupGate` rows of the standard recursion config. A multiplication then takes four rows
+/// instead of the dozens of bit decompositions its range checks would need.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Bn254MulGate;
+
+impl Bn254MulGate {
+    pub fn wire_a(i: usize) -> usize {
+        debug_assert!(i < NUM_LIMBS);
+        i
+    }
+
+    pub fn wire_b(i: usize) -> usize {
+        debug_assert!(i < NUM_LIMBS);
+        NUM_LIMBS + i
+    }
+
+    pub fn wire_quotient(i: usize) -> usize {
+        debug_assert!(i < NUM_LIMBS);
+        2 * NUM_LIMBS + i
+    }
+
+    pub fn wire_remainder(i: usize) -> usize {
+        debug_assert!(i < NUM_LIMBS);
+        3 * NUM_LIMBS + i
+    }
+
+    /// Offset carry out of the column pair `(2i, 2i + 1)`.
+    pub fn wire_carry(i: usize) -> usize {
+        debug_assert!(i < NUM_CARRIES);
+        4 * NUM_LIMBS + i
+    }
+
+    /// Constraints over any field, given a way to read the local wires.
+    fn eval<K: Field>(wire: impl Fn(usize) -> K) -> Vec<K> {
+        let p = modulus_limbs();
+        let columns = (0..NUM_COLUMNS)
+            .map(|k| {
+                let mut sum = K::ZERO;
+                for i in k.saturating_sub(NUM_LIMBS - 1)..NUM_LIMBS.min(k + 1) {
+                    sum += wire(Self::wire_a(i)) * wire(Self::wire_b(k - i));
+                    sum -= wire(Self::wire_quotient(i)) * K::from_canonical_u64(p[k - i]);
+                }
+                if k < NUM_LIMBS {
+                    sum -= wire(Self::wire_remainder(k));
+                }
+                sum
+            })
+            .collect::<Vec<_>>();
+
+        let mut carry_in = K::ZERO;
+        (0..NUM_PAIRS)
+            .map(|m| {
+                let pair = columns[2 * m] + columns[2 * m + 1] * K::from_canonical_u64(LIMB_BASE);
+                let carry_out = if m < NUM_CARRIES {
+                    wire(Self::wire_carry(m)) - K::from_canonical_u64(CARRY_OFFSET)
+                } else {
+                    K::ZERO
+                };
+                let constraint = pair + carry_in - carry_out * K::from_canonical_u64(PAIR_BASE);
+                carry_in = carry_out;
+                constraint
+            })
+            .collect()
+    }
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> Gate<F, D> for Bn254MulGate {
+    fn id(&self) -> String {
+        format!("{self:?}")
+    }
+
+    fn serialize(
+        &self,
+        _dst: &mut Vec<u8>,
+        _common_data: &CommonCircuitData<F, D>,
+    ) -> IoResult<()> {
+        Ok(())
+    }
+
+    fn deserialize(_src: &mut Buffer, _common_data: &CommonCircuitData<F, D>) -> IoResult<Self> {
+        Ok(Self)
+    }
+
+    fn eval_unfiltered(&self, vars: EvaluationVars<F, D>) -> Vec<F::Extension> {
+        Self::eval(|i| vars.local_wires[i])
+    }
+
+    fn eval_unfiltered_base_one(
+        &self,
+        vars: EvaluationVarsBase<F>,
+        mut yield_constr: StridedConstraintConsumer<F>,
+    ) {
+        yield_constr.many(Self::eval(|i| vars.local_wires[i]));
+    }
+
+    fn eval_unfiltered_circuit(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        vars: EvaluationTargets<D>,
+    ) -> Vec<ExtensionTarget<D>> {
+        let p = modulus_limbs();
+        let wires = vars.local_wires;
+        let columns = (0..NUM_COLUMNS)
+            .map(|k| {
+                let mut sum = builder.zero_extension();
+                for i in k.saturating_sub(NUM_LIMBS - 1)..NUM_LIMBS.min(k + 1) {
+                    let a = wires[Self::wire_a(i)];
+                    let b = wires[Self::wire_b(k - i)];
+                    sum = builder.mul_add_extension(a, b, sum);
+                    let p_limb = -F::from_canonical_u64(p[k - i]);
+                    sum =
+                        builder.mul_const_add_extension(p_limb, wires[Self::wire_quotient(i)], sum);
+                }
+                if k < NUM_LIMBS {
+                    sum = builder.sub_extension(sum, wires[Self::wire_remainder(k)]);
+                }
+                sum
+            })
+            .collect::<Vec<_>>();
+
+        let offset = builder.constant_extension(F::Extension::from_canonical_u64(CARRY_OFFSET));
+        let mut carry_in = None;
+        (0..NUM_PAIRS)
+            .map(|m| {
+                let base = F::from_canonical_u64(LIMB_BASE);
+                let mut constraint =
+                    builder.mul_const_add_extension(base, columns[2 * m + 1], columns[2 * m]);
+                if let Some(carry_in) = carry_in {
+                    constraint = builder.add_extension(constraint, carry_in);
+                }
+                if m < NUM_CARRIES {
+                    let carry_out = builder.sub_extension(wires[Self::wire_carry(m)], offset);
+                    let pair_base = -F::from_canonical_u64(PAIR_BASE);
+                    constraint = builder.mul_const_add_extension(pair_base, carry_out, constraint);
+                    carry_in = Some(carry_out);
+                }
+                constraint
+            })
+            .collect()
+    }
+
+    fn generators(&self, row: usize, _local_constants: &[F]) -> Vec<WitnessGeneratorRef<F, D>> {
+        vec![WitnessGeneratorRef::new(
+            Bn254MulGenerator::<F, D> {
+                row,
+                _phantom: PhantomData,
+            }
+            .adapter(),
+        )]
+    }
+
+    fn num_wires(&self) -> usize {
+        MUL_WIRES
+    }
+
+    fn num_constants(&self) -> usize {
+        0
+    }
+
+    fn degree(&self) -> usize {
+        2
+    }
+
+    fn num_constraints(&self) -> usize {
+        NUM_PAIRS
+    }
+}
+
+/// Witness generator filling the quotient, remainder and carries of a `Bn254MulGate` row.
+#[derive(Debug)]
+pub struct Bn254MulGenerator<F: RichField + Extendable<D>, const D: usize> {
+    row: usize,
+    _phantom: PhantomData<F>,
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> SimpleGenerator<F, D>
+    for Bn254MulGenerator<F, D>
+{
+    fn id(&self) -> String {
+        "Bn254MulGenerator".to_string()
+    }
+
+    fn dependencies(&self) -> Vec<Target> {
+        (0..NUM_LIMBS)
+            .flat_map(|i| [Bn254MulGate::wire_a(i), Bn254MulGate::wire_b(i)])
+            .map(|column| Target::wire(self.row, column))
+            .collect()
+    }
+
+    fn run_once(
+        &self,
+        witness: &PartitionWitness<F>,
+        out_buffer: &mut GeneratedValues<F>,
+    ) -> anyhow::Result<()> {
+        let read = |wire: fn(usize) -> usize| {
+            (0..NUM_LIMBS)
+                .map(|i| {
+                    witness
+                        .get_target(Target::wire(self.row, wire(i)))
+                        .to_canonical_u64()
+                })
+                .collect::<Vec<_>>()
+        };
+        let a = read(Bn254MulGate::wire_a);
+        let b = read(Bn254MulGate::wire_b);
+        let (quotient, remainder) =
+            (limbs_to_biguint(&a) * limbs_to_biguint(&b)).div_rem(&modulus());
+        ensure!(
+            quotient.bits() as usize <= NUM_LIMBS * LIMB_BITS,
+            "non-canonical operand in Bn254MulGate"
+        );
+        let quotient = biguint_to_limbs(&quotient, NUM_LIMBS);
+        let remainder = biguint_to_limbs(&remainder, NUM_LIMBS);
+
+        let p = modulus_limbs();
+        let mut columns = vec![0i128; NUM_COLUMNS];
+        for i in 0..NUM_LIMBS {
+            for j in 0..NUM_LIMBS {
+                columns[i + j] += (a[i] * b[j]) as i128 - (quotient[i] * p[j]) as i128;
+            }
+            columns[i] -= remainder[i] as i128;
+        }
+        let mut carry = 0i128;
+        let mut carries = Vec::with_capacity(NUM_CARRIES);
+        for pair in columns.chunks(2).take(NUM_CARRIES) {
+            let total = pair[0] + (pair[1] << LIMB_BITS) + carry;
+            ensure!(
+                total % PAIR_BASE as i128 == 0,
+                "inexact carry in Bn254MulGate"
+            );
+            carry = total / PAIR_BASE as i128;
+            carries.push(
+                (carry + CARRY_OFFSET as i128)
+                    .to_u64()
+                    .ok_or_else(|| anyhow!("carry out of range in Bn254MulGate"))?,
+            );
+        }
+
+        let mut set = |column: usize, value: u64| {
+            out_buffer.set_target(Target::wire(self.row, column), F::from_canonical_u64(value))
+        };
+        for i in 0..NUM_LIMBS {
+            set(Bn254MulGate::wire_quotient(i), quotient[i])?;
+            set(Bn254MulGate::wire_remainder(i), remainder[i])?;
+        }
+        for (i, &carry) in carries.iter().enumerate() {
+            set(Bn254MulGate::wire_carry(i), carry)?;
+        }
+        Ok(())
+    }
+
+    fn serialize(&self, dst: &mut Vec<u8>, _common_data: &CommonCircuitData<F, D>) -> IoResult<()> {
+        dst.write_usize(self.row)
+    }
+
+    fn deserialize(src: &mut Buffer, _common_data: &CommonCircuitData<F, D>) -> IoResult<Self> {
+        let row = src.read_usize()?;
+        Ok(Self {
+            row,
+            _phantom: PhantomData,
+        })
+    }
+}
+
+fn modulus_limbs() -> Vec<u64> {
+    biguint_to_limbs(&modulus(), NUM_LIMBS)
+}
+
+/// Limbs of `p - 1`, the largest canonical scalar.
+fn max_limbs() -> Vec<u64> {
+    biguint_to_limbs(&(modulus() - 1u32), NUM_LIMBS)
+}
+
+/// Carries and quotients are split as `w = lo + 2^7 * hi` with 16-bit `lo` and `hi`: every `w`
+/// below `2^23` has such a split, and any `w` that has one is below `2^24`.
+const WIDE_SHIFT: usize = 7;
+
+/// A gate checking the outputs of a `Bn254MulGate` or `Bn254AffineGate` row, so that all of
+/// their range checks become lookups into the limb table.
+///
+/// The remainder `r` is canonical iff `p - 1 - r` has 16-bit limbs `d`: the gate constrains
+/// `r + d = p - 1` limb by limb, with a boolean borrow between limbs and none out of the top
+/// one. Each of the `num_wide` carries and quotients `w` is split as `lo + 2^7 * hi`. The caller
+/// looks up `r`, `d`, `lo` and `hi` in the limb table, which also bounds every `w` below `2^24`.
+///
+/// Everything that is looked up is routed; the borrows are advice wires after them.
+#[derive(Copy, Clone, Debug)]
+pub struct Bn254CheckGate {
+    num_wide: usize,
+}
+
+impl Bn254CheckGate {
+    pub fn new(num_wide: usize) -> Self {
+        Self { num_wide }
+    }
+
+    pub fn wire_remainder(&self, i: usize) -> usize {
+        debug_assert!(i < NUM_LIMBS);
+        i
+    }
+
+    /// Limb `i` of `p - 1 - r`.
+    pub fn wire_difference(&self, i: usize) -> usize {
+        debug_assert!(i < NUM_LIMBS);
+        NUM_LIMBS + i
+    }
+
+    pub fn wire_wide(&self, j: usize) -> usize {
+        debug_assert!(j < self.num_wide);
+        2 * NUM_LIMBS + j
+    }
+
+    pub fn wire_low(&self, j: usize) -> usize {
+        debug_assert!(j < self.num_wide);
+        2 * NUM_LIMBS + self.num_wide + j
+    }
+
+    pub fn wire_high(&self, j: usize) -> usize {
+        debug_assert!(j < self.num_wide);
+        2 * NUM_LIMBS + 2 * self.num_wide + j
+    }
+
+    /// Borrow out of limb `i` of `p - 1 - r`.
+    pub fn wire_borrow(&self, i: usize) -> usize {
+        debug_assert!(i < NUM_LIMBS - 1);
+        self.num_routed_wires() + i
+    }
+
+    fn num_routed_wires(&self) -> usize {
+        2 * NUM_LIMBS + 3 * self.num_wide
+    }
+
+    /// Whether a row of `config` has room for the gate.
+    fn fits(&self, config: &CircuitConfig) -> bool {
+        self.num_routed_wires() <= config.num_routed_wires
+            && self.num_routed_wires() + NUM_LIMBS - 1 <= config.num_wires
+    }
+
+    /// Constraints over any field, given a way to read the local wires.
+    fn eval<K: Field>(&self, wire: impl Fn(usize) -> K) -> Vec<K> {
+        let max = max_limbs();
+        let base = K::from_canonical_u64(LIMB_BASE);
+        let borrow = |i: usize| {
+            if i < NUM_LIMBS - 1 {
+                wire(self.wire_borrow(i))
+            } else {
+                K::ZERO
+            }
+        };
+        let mut constraints = (0..NUM_LIMBS)
+            .map(|i| {
+                let borrow_in = if i > 0 { borrow(i - 1) } else { K::ZERO };
+                K::from_canonical_u64(max[i]) - wire(self.wire_remainder(i)) - borrow_in
+                    + borrow(i) * base
+                    - wire(self.wire_difference(i))
+            })
+            .collect::<Vec<_>>();
+        constraints.extend((0..NUM_LIMBS - 1).map(|i| {
+            let borrow = wire(self.wire_borrow(i));
+            borrow * (borrow - K::ONE)
+        }));
+        let shift = K::from_canonical_u64(1 << WIDE_SHIFT);
+        constraints.extend((0..self.num_wide).map(|j| {
+            wire(self.wire_wide(j)) - wire(self.wire_low(j)) - wire(self.wire_high(j)) * shift
+        }));
+        constraints
+    }
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> Gate<F, D> for Bn254CheckGate {
+    fn id(&self) -> String {
+        format!("{self:?}")
+    }
+
+    fn serialize(
+        &self,
+        dst: &mut Vec<u8>,
+        _common_data: &CommonCircuitData<F, D>,
+    ) -> IoResult<()> {
+        dst.write_usize(self.num_wide)
+    }
+
+    fn deserialize(src: &mut Buffer, _common_data: &CommonCircuitData<F, D>) -> IoResult<Self> {
+        Ok(Self::new(src.read_usize()?))
+    }
+
+    fn eval_unfiltered(&self, vars: EvaluationVars<F, D>) -> Vec<F::Extension> {
+        self.eval(|i| vars.local_wires[i])
+    }
+
+    fn eval_unfiltered_base_one(
+        &self,
+        vars: EvaluationVarsBase<F>,
+        mut yield_constr: StridedConstraintConsumer<F>,
+    ) {
+        yield_constr.many(self.eval(|i| vars.local_wires[i]));
+    }
+
+    fn eval_unfiltered_circuit(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        vars: EvaluationTargets<D>,
+    ) -> Vec<ExtensionTarget<D>> {
+        let max = max_limbs();
+        let wires = vars.local_wires;
+        let base = F::from_canonical_u64(LIMB_BASE);
+        let mut constraints = Vec::with_capacity(Gate::<F, D>::num_constraints(self));
+        for (i, &max) in max.iter().enumerate() {
+            let max = builder.constant_extension(F::Extension::from_canonical_u64(max));
+            let mut constraint = builder.sub_extension(max, wires[self.wire_remainder(i)]);
+            constraint = builder.sub_extension(constraint, wires[self.wire_difference(i)]);
+            if i > 0 {
+                constraint = builder.sub_extension(constraint, wires[self.wire_borrow(i - 1)]);
+            }
+            if i < NUM_LIMBS - 1 {
+                let borrow = wires[self.wire_borrow(i)];
+                constraint = builder.mul_const_add_extension(base, borrow, constraint);
+            }
+            constraints.push(constraint);
+        }
+        for i in 0..NUM_LIMBS - 1 {
+            let borrow = wires[self.wire_borrow(i)];
+            constraints.push(builder.mul_sub_extension(borrow, borrow, borrow));
+        }
+        let shift = -F::from_canonical_u64(1 << WIDE_SHIFT);
+        for j in 0..self.num_wide {
+            let wide = builder.sub_extension(wires[self.wire_wide(j)], wires[self.wire_low(j)]);
+            let high = wires[self.wire_high(j)];
+            constraints.push(builder.mul_const_add_extension(shift, high, wide));
+        }
+        constraints
+    }
+
+    fn generators(&self, row: usize, _local_constants: &[F]) -> Vec<WitnessGeneratorRef<F, D>> {
+        vec![WitnessGeneratorRef::new(
+            Bn254CheckGenerator::<F, D> {
+                row,
+                gate: *self,
+                _phantom: PhantomData,
+            }
+            .adapter(),
+        )]
+    }
+
+    fn num_wires(&self) -> usize {
+        self.num_routed_wires() + NUM_LIMBS - 1
+    }
+
+    fn num_constants(&self) -> usize {
+        0
+    }
+
+    fn degree(&self) -> usize {
+        2
+    }
+
+    fn num_constraints(&self) -> usize {
+        2 * NUM_LIMBS - 1 + self.num_wide
+    }
+}
+
+/// Witness generator filling the differences, splits and borrows of a `Bn254CheckGate` row.
+#[derive(Debug)]
+pub struct Bn254CheckGenerator<F: RichField + Extendable<D>, const D: usize> {
+    row: usize,
+    gate: Bn254CheckGate,
+    _phantom: PhantomData<F>,
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> SimpleGenerator<F, D>
+    for Bn254CheckGenerator<F, D>
+{
+    fn id(&self) -> String {
+        "Bn254CheckGenerator".to_string()
+    }
+
+    fn dependencies(&self) -> Vec<Target> {
+        let remainder = (0..NUM_LIMBS).map(|i| self.gate.wire_remainder(i));
+        let wide = (0..self.gate.num_wide).map(|j| self.gate.wire_wide(j));
+        remainder
+            .chain(wide)
+            .map(|column| Target::wire(self.row, column))
+            .collect()
+    }
+
+    fn run_once(
+        &self,
+        witness: &PartitionWitness<F>,
+        out_buffer: &mut GeneratedValues<F>,
+    ) -> anyhow::Result<()> {
+        let read = |column| {
+            witness
+                .get_target(Target::wire(self.row, column))
+                .to_canonical_u64()
+        };
+        let mut set = |column: usize, value: u64| {
+            out_buffer.set_target(Target::wire(self.row, column), F::from_canonical_u64(value))
+        };
+        let gate = &self.gate;
+
+        let mut borrow = 0;
+        for (i, &max) in max_limbs().iter().enumerate() {
+            let remainder = read(gate.wire_remainder(i));
+            ensure!(
+                remainder < LIMB_BASE,
+                "remainder limb out of range in Bn254CheckGate"
+            );
+            let difference = (max + LIMB_BASE) - remainder - borrow;
+            borrow = u64::from(difference < LIMB_BASE);
+            set(gate.wire_difference(i), difference % LIMB_BASE)?;
+            if i < NUM_LIMBS - 1 {
+                set(gate.wire_borrow(i), borrow)?;
+            }
+        }
+        ensure!(borrow == 0, "non-canonical remainder in Bn254CheckGate");
+
+        for j in 0..gate.num_wide {
+            let wide = read(gate.wire_wide(j));
+            ensure!(
+                wide >> (LIMB_BITS + WIDE_SHIFT) == 0,
+                "carry or quotient out of range in Bn254CheckGate"
+            );
+            set(gate.wire_low(j), wide & ((1 << WIDE_SHIFT) - 1))?;
+            set(gate.wire_high(j), wide >> WIDE_SHIFT)?;
+        }
+        Ok(())
+    }
+
+    fn serialize(&self, dst: &mut Vec<u8>, common_data: &CommonCircuitData<F, D>) -> IoResult<()> {
+        dst.write_usize(self.row)?;
+        Gate::<F, D>::serialize(&self.gate, dst, common_data)
+    }
+
+    fn deserialize(src: &mut Buffer, common_data: &CommonCircuitData<F, D>) -> IoResult<Self> {
+        let row = src.read_usize()?;
+        let gate = <Bn254CheckGate as Gate<F, D>>::deserialize(src, common_data)?;
+        Ok(Self {
+            row,
+            gate,
+            _phantom: PhantomData,
+        })
+    }
+}
+
+/// Adds a `Bn254CheckGate` row for the output `remainder` and the `wide` carries and quotient
+/// of a gate row, and looks up every limb it relies on in the limb table.
+fn check_outputs<F: RichField + Extendable<D>, const D: usize>(
+    remainder: &[Target; NUM_LIMBS],
+    wide: &[Target],
+    builder: &mut CircuitBuilder<F, D>,
+) {
+    let gate = Bn254CheckGate::new(wide.len());
+    let row = builder.add_gate(gate, vec![]);
+    let wire = |column| Target::wire(row, column);
+    for (i, &limb) in remainder.iter().enumerate() {
+        builder.connect(limb, wire(gate.wire_remainder(i)));
+    }
+    for (j, &value) in wide.iter().enumerate() {
+        builder.connect(value, wire(gate.wire_wide(j)));
+    }
+    let limbs = (0..NUM_LIMBS)
+        .flat_map(|i| [gate.wire_remainder(i), gate.wire_difference(i)])
+        .chain((0..wide.len()).flat_map(|j| [gate.wire_low(j), gate.wire_high(j)]))
+        .map(wire)
+        .collect::<Vec<_>>();
+    range_check_limbs(builder, &limbs);
+}
+
+/// Returns `a * b` using one `Bn254MulGate` row, one `Bn254CheckGate` row and lookups into the
+/// limb table, which this enables on `builder`.
+///
+/// When the builder's config routes fewer wires than the gates have, this falls back to
+/// `FrTarget::mul`.
+pub fn mul_with_gate<F: RichField + Extendable<D>, const D: usize>(
+    a: &FrTarget<F, D>,
+    b: &FrTarget<F, D>,
+    builder: &mut CircuitBuilder<F, D>,
+) -> FrTarget<F, D> {
+    if let (Some(a), Some(b)) = (a.try_to_native(builder), b.try_to_native(builder)) {
+        return FrTarget::constant(&(a * b), builder);
+    }
+    if MUL_WIRES > builder.config.num_routed_wires
+        || !Bn254CheckGate::new(NUM_CARRIES).fits(&builder.config)
+    {
+        return a.mul(b, builder);
+    }
+
+    enable_limb_lookups(builder);
+    let row = builder.add_gate(Bn254MulGate, vec![]);
+    let wire = |column| Target::wire(row, column);
+    for i in 0..NUM_LIMBS {
+        builder.connect(a.limbs[i], wire(Bn254MulGate::wire_a(i)));
+        builder.connect(b.limbs[i], wire(Bn254MulGate::wire_b(i)));
+    }
+
+    let quotient = (0..NUM_LIMBS)
+        .map(|i| wire(Bn254MulGate::wire_quotient(i)))
+        .collect::<Vec<_>>();
+    let remainder: [Target; NUM_LIMBS] =
+        std::array::from_fn(|i| wire(Bn254MulGate::wire_remainder(i)));
+    let carries = (0..NUM_CARRIES)
+        .map(|i| wire(Bn254MulGate::wire_carry(i)))
+        .collect::<Vec<_>>();
+    range_check_limbs(builder, &quotient);
+    check_outputs(&remainder, &carries, builder);
+
+    FrTarget::from_limbs(remainder)
+}
+
+/// Maximum number of terms of a `Bn254AffineGate`: with three terms its 80 wires are exactly
+/// the routed wires of the standard recursion config.
+pub const MAX_AFFINE_TERMS: usize = 3;
+/// The affine gate only has the 16 columns of the remainder, checked two at a time.
+const AFFINE_PAIRS: usize = NUM_LIMBS / 2;
+const AFFINE_CARRIES: usize = AFFINE_PAIRS - 1;
+/// An honest column sums at most 48 products of 16-bit values and subtracts `q * p[k]` with `q`
+/// below `48 * 2^16 + 1`, so it is below `3 * 2^36` in magnitude, a pair (plus its 32-bit
+/// constant) below `2^54` and the carries below `2^22`.
+///
+/// A dishonest prover may pick any quotient and carries the `Bn254CheckGate` row lets through,
+/// i.e. below `2^24`, so `q * p[k]` and a column can reach `2^41` and a pair `2^57`. Every
+/// constraint is still below `2^59` in magnitude, far below the Goldilocks modulus, so the
+/// constraints hold over the integers and the 22 bits only need to cover honest carries.
+const AFFINE_CARRY_BITS: usize = 22;
+const AFFINE_CARRY_OFFSET: u64 = 1 << AFFINE_CARRY_BITS;
+/// The reduced sum is below `(3 * 16 * 2^16 + 1) * p`, so the quotient fits in 22 bits.
+const AFFINE_QUOTIENT_BITS: usize = 22;
+
+/// A gate constraining one row of a BN254 Poseidon linear layer together with a round constant,
+/// `Σ mⱼ·xⱼ + c = q * p + r` for up to `MAX_AFFINE_TERMS` terms.
+///
+/// This covers the ARK and MDS steps of a round, leaving the S-box to `Bn254MulGate`: a round of
+/// width up to 3 then takes three multiplications per S-box plus one affine row per lane,
+/// each with its `Bn254CheckGate` row and a couple of `LookupGate` rows, instead of hundreds of
+/// generic rows. The coefficients `mⱼ` are part of the gate, so every MDS row is its own gate
+/// type. Rather than multiplying `mⱼ` and `xⱼ` limb by limb, each input limb `xⱼ[i]` is
+/// weighted by the limbs of `mⱼ * 2^(16i) mod p`. The sum then only spans the 16 columns of `r`,
+/// the quotient is a single small wire and every constraint is linear.
+///
+/// The round constant changes every round, so it is not part of the gate: it is wired in as
+/// eight 32-bit constants, one per column pair. As for `Bn254MulGate`, `affine_with_gate` checks
+/// `q`, `r` and the carries with a `Bn254CheckGate` row and 48 lookups.
+#[derive(Clone, Debug)]
+pub struct Bn254AffineGate {
+    coefficients: Vec<Fr>,
+    /// Limbs of `mⱼ * 2^(16i) mod p`, indexed like the input wires.
+    scaled_limbs: Vec<[u64; NUM_LIMBS]>,
+}
+
+impl Bn254AffineGate {
+    pub fn new(coefficients: Vec<Fr>) -> Self {
+        assert!(
+            (1..=MAX_AFFINE_TERMS).contains(&coefficients.len()),
+            "Bn254AffineGate takes 1 to {MAX_AFFINE_TERMS} terms, got {}",
+            coefficients.len()
+        );
+        let limb_base = Fr::from(LIMB_BASE);
+        let scaled_limbs = coefficients
+            .iter()
+            .flat_map(|&coefficient| {
+                (0..NUM_LIMBS).scan(coefficient, move |scaled, _| {
+                    let limbs = fr_to_limbs(scaled);
+                    *scaled *= limb_base;
+                    Some(limbs)
+                })
+            })
+            .collect();
+        Self {
+            coefficients,
+            scaled_limbs,
+        }
+    }
+
+    pub fn coefficients(&self) -> &[Fr] {
+        &self.coefficients
+    }
+
+    fn num_terms(&self) -> usize {
+        self.coefficients.len()
+    }
+
+    /// Number of wires of a gate with `num_terms` terms.
+    fn num_wires_for(num_terms: usize) -> usize {
+        num_terms * NUM_LIMBS + AFFINE_PAIRS + NUM_LIMBS + 1 + AFFINE_CARRIES
+    }
+
+    pub fn wire_input(&self, term: usize, i: usize) -> usize {
+        debug_assert!(term < self.num_terms() && i < NUM_LIMBS);
+        term * NUM_LIMBS + i
+    }
+
+    /// The 32-bit limb pair `m` of the constant.
+    pub fn wire_constant(&self, m: usize) -> usize {
+        debug_assert!(m < AFFINE_PAIRS);
+        self.num_terms() * NUM_LIMBS + m
+    }
+
+    pub fn wire_remainder(&self, i: usize) -> usize {
+        debug_assert!(i < NUM_LIMBS);
+        self.num_terms() * NUM_LIMBS + AFFINE_PAIRS + i
+    }
+
+    pub fn wire_quotient(&self) -> usize {
+        self.num_terms() * NUM_LIMBS + AFFINE_PAIRS + NUM_LIMBS
+    }
+
+    /// Offset carry out of the column pair `(2m, 2m + 1)`.
+    pub fn wire_carry(&self, m: usize) -> usize {
+        debug_assert!(m < AFFINE_CARRIES);
+        self.wire_quotient() + 1 + m
+    }
+
+    /// Constraints over any field, given a way to read the local wires.
+    fn eval<K: Field>(&self, wire: impl Fn(usize) -> K) -> Vec<K> {
+        let p = modulus_limbs();
+        let quotient = wire(self.wire_quotient());
+        let columns = (0..NUM_LIMBS)
+            .map(|k| {
+                let mut sum =
+                    -wire(self.wire_remainder(k)) - quotient * K::from_canonical_u64(p[k]);
+                for (term, limbs) in self.scaled_limbs.chunks(NUM_LIMBS).enumerate() {
+                    for (i, scaled) in limbs.iter().enumerate() {
+                        sum += wire(self.wire_input(term, i)) * K::from_canonical_u64(scaled[k]);
+                    }
+                }
+                sum
+            })
+            .collect::<Vec<_>>();
+
+        let mut carry_in = K::ZERO;
+        (0..AFFINE_PAIRS)
+            .map(|m| {
+                let pair = columns[2 * m]
+                    + columns[2 * m + 1] * K::from_canonical_u64(LIMB_BASE)
+                    + wire(self.wire_constant(m));
+                let carry_out = if m < AFFINE_CARRIES {
+                    wire(self.wire_carry(m)) - K::from_canonical_u64(AFFINE_CARRY_OFFSET)
+                } else {
+                    K::ZERO
+                };
+                let constraint = pair + carry_in - carry_out * K::from_canonical_u64(PAIR_BASE);
+                carry_in = carry_out;
+                constraint
+            })
+            .collect()
+    }
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> Gate<F, D> for Bn254AffineGate {
+    fn id(&self) -> String {
+        let coefficients = self
+            .coefficients
+            .iter()
+            .map(|c| BigUint::from(c.into_bigint()).to_string())
+            .collect::<Vec<_>>();
+        format!("Bn254AffineGate {{ coefficients: [{}] }}", coefficients.join(", "))
+    }
+
+    fn serialize(
+        &self,
+        dst: &mut Vec<u8>,
+        _common_data: &CommonCircuitData<F, D>,
+    ) -> IoResult<()> {
+        dst.write_usize(self.num_terms())?;
+        for coefficient in &self.coefficients {
+            for limb in fr_to_limbs(coefficient) {
+                dst.write_usize(limb as usize)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn deserialize(src: &mut Buffer, _common_data: &CommonCircuitData<F, D>) -> IoResult<Self> {
+        let num_terms = src.read_usize()?;
+        if !(1..=MAX_AFFINE_TERMS).contains(&num_terms) {
+            return Err(IoError);
+        }
+        let coefficients = (0..num_terms)
+            .map(|_| {
+                let limbs = (0..NUM_LIMBS)
+                    .map(|_| Ok(src.read_usize()? as u64))
+                    .collect::<IoResult<Vec<_>>>()?;
+                let value = limbs_to_biguint(&limbs);
+                if value >= modulus() {
+                    return Err(IoError);
+                }
+                Ok(Fr::from(value))
+            })
+            .collect::<IoResult<Vec<_>>>()?;
+        Ok(Self::new(coefficients))
+    }
+
+    fn eval_unfiltered(&self, vars: EvaluationVars<F, D>) -> Vec<F::Extension> {
+        self.eval(|i| vars.local_wires[i])
+    }
+
+    fn eval_unfiltered_base_one(
+        &self,
+        vars: EvaluationVarsBase<F>,
+        mut yield_constr: StridedConstraintConsumer<F>,
+    ) {
+        yield_constr.many(self.eval(|i| vars.local_wires[i]));
+    }
+
+    fn eval_unfiltered_circuit(
+        &self,
+        builder: &mut CircuitBuilder<F, D>,
+        vars: EvaluationTargets<D>,
+    ) -> Vec<ExtensionTarget<D>> {
+        let p = modulus_limbs();
+        let wires = vars.local_wires;
+        let quotient = wires[self.wire_quotient()];
+        let columns = (0..NUM_LIMBS)
+            .map(|k| {
+                let p_limb = -F::from_canonical_u64(p[k]);
+                let mut sum = builder.mul_const_extension(p_limb, quotient);
+                sum = builder.sub_extension(sum, wires[self.wire_remainder(k)]);
+                for (term, limbs) in self.scaled_limbs.chunks(NUM_LIMBS).enumerate() {
+                    for (i, scaled) in limbs.iter().enumerate() {
+                        let x = wires[self.wire_input(term, i)];
+                        let scaled = F::from_canonical_u64(scaled[k]);
+                        sum = builder.mul_const_add_extension(scaled, x, sum);
+                    }
+                }
+                sum
+            })
+            .collect::<Vec<_>>();
+
+        let offset =
+            builder.constant_extension(F::Extension::from_canonical_u64(AFFINE_CARRY_OFFSET));
+        let mut carry_in = None;
+        (0..AFFINE_PAIRS)
+            .map(|m| {
+                let base = F::from_canonical_u64(LIMB_BASE);
+                let mut constraint =
+                    builder.mul_const_add_extension(base, columns[2 * m + 1], columns[2 * m]);
+                constraint = builder.add_extension(constraint, wires[self.wire_constant(m)]);
+                if let Some(carry_in) = carry_in {
+                    constraint = builder.add_extension(constraint, carry_in);
+                }
+                if m < AFFINE_CARRIES {
+                    let carry_out = builder.sub_extension(wires[self.wire_carry(m)], offset);
+                    let pair_base = -F::from_canonical_u64(PAIR_BASE);
+                    constraint = builder.mul_const_add_extension(pair_base, carry_out, constraint);
+                    carry_in = Some(carry_out);
+                }
+                constraint
+            })
+            .collect()
+    }
+
+    fn generators(&self, row: usize, _local_constants: &[F]) -> Vec<WitnessGeneratorRef<F, D>> {
+        vec![WitnessGeneratorRef::new(
+            Bn254AffineGenerator::<F, D> {
+                row,
+                gate: self.clone(),
+                _phantom: PhantomData,
+            }
+            .adapter(),
+        )]
+    }
+
+    fn num_wires(&self) -> usize {
+        Self::num_wires_for(self.num_terms())
+    }
+
+    fn num_constants(&self) -> usize {
+        0
+    }
+
+    fn degree(&self) -> usize {
+        1
+    }
+
+    fn num_constraints(&self) -> usize {
+        AFFINE_PAIRS
+    }
+}
+
+/// Witness generator filling the remainder, quotient and carries of a `Bn254AffineGate` row.
+#[derive(Debug)]
+pub struct Bn254AffineGenerator<F: RichField + Extendable<D>, const D: usize> {
+    row: usize,
+    gate: Bn254AffineGate,
+    _phantom: PhantomData<F>,
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> SimpleGenerator<F, D>
+    for Bn254AffineGenerator<F, D>
+{
+    fn id(&self) -> String {
+        "Bn254AffineGenerator".to_string()
+    }
+
+    fn dependencies(&self) -> Vec<Target> {
+        let inputs = (0..self.gate.num_terms())
+            .flat_map(|term| (0..NUM_LIMBS).map(move |i| self.gate.wire_input(term, i)));
+        let constant = (0..AFFINE_PAIRS).map(|m| self.gate.wire_constant(m));
+        inputs
+            .chain(constant)
+            .map(|column| Target::wire(self.row, column))
+            .collect()
+    }
+
+    fn run_once(
+        &self,
+        witness: &PartitionWitness<F>,
+        out_buffer: &mut GeneratedValues<F>,
+    ) -> anyhow::Result<()> {
+        let read = |column| {
+            witness
+                .get_target(Target::wire(self.row, column))
+                .to_canonical_u64()
+        };
+        let gate = &self.gate;
+        let inputs = (0..gate.num_terms())
+            .flat_map(|term| (0..NUM_LIMBS).map(move |i| read(gate.wire_input(term, i))))
+            .collect::<Vec<_>>();
+        let constant = (0..AFFINE_PAIRS)
+            .map(|m| read(gate.wire_constant(m)))
+            .collect::<Vec<_>>();
+
+        let mut sum = constant
+            .iter()
+            .rev()
+            .fold(BigUint::zero(), |acc, &pair| (acc << (2 * LIMB_BITS)) + pair);
+        for (&x, scaled) in inputs.iter().zip(&gate.scaled_limbs) {
+            sum += limbs_to_biguint(scaled) * x;
+        }
+        let (quotient, remainder) = sum.div_rem(&modulus());
+        let quotient = quotient
+            .to_u64()
+            .filter(|quotient| quotient >> AFFINE_QUOTIENT_BITS == 0)
+            .ok_or_else(|| anyhow!("non-canonical input in Bn254AffineGate"))?;
+        let remainder = biguint_to_limbs(&remainder, NUM_LIMBS);
+
+        let p = modulus_limbs();
+        let mut columns = (0..NUM_LIMBS)
+            .map(|k| remainder[k] as i128 + (quotient * p[k]) as i128)
+            .map(|negative| -negative)
+            .collect::<Vec<_>>();
+        for (&x, scaled) in inputs.iter().zip(&gate.scaled_limbs) {
+            for (column, &limb) in columns.iter_mut().zip(scaled) {
+                *column += (x * limb) as i128;
+            }
+        }
+        let mut carry = 0i128;
+        let mut carries = Vec::with_capacity(AFFINE_CARRIES);
+        for (m, pair) in columns.chunks(2).enumerate() {
+            let total = pair[0] + (pair[1] << LIMB_BITS) + constant[m] as i128 + carry;
+            ensure!(
+                total % PAIR_BASE as i128 == 0,
+                "inexact carry in Bn254AffineGate"
+            );
+            carry = total / PAIR_BASE as i128;
+            if m < AFFINE_CARRIES {
+                carries.push(
+                    (carry + AFFINE_CARRY_OFFSET as i128)
+                        .to_u64()
+                        .ok_or_else(|| anyhow!("carry out of range in Bn254AffineGate"))?,
+                );
+            }
+        }
+        ensure!(carry == 0, "inexact carry in Bn254AffineGate");
+
+        let mut set = |column: usize, value: u64| {
+            out_buffer.set_target(Target::wire(self.row, column), F::from_canonical_u64(value))
+        };
+        for (i, &limb) in remainder.iter().enumerate() {
+            set(gate.wire_remainder(i), limb)?;
+        }
+        set(gate.wire_quotient(), quotient)?;
+        for (m, &carry) in carries.iter().enumerate() {
+            set(gate.wire_carry(m), carry)?;
+        }
+        Ok(())
+    }
+
+    fn serialize(&self, dst: &mut Vec<u8>, common_data: &CommonCircuitData<F, D>) -> IoResult<()> {
+        dst.write_usize(self.row)?;
+        Gate::<F, D>::serialize(&self.gate, dst, common_data)
+    }
+
+    fn deserialize(src: &mut Buffer, common_data: &CommonCircuitData<F, D>) -> IoResult<Self> {
+        let row = src.read_usize()?;
+        let gate = <Bn254AffineGate as Gate<F, D>>::deserialize(src, common_data)?;
+        Ok(Self {
+            row,
+            gate,
+            _phantom: PhantomData,
+        })
+    }
+}
+
+/// Returns `Σ mⱼ·xⱼ + c` using one `Bn254AffineGate` row, one `Bn254CheckGate` row and lookups
+/// into the limb table, which this enables on `builder`.
+///
+/// Constant terms are folded into `c`. With more than `MAX_AFFINE_TERMS` other terms, or when
+/// the builder's config routes fewer wires than the gates have, this falls back to
+/// `FrTarget::linear_combination`.
+pub fn affine_with_gate<F: RichField + Extendable<D>, const D: usize>(
+    terms: &[(Fr, FrTarget<F, D>)],
+    constant: &Fr,
+    builder: &mut CircuitBuilder<F, D>,
+) -> FrTarget<F, D> {
+    let mut constant = *constant;
+    let mut variables = Vec::new();
+    for &(coefficient, x) in terms {
+        match x.try_to_native(builder) {
+            Some(x) => constant += coefficient * x,
+            None if !coefficient.is_zero() => variables.push((coefficient, x)),
+            None => {}
+        }
+    }
+    if variables.is_empty() {
+        return FrTarget::constant(&constant, builder);
+    }
+    if variables.len() > MAX_AFFINE_TERMS
+        || Bn254AffineGate::num_wires_for(variables.len()) > builder.config.num_routed_wires
+        || !Bn254CheckGate::new(AFFINE_CARRIES + 1).fits(&builder.config)
+    {
+        return FrTarget::linear_combination(&variables, &constant, builder);
+    }
+
+    enable_limb_lookups(builder);
+    let coefficients = variables.iter().map(|&(coefficient, _)| coefficient).collect();
+    let gate = Bn254AffineGate::new(coefficients);
+    let row = builder.add_gate(gate.clone(), vec![]);
+    let wire = |column| Target::wire(row, column);
+    for (term, (_, x)) in variables.iter().enumerate() {
+        for (i, &limb) in x.limbs.iter().enumerate() {
+            builder.connect(limb, wire(gate.wire_input(term, i)));
+        }
+    }
+    for (m, pair) in fr_to_limbs(&constant).chunks(2).enumerate() {
+        let pair = builder.constant(F::from_canonical_u64(pair[0] + (pair[1] << LIMB_BITS)));
+        builder.connect(pair, wire(gate.wire_constant(m)));
+    }
+
+    let remainder: [Target; NUM_LIMBS] = std::array::from_fn(|i| wire(gate.wire_remainder(i)));
+    let wide = (0..AFFINE_CARRIES)
+        .map(|m| wire(gate.wire_carry(m)))
+        .chain([wire(gate.wire_quotient())])
+        .collect::<Vec<_>>();
+    check_outputs(&remainder, &wide, builder);
+
+    FrTarget::from_limbs(remainder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arithmetic::num_rows;
+    use crate::params::PoseidonParams;
+    use crate::poseidon::PoseidonCircuit;
+    use ark_ff::One;
+    use ark_std::UniformRand;
+    use plonky2::{
+        gates::gate_testing::{test_eval_fns, test_low_degree},
+        iop::witness::PartialWitness,
+        plonk::{
+            circuit_data::CircuitConfig,
+            config::{GenericConfig, PoseidonGoldilocksConfig},
+        },
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn low_degree() {
+        test_low_degree::<F, _, D>(Bn254MulGate);
+    }
+
+    #[test]
+    fn eval_fns() -> anyhow::Result<()> {
+        test_eval_fns::<F, C, _, D>(Bn254MulGate)
+    }
+
+    #[test]
+    fn check_low_degree() {
+        test_low_degree::<F, _, D>(Bn254CheckGate::new(NUM_CARRIES));
+    }
+
+    #[test]
+    fn check_eval_fns() -> anyhow::Result<()> {
+        test_eval_fns::<F, C, _, D>(Bn254CheckGate::new(NUM_CARRIES))
+    }
+
+    fn affine_gate() -> Bn254AffineGate {
+        Bn254AffineGate::new(PoseidonParams::new(3).mds[0].clone())
+    }
+
+    #[test]
+    fn affine_low_degree() {
+        test_low_degree::<F, _, D>(affine_gate());
+    }
+
+    #[test]
+    fn affine_eval_fns() -> anyhow::Result<()> {
+        test_eval_fns::<F, C, _, D>(affine_gate())
+    }
+
+    #[test]
+    fn test_affine_with_gate() {
+        let mut rng = ark_std::test_rng();
+        let mds = PoseidonParams::new(3).mds;
+        let minus_one = -Fr::one();
+        let cases = [
+            (mds[1].clone(), Fr::rand(&mut rng)),
+            (vec![minus_one; 3], minus_one),
+            (vec![Fr::rand(&mut rng), Fr::one()], Fr::zero()),
+            (vec![Fr::rand(&mut rng)], Fr::rand(&mut rng)),
+        ];
+
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let mut inputs = Vec::new();
+        for (coefficients, constant) in cases {
+            let values = coefficients
+                .iter()
+                .map(|_| -Fr::rand(&mut rng))
+                .collect::<Vec<_>>();
+            let terms = coefficients
+                .iter()
+                .map(|&coefficient| (coefficient, FrTarget::new(&mut builder)))
+                .collect::<Vec<_>>();
+            let num_gates = builder.num_gates();
+            let result = affine_with_gate(&terms, &constant, &mut builder);
+            assert!(
+                builder.num_gates() > num_gates,
+                "affine_with_gate did not add a gate"
+            );
+            let expected = coefficients
+                .iter()
+                .zip(&values)
+                .fold(constant, |acc, (m, x)| acc + *m * x);
+            let expected = FrTarget::constant(&expected, &mut builder);
+            for (a, b) in result.limbs.into_iter().zip(expected.limbs) {
+                builder.connect(a, b);
+            }
+            inputs.extend(terms.into_iter().map(|(_, x)| x).zip(values));
+        }
+
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        for (x, value) in &inputs {
+            x.set_witness(&mut pw, value).unwrap();
+        }
+        let proof = data.prove(pw).unwrap();
+        data.verify(proof).unwrap();
+    }
+
+    #[test]
+    fn test_mul_with_gate() {
+        let mut rng = ark_std::test_rng();
+        let cases = [
+            (Fr::rand(&mut rng), Fr::rand(&mut rng)),
+            (-Fr::one(), -Fr::one()),
+            (Fr::zero(), Fr::rand(&mut rng)),
+        ];
+
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let mut inputs = Vec::new();
+        for (x_value, y_value) in cases {
+            let x = FrTarget::new(&mut builder);
+            let y = FrTarget::new(&mut builder);
+            let product = mul_with_gate(&x, &y, &mut builder);
+            let expected = FrTarget::constant(&(x_value * y_value), &mut builder);
+            for (a, b) in product.limbs.into_iter().zip(expected.limbs) {
+                builder.connect(a, b);
+            }
+            inputs.push((x, x_value, y, y_value));
+        }
+
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        for (x, x_value, y, y_value) in &inputs {
+            x.set_witness(&mut pw, x_value).unwrap();
+            y.set_witness(&mut pw, y_value).unwrap();
+        }
+        let proof = data.prove(pw).unwrap();
+        data.verify(proof).unwrap();
+    }
+    #[test]
+    fn test_check_gate_rejects_non_canonical_remainders() {
+        let config = CircuitConfig::standard_recursion_config();
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        enable_limb_lookups(&mut builder);
+        let remainder: [Target; NUM_LIMBS] = builder.add_virtual_target_arr();
+        let carry = builder.add_virtual_target();
+        check_outputs(&remainder, &[carry], &mut builder);
+        let data = builder.build::<C>();
+
+        let prove = |value: &BigUint, carry_value: u64| {
+            let mut pw = PartialWitness::new();
+            for (&limb, value) in remainder.iter().zip(biguint_to_limbs(value, NUM_LIMBS)) {
+                pw.set_target(limb, F::from_canonical_u64(value)).unwrap();
+            }
+            pw.set_target(carry, F::from_canonical_u64(carry_value)).unwrap();
+            data.prove(pw)
+        };
+        let max = modulus() - 1u32;
+        data.verify(prove(&max, (1 << 23) - 1).unwrap()).unwrap();
+        assert!(prove(&modulus(), 0).is_err());
+        assert!(prove(&max, 1 << 24).is_err());
+    }
+
+    /// Minimum share of rows, in percent, saved by the custom gates on a single Poseidon round of
+    /// width up to `MAX_AFFINE_TERMS`, full or partial.
+    const MIN_ROUND_SAVING_PERCENT: usize = 90;
+
+    /// Returns the rows added by round `round` of a Poseidon permutation over private inputs,
+    /// including the `LookupGate` rows of its lookups.
+    fn round_rows(width: usize, round: usize, gates: bool) -> usize {
+        let mut circuit = PoseidonCircuit::<F, D>::with_width(width).with_gates(gates);
+        let mut state = (0..width)
+            .map(|_| FrTarget::new(&mut circuit.builder))
+            .collect::<Vec<_>>();
+        let rows = num_rows(&circuit.builder);
+        circuit.round(&mut state, round);
+        num_rows(&circuit.builder) - rows
+    }
+
+    #[test]
+    fn test_round_rows() {
+        for width in 2..=MAX_AFFINE_TERMS {
+            let params = PoseidonParams::new(width);
+            let partial_round = params.full_rounds / 2;
+            assert!(params.is_full_round(0) && !params.is_full_round(partial_round));
+            for round in [0, partial_round] {
+                let gates = round_rows(width, round, true);
+                let generic = round_rows(width, round, false);
+                let saved = 100 * (generic - gates) / generic;
+                assert!(
+                    saved >= MIN_ROUND_SAVING_PERCENT,
+                    "round {round} of width {width}: {gates} rows with the gates, {generic} without"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_mul_with_gate_falls_back_on_narrow_configs() {
+        let config = CircuitConfig {
+            num_routed_wires: 64,
+            ..CircuitConfig::standard_recursion_config()
+        };
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let x = FrTarget::new(&mut builder);
+        let y = FrTarget::new(&mut builder);
+        let product = mul_with_gate(&x, &y, &mut builder);
+
+        let mut rng = ark_std::test_rng();
+        let (x_value, y_value) = (Fr::rand(&mut rng), Fr::rand(&mut rng));
+        let expected = FrTarget::constant(&(x_value * y_value), &mut builder);
+        for (a, b) in product.limbs.into_iter().zip(expected.limbs) {
+            builder.connect(a, b);
+        }
+
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        x.set_witness(&mut pw, &x_value).unwrap();
+        y.set_witness(&mut pw, &y_value).unwrap();
+        let proof = data.prove(pw).unwrap();
+        data.verify(proof).unwrap();
+    }
+}
@@ -1,2 +1,12 @@
 pub mod arithmetic;
+pub mod convert;
+pub mod gate;
+pub mod merkle;
+pub mod native;
+pub mod params;
 pub mod poseidon;
+pub mod poseidon2;
+pub mod serialization;
+
+#[cfg(test)]
+mod test_vectors;
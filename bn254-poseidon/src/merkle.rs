@@ -0,0 +1,278 @@
+//! Sparse Merkle tree openings over BN254 Poseidon.
+//!
+//! Internal nodes are circomlib's `poseidon([left, right])`, i.e. `PoseidonCircuit::new()` and
+//! `PoseidonNative::new(3)`, so roots can be recomputed by the circomlibjs-generated Poseidon
+//! contracts on the EVM. Bit `i` of the key, least significant first, says whether the node at
+//! height `i` on the path is a right child. Empty leaves are zero.
+
+use anyhow::ensure;
+use ark_bn254::Fr;
+use ark_ff::{PrimeField, Zero};
+use plonky2::{
+    field::extension::Extendable,
+    hash::hash_types::RichField,
+    iop::{target::BoolTarget, witness::WitnessWrite},
+};
+use std::collections::HashMap;
+
+use super::arithmetic::{register_public_fr, FrTarget};
+use super::convert::fr_to_bits_le;
+use super::native::PoseidonNative;
+use super::poseidon::{PermutationCircuit, DEFAULT_WIDTH};
+
+/// Maximum tree depth: one level per bit of a BN254 scalar key.
+pub const MAX_DEPTH: usize = Fr::MODULUS_BIT_SIZE as usize;
+
+/// Computes the root of the path from `leaf` up through `siblings`, ordering each pair of
+/// children by the matching bit of `key_bits`.
+pub fn merkle_root<F: RichField + Extendable<D>, const D: usize>(
+    circuit: &mut impl PermutationCircuit<F, D>,
+    key_bits: &[BoolTarget],
+    leaf: &FrTarget<F, D>,
+    siblings: &[FrTarget<F, D>],
+) -> FrTarget<F, D> {
+    assert!(key_bits.len() >= siblings.len(), "not enough key bits");
+    let mut node = *leaf;
+    for (&bit, sibling) in key_bits.iter().zip(siblings) {
+        let (left, right) = FrTarget::swap(bit, &node, sibling, circuit.builder());
+        node = circuit.hash_fr(&[left, right]);
+    }
+    node
+}
+
+/// Targets of a sparse Merkle inclusion proof: the key and siblings are private, the root and
+/// leaf are public inputs, in that order.
+///
+/// The key is not public, so the leaf should commit to it (e.g. `poseidon([key, value])`) if
+/// the verifier needs to know where it sits in the tree.
+pub struct MerkleProofTarget<F: RichField + Extendable<D>, const D: usize> {
+    pub key: FrTarget<F, D>,
+    pub leaf: FrTarget<F, D>,
+    pub siblings: Vec<FrTarget<F, D>>,
+    pub root: FrTarget<F, D>,
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> MerkleProofTarget<F, D> {
+    /// Adds an inclusion proof for a tree of the given depth to `circuit`, which should be a
+    /// `PoseidonCircuit` of width 3 to match `SparseMerkleTree`.
+    pub fn new(depth: usize, circuit: &mut impl PermutationCircuit<F, D>) -> Self {
+        assert!(depth <= MAX_DEPTH, "depth must be at most {MAX_DEPTH}, got {depth}");
+        let key = FrTarget::new(circuit.builder());
+        let leaf = FrTarget::new(circuit.builder());
+        let siblings = (0..depth)
+            .map(|_| FrTarget::new(circuit.builder()))
+            .collect::<Vec<_>>();
+        let key_bits = key.to_bits_le(circuit.builder());
+        let root = merkle_root(circuit, &key_bits, &leaf, &siblings);
+        register_public_fr(circuit.builder(), &root);
+        register_public_fr(circuit.builder(), &leaf);
+        Self {
+            key,
+            leaf,
+            siblings,
+            root,
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.siblings.len()
+    }
+
+    /// Assigns the key, leaf and siblings of `proof`; the root is derived by the circuit.
+    pub fn set_witness<W: WitnessWrite<F>>(
+        &self,
+        witness: &mut W,
+        proof: &MerkleProof,
+    ) -> anyhow::Result<()> {
+        ensure!(
+            proof.siblings.len() == self.depth(),
+            "expected {} siblings, got {}",
+            self.depth(),
+            proof.siblings.len()
+        );
+        self.key.set_witness(witness, &proof.key)?;
+        self.leaf.set_witness(witness, &proof.leaf)?;
+        for (sibling, value) in self.siblings.iter().zip(&proof.siblings) {
+            sibling.set_witness(witness, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Native sparse Merkle inclusion proof, as produced by `SparseMerkleTree::prove`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub key: Fr,
+    pub leaf: Fr,
+    /// Siblings from the leaf level up to the children of the root.
+    pub siblings: Vec<Fr>,
+}
+
+impl MerkleProof {
+    /// Recomputes the root, like `merkle_root` does in the circuit.
+    pub fn root(&self) -> Fr {
+        let hasher = PoseidonNative::new(DEFAULT_WIDTH);
+        let bits = fr_to_bits_le(&self.key);
+        self.siblings
+            .iter()
+            .zip(bits)
+            .fold(self.leaf, |node, (&sibling, bit)| {
+                let pair = if bit { [sibling, node] } else { [node, sibling] };
+                hasher.hash(&pair)
+            })
+    }
+}
+
+/// Native sparse Merkle tree of a fixed depth, storing only the non-empty nodes.
+#[derive(Clone, Debug)]
+pub struct SparseMerkleTree {
+    depth: usize,
+    hasher: PoseidonNative,
+    /// Non-empty nodes, keyed by their path from the root, i.e. the key bits above their height.
+    nodes: HashMap<Vec<bool>, Fr>,
+    /// Root of an empty subtree of each height, from the leaves (zero) up to the root.
+    empty: Vec<Fr>,
+}
+
+impl SparseMerkleTree {
+    pub fn new(depth: usize) -> Self {
+        assert!(depth <= MAX_DEPTH, "depth must be at most {MAX_DEPTH}, got {depth}");
+        let hasher = PoseidonNative::new(DEFAULT_WIDTH);
+        let mut empty = vec![Fr::zero()];
+        for height in 0..depth {
+            empty.push(hasher.hash(&[empty[height], empty[height]]));
+        }
+        Self {
+            depth,
+            hasher,
+            nodes: HashMap::new(),
+            empty,
+        }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn root(&self) -> Fr {
+        self.node(self.depth, &[])
+    }
+
+    /// Returns the leaf at `key`, which is zero if it has never been set.
+    pub fn get(&self, key: &Fr) -> Fr {
+        self.node(0, &self.path(key))
+    }
+
+    /// Sets the leaf at `key` and updates the nodes above it. Keys that agree on their low
+    /// `depth` bits address the same leaf.
+    pub fn insert(&mut self, key: &Fr, leaf: Fr) {
+        let path = self.path(key);
+        self.nodes.insert(path.clone(), leaf);
+        for height in 0..self.depth {
+            let mut child = path[height..].to_vec();
+            let node = self.node(height, &child);
+            child[0] = !child[0];
+            let sibling = self.node(height, &child);
+            let pair = if path[height] { [sibling, node] } else { [node, sibling] };
+            self.nodes.insert(path[height + 1..].to_vec(), self.hasher.hash(&pair));
+        }
+    }
+
+    /// Returns an inclusion proof for the leaf at `key`, which may be empty.
+    pub fn prove(&self, key: &Fr) -> MerkleProof {
+        let path = self.path(key);
+        let siblings = (0..self.depth)
+            .map(|height| {
+                let mut sibling = path[height..].to_vec();
+                sibling[0] = !sibling[0];
+                self.node(height, &sibling)
+            })
+            .collect();
+        MerkleProof {
+            key: *key,
+            leaf: self.get(key),
+            siblings,
+        }
+    }
+
+    /// The low `depth` bits of `key`, i.e. the path from the leaf to the root.
+    fn path(&self, key: &Fr) -> Vec<bool> {
+        fr_to_bits_le(key)[..self.depth].to_vec()
+    }
+
+    /// The node at `height` whose path from the root is `path`.
+    fn node(&self, height: usize, path: &[bool]) -> Fr {
+        self.nodes.get(path).copied().unwrap_or(self.empty[height])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arithmetic::public_inputs_to_fr;
+    use crate::poseidon::PoseidonCircuit;
+    use ark_std::UniformRand;
+    use plonky2::{
+        iop::witness::PartialWitness,
+        plonk::config::{GenericConfig, PoseidonGoldilocksConfig},
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn test_sparse_merkle_tree() {
+        let mut rng = ark_std::test_rng();
+        let mut tree = SparseMerkleTree::new(8);
+        let empty_root = tree.root();
+        assert_eq!(tree.prove(&Fr::from(5u64)).root(), empty_root);
+
+        let entries = [(3u64, Fr::rand(&mut rng)), (200, Fr::rand(&mut rng))];
+        for (key, leaf) in entries {
+            tree.insert(&Fr::from(key), leaf);
+        }
+        assert_ne!(tree.root(), empty_root);
+        for (key, leaf) in entries {
+            let proof = tree.prove(&Fr::from(key));
+            assert_eq!(proof.leaf, leaf);
+            assert_eq!(proof.root(), tree.root());
+        }
+        // Only the low `depth` bits of a key select the leaf.
+        assert_eq!(tree.get(&Fr::from(3u64 + 256)), entries[0].1);
+        // Non-membership: the empty leaf at an unused key opens to the same root.
+        let proof = tree.prove(&Fr::from(4u64));
+        assert_eq!(proof.leaf, Fr::zero());
+        assert_eq!(proof.root(), tree.root());
+    }
+
+    #[test]
+    fn test_merkle_proof_circuit() {
+        let mut rng = ark_std::test_rng();
+        let depth = 16;
+        let mut tree = SparseMerkleTree::new(depth);
+        let key = Fr::from(0xbeefu64);
+        tree.insert(&key, Fr::rand(&mut rng));
+        tree.insert(&Fr::from(0xbeeeu64), Fr::rand(&mut rng));
+        let proof = tree.prove(&key);
+
+        let mut circuit = PoseidonCircuit::<F, D>::new();
+        let target = MerkleProofTarget::new(depth, &mut circuit);
+        let data = circuit.builder.build::<C>();
+
+        let mut pw = PartialWitness::new();
+        target.set_witness(&mut pw, &proof).unwrap();
+        let output = data.prove(pw).unwrap();
+        let public = public_inputs_to_fr(&output.public_inputs).unwrap();
+        assert_eq!(public, [tree.root(), proof.leaf]);
+        data.verify(output).unwrap();
+
+        // A wrong sibling yields a different root.
+        let mut bad = proof.clone();
+        bad.siblings[3] += Fr::from(1u64);
+        let mut pw = PartialWitness::new();
+        target.set_witness(&mut pw, &bad).unwrap();
+        let output = data.prove(pw).unwrap();
+        assert_ne!(public_inputs_to_fr(&output.public_inputs).unwrap()[0], tree.root());
+    }
+}
@@ -0,0 +1,258 @@
+use ark_bn254::Fr;
+use ark_ff::{Field, One, Zero};
+
+use super::params::{Poseidon2Params, PoseidonParams, ALPHA};
+
+/// Out-of-circuit BN254 Poseidon over `ark_bn254::Fr`, using the same parameters and state
+/// layout as `PoseidonCircuit`.
+#[derive(Clone, Debug)]
+pub struct PoseidonNative {
+    params: PoseidonParams,
+}
+
+impl PoseidonNative {
+    pub fn new(width: usize) -> Self {
+        Self {
+            params: PoseidonParams::new(width),
+        }
+    }
+
+    pub fn params(&self) -> &PoseidonParams {
+        &self.params
+    }
+
+    pub fn permute(&self, state: &mut [Fr]) {
+        assert_eq!(state.len(), self.params.width);
+        for round in 0..self.params.rounds() {
+            for (x, c) in state.iter_mut().zip(&self.params.ark[round]) {
+                *x += c;
+            }
+            if self.params.is_full_round(round) {
+                for x in state.iter_mut() {
+                    *x = x.pow([ALPHA]);
+                }
+            } else {
+                state[0] = state[0].pow([ALPHA]);
+            }
+            mat_mul(&self.params.mds, state);
+        }
+    }
+
+    /// Hashes at most `width - 1` inputs like `PoseidonCircuit::hash_fr`: the inputs follow a
+    /// zero capacity lane, shorter inputs are zero-padded and the first lane of the permuted
+    /// state is returned. Use `PoseidonSpongeNative` for longer inputs.
+    pub fn hash(&self, inputs: &[Fr]) -> Fr {
+        let rate = self.params.width - 1;
+        assert!(
+            inputs.len() <= rate,
+            "hash takes at most {rate} inputs, got {}; use the sponge instead",
+            inputs.len()
+        );
+        let mut state = vec![Fr::zero(); self.params.width];
+        state[1..=inputs.len()].copy_from_slice(inputs);
+        self.permute(&mut state);
+        state[0]
+    }
+}
+
+/// Out-of-circuit BN254 Poseidon2 over `ark_bn254::Fr`, using the same parameters and state
+/// layout as `Poseidon2Circuit`.
+#[derive(Clone, Debug)]
+pub struct Poseidon2Native {
+    params: Poseidon2Params,
+}
+
+impl Poseidon2Native {
+    pub fn new(width: usize) -> Self {
+        Self {
+            params: Poseidon2Params::new(width),
+        }
+    }
+
+    pub fn params(&self) -> &Poseidon2Params {
+        &self.params
+    }
+
+    pub fn permute(&self, state: &mut [Fr]) {
+        assert_eq!(state.len(), self.params.width);
+        mat_mul(&self.params.external_matrix, state);
+        for round in 0..self.params.rounds() {
+            if self.params.is_full_round(round) {
+                for (x, c) in state.iter_mut().zip(&self.params.ark[round]) {
+                    *x = (*x + c).pow([ALPHA]);
+                }
+            } else {
+                state[0] = (state[0] + self.params.ark[round][0]).pow([ALPHA]);
+            }
+            mat_mul(self.params.matrix(round), state);
+        }
+    }
+
+    /// Hashes at most `width - 1` inputs like `Poseidon2Circuit::hash_fr`: the inputs follow
+    /// a zero capacity lane and the first lane of the permuted state is returned.
+    pub fn hash(&self, inputs: &[Fr]) -> Fr {
+        assert!(inputs.len() < self.params.width);
+        let mut state = vec![Fr::zero(); self.params.width];
+        state[1..=inputs.len()].copy_from_slice(inputs);
+        self.permute(&mut state);
+        state[0]
+    }
+}
+
+fn mat_mul(matrix: &[Vec<Fr>], state: &mut [Fr]) {
+    let new_state = matrix
+        .iter()
+        .map(|row| row.iter().zip(state.iter()).map(|(m, x)| *m * x).sum())
+        .collect::<Vec<Fr>>();
+    state.copy_from_slice(&new_state);
+}
+
+/// Whether a sponge is absorbing or squeezing, with the next rate lane it will use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SpongeMode {
+    Absorbing(usize),
+    Squeezing(usize),
+}
+
+/// Out-of-circuit counterpart of `PoseidonSponge`, with one capacity lane and `width - 1` rate
+/// lanes.
+#[derive(Clone, Debug)]
+pub struct PoseidonSpongeNative {
+    poseidon: PoseidonNative,
+    state: Vec<Fr>,
+    mode: SpongeMode,
+}
+
+impl PoseidonSpongeNative {
+    pub fn new(width: usize, domain_tag: &Fr) -> Self {
+        let mut state = vec![Fr::zero(); width];
+        state[0] = *domain_tag;
+        Self {
+            poseidon: PoseidonNative::new(width),
+            state,
+            mode: SpongeMode::Absorbing(0),
+        }
+    }
+
+    fn rate(&self) -> usize {
+        self.state.len() - 1
+    }
+
+    pub fn absorb(&mut self, inputs: &[Fr]) {
+        for x in inputs {
+            let pos = match self.mode {
+                SpongeMode::Absorbing(pos) => pos,
+                SpongeMode::Squeezing(_) => 0,
+            };
+            self.state[1 + pos] += x;
+            if pos + 1 == self.rate() {
+                self.poseidon.permute(&mut self.state);
+                self.mode = SpongeMode::Absorbing(0);
+            } else {
+                self.mode = SpongeMode::Absorbing(pos + 1);
+            }
+        }
+    }
+
+    pub fn squeeze(&mut self, num_outputs: usize) -> Vec<Fr> {
+        let mut outputs = Vec::with_capacity(num_outputs);
+        for _ in 0..num_outputs {
+            let pos = match self.mode {
+                SpongeMode::Absorbing(pos) => {
+                    // 10* padding of the pending block.
+                    self.state[1 + pos] += Fr::one();
+                    self.poseidon.permute(&mut self.state);
+                    0
+                }
+                SpongeMode::Squeezing(pos) if pos == self.rate() => {
+                    self.poseidon.permute(&mut self.state);
+                    0
+                }
+                SpongeMode::Squeezing(pos) => pos,
+            };
+            outputs.push(self.state[1 + pos]);
+            self.mode = SpongeMode::Squeezing(pos + 1);
+        }
+        outputs
+    }
+}
+
+/// circomlibjs' `poseidon(inputs)`, which uses a state of width `inputs.len() + 1`.
+pub fn poseidon_hash(inputs: &[Fr]) -> Fr {
+    PoseidonNative::new(inputs.len() + 1).hash(inputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_vectors::{poseidon2_vectors, poseidon_vectors};
+
+    #[test]
+    fn test_native_vectors() {
+        for vector in poseidon_vectors() {
+            assert_eq!(poseidon_hash(&vector.inputs), vector.output, "inputs: {:?}", vector.inputs);
+        }
+    }
+
+    #[test]
+    fn test_native_hash_pads_short_inputs() {
+        let poseidon = PoseidonNative::new(3);
+        let one = Fr::one();
+        assert_eq!(poseidon.hash(&[one]), poseidon.hash(&[one, Fr::zero()]));
+    }
+
+    #[test]
+    #[should_panic(expected = "at most 2 inputs")]
+    fn test_native_hash_rejects_more_than_rate_inputs() {
+        PoseidonNative::new(3).hash(&[Fr::one(); 3]);
+    }
+
+    #[test]
+    fn test_poseidon2_native_vectors() {
+        for vector in poseidon2_vectors() {
+            let mut state = vector.input.clone();
+            Poseidon2Native::new(state.len()).permute(&mut state);
+            assert_eq!(state, vector.output, "input: {:?}", vector.input);
+        }
+    }
+
+    #[test]
+    fn test_sponge_padding_and_domain_separation() {
+        let hash = |inputs: &[Fr], domain_tag: u64| {
+            let mut sponge = PoseidonSpongeNative::new(3, &Fr::from(domain_tag));
+            sponge.absorb(inputs);
+            sponge.squeeze(1)[0]
+        };
+        let one = Fr::one();
+        let zero = Fr::zero();
+        let outputs = [
+            hash(&[], 0),
+            hash(&[zero], 0),
+            hash(&[zero, zero], 0),
+            hash(&[one], 0),
+            hash(&[one, zero], 0),
+            hash(&[one], 1),
+            hash(&[one, zero, zero], 0),
+        ];
+        for (i, a) in outputs.iter().enumerate() {
+            for b in &outputs[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sponge_incremental() {
+        let inputs = (1..=5u64).map(Fr::from).collect::<Vec<_>>();
+        let mut all_at_once = PoseidonSpongeNative::new(3, &Fr::zero());
+        all_at_once.absorb(&inputs);
+        let mut incremental = PoseidonSpongeNative::new(3, &Fr::zero());
+        for x in &inputs {
+            incremental.absorb(std::slice::from_ref(x));
+        }
+        let expected = all_at_once.squeeze(5);
+        let mut outputs = incremental.squeeze(2);
+        outputs.extend(incremental.squeeze(3));
+        assert_eq!(outputs, expected);
+    }
+}
@@ -0,0 +1,180 @@
+use ark_bn254::Fr;
+use ark_crypto_primitives::sponge::poseidon::find_poseidon_ark_and_mds;
+use ark_ff::{One, PrimeField, Zero};
+
+pub const ALPHA: u64 = 5;
+pub const FULL_ROUNDS: usize = 8;
+
+/// Partial rounds for state widths `t = 2..=17`, matching circomlib's `poseidon.circom`.
+pub const PARTIAL_ROUNDS: [usize; 16] = [56, 57, 56, 60, 60, 63, 64, 63, 60, 66, 60, 65, 70, 60, 64, 68];
+
+pub const MIN_WIDTH: usize = 2;
+pub const MAX_WIDTH: usize = MIN_WIDTH + PARTIAL_ROUNDS.len() - 1;
+
+/// Poseidon parameters over the BN254 scalar field for a single state width.
+///
+/// The round constants and MDS matrix are regenerated with the Grain LFSR of the reference
+/// `generate_parameters_grain.sage` script, which is how circomlib's `poseidon_constants.json`
+/// was produced, so they are identical to the circomlib/iden3 constants.
+#[derive(Clone, Debug)]
+pub struct PoseidonParams {
+    pub width: usize,
+    pub full_rounds: usize,
+    pub partial_rounds: usize,
+    /// Round constants, one row of `width` elements per round.
+    pub ark: Vec<Vec<Fr>>,
+    pub mds: Vec<Vec<Fr>>,
+}
+
+impl PoseidonParams {
+    pub fn new(width: usize) -> Self {
+        assert!(
+            (MIN_WIDTH..=MAX_WIDTH).contains(&width),
+            "Poseidon width must be between {MIN_WIDTH} and {MAX_WIDTH}, got {width}"
+        );
+        let partial_rounds = PARTIAL_ROUNDS[width - MIN_WIDTH];
+        let (ark, mds) = find_poseidon_ark_and_mds::<Fr>(
+            Fr::MODULUS_BIT_SIZE as u64,
+            width - 1,
+            FULL_ROUNDS as u64,
+            partial_rounds as u64,
+            0,
+        );
+        Self {
+            width,
+            full_rounds: FULL_ROUNDS,
+            partial_rounds,
+            ark,
+            mds,
+        }
+    }
+
+    pub fn rounds(&self) -> usize {
+        self.full_rounds + self.partial_rounds
+    }
+
+    /// Whether `round` applies the S-box to the whole state rather than the first lane only.
+    pub fn is_full_round(&self, round: usize) -> bool {
+        round < self.full_rounds / 2 || round >= self.full_rounds / 2 + self.partial_rounds
+    }
+}
+
+pub const POSEIDON2_FULL_ROUNDS: usize = 8;
+pub const POSEIDON2_PARTIAL_ROUNDS: usize = 56;
+/// `t = 2` is not supported: no reference implementation publishes a BN254 `t = 2` instance, so
+/// there is no external known-answer test to check its constants and matrices against.
+pub const POSEIDON2_MIN_WIDTH: usize = 3;
+pub const POSEIDON2_MAX_WIDTH: usize = 4;
+
+/// Diagonal of the `t = 4` internal matrix minus the identity, from Aztec's barretenberg.
+const POSEIDON2_INTERNAL_DIAGONAL_4: [&str; 4] = [
+    "10dc6e9c006ea38b04b1e03b4bd9490c0d03f98929ca1d7fb56821fd19d3b6e7",
+    "0c28145b6a44df3e0149b3d0a30b3bb599df9756d4dd9b84a86b38cfb45a740b",
+    "00544b8338791518b2c7645a50392798b21f75bb60e3596170067d00141cac15",
+    "222c01175718386f2e2e82eb122789e352e105a3b8fa852613bc534433ee428b",
+];
+
+/// Poseidon2 parameters over the BN254 scalar field for a single state width.
+///
+/// The reference Poseidon2 script draws `R_F·t + R_P` round constants from the same Grain LFSR
+/// as Poseidon, seeded with the same header, so they are a prefix of the Poseidon constant
+/// stream for `(t, R_F, R_P)`. This reproduces the HorizenLabs `t = 3` and barretenberg `t = 4`
+/// instances, the only widths with published BN254 vectors.
+#[derive(Clone, Debug)]
+pub struct Poseidon2Params {
+    pub width: usize,
+    pub full_rounds: usize,
+    pub partial_rounds: usize,
+    /// Round constants, one row of `width` elements per round. Partial rounds only have a
+    /// constant for the first lane; the other entries are zero.
+    pub ark: Vec<Vec<Fr>>,
+    /// External linear layer, applied before the first round and after every full round.
+    pub external_matrix: Vec<Vec<Fr>>,
+    /// Internal linear layer `J + diag(d)`, applied after every partial round.
+    pub internal_matrix: Vec<Vec<Fr>>,
+}
+
+impl Poseidon2Params {
+    pub fn new(width: usize) -> Self {
+        assert!(
+            (POSEIDON2_MIN_WIDTH..=POSEIDON2_MAX_WIDTH).contains(&width),
+            "Poseidon2 width must be between {POSEIDON2_MIN_WIDTH} and {POSEIDON2_MAX_WIDTH}, \
+             got {width}"
+        );
+        let full_rounds = POSEIDON2_FULL_ROUNDS;
+        let partial_rounds = POSEIDON2_PARTIAL_ROUNDS;
+        let (poseidon_ark, _) = find_poseidon_ark_and_mds::<Fr>(
+            Fr::MODULUS_BIT_SIZE as u64,
+            width - 1,
+            full_rounds as u64,
+            partial_rounds as u64,
+            0,
+        );
+        let mut constants = poseidon_ark.into_iter().flatten();
+        let ark = (0..full_rounds + partial_rounds)
+            .map(|round| {
+                if round < full_rounds / 2 || round >= full_rounds / 2 + partial_rounds {
+                    constants.by_ref().take(width).collect()
+                } else {
+                    let mut row = vec![Fr::zero(); width];
+                    row[0] = constants.next().unwrap();
+                    row
+                }
+            })
+            .collect();
+
+        let external_matrix = match width {
+            // circ(2, 1, ..., 1)
+            3 => (0..width)
+                .map(|i| (0..width).map(|j| Fr::from(1 + (i == j) as u64)).collect())
+                .collect(),
+            4 => [[5, 7, 1, 3], [4, 6, 1, 1], [1, 3, 5, 7], [1, 1, 4, 6]]
+                .iter()
+                .map(|row| row.iter().map(|&x| Fr::from(x as u64)).collect())
+                .collect(),
+            _ => unreachable!(),
+        };
+        let diagonal = match width {
+            3 => vec![Fr::one(), Fr::one(), Fr::from(2u64)],
+            4 => POSEIDON2_INTERNAL_DIAGONAL_4
+                .iter()
+                .map(|x| Fr::from_be_bytes_mod_order(&hex::decode(x).unwrap()))
+                .collect(),
+            _ => unreachable!(),
+        };
+        let internal_matrix = (0..width)
+            .map(|i| {
+                (0..width)
+                    .map(|j| if i == j { Fr::one() + diagonal[i] } else { Fr::one() })
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            width,
+            full_rounds,
+            partial_rounds,
+            ark,
+            external_matrix,
+            internal_matrix,
+        }
+    }
+
+    pub fn rounds(&self) -> usize {
+        self.full_rounds + self.partial_rounds
+    }
+
+    /// Whether `round` applies the S-box to the whole state rather than the first lane only.
+    pub fn is_full_round(&self, round: usize) -> bool {
+        round < self.full_rounds / 2 || round >= self.full_rounds / 2 + self.partial_rounds
+    }
+
+    /// The linear layer applied after `round`.
+    pub fn matrix(&self, round: usize) -> &[Vec<Fr>] {
+        if self.is_full_round(round) {
+            &self.external_matrix
+        } else {
+            &self.internal_matrix
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use ark_bn254::Fr;
+use ark_ff::{One, Zero};
 use plonky2::{
     field::extension::Extendable,
     hash::hash_types::RichField,
@@ -8,79 +10,442 @@ use plonky2::{
 };
 
 use super::arithmetic::FrTarget;
+use super::gate::{affine_with_gate, mul_with_gate};
+use super::native::SpongeMode;
+use super::params::PoseidonParams;
 
-pub const RATE: usize = 2;
+/// Number of capacity lanes; the remaining `width - CAPACITY` lanes form the rate.
 pub const CAPACITY: usize = 1;
-pub const WIDTH: usize = RATE + CAPACITY;
+/// Width used by `PoseidonCircuit::new`, i.e. a 2-to-1 hash.
+pub const DEFAULT_WIDTH: usize = 3;
+
+/// A BN254 permutation circuit together with its builder.
+///
+/// Hashing and the sponge are implemented once on top of `permute`, so `PoseidonCircuit` and
+/// `Poseidon2Circuit` expose the same API and can be swapped, e.g. in benchmarks.
+pub trait PermutationCircuit<F: RichField + Extendable<D>, const D: usize> {
+    fn builder(&mut self) -> &mut CircuitBuilder<F, D>;
+
+    fn width(&self) -> usize;
+
+    /// Applies the permutation to `state` in place.
+    fn permute(&mut self, state: &mut [FrTarget<F, D>]);
+
+    fn rate(&self) -> usize {
+        self.width() - CAPACITY
+    }
+
+    /// Hashes at most `rate()` inputs with a single permutation.
+    ///
+    /// With exactly `rate()` inputs this is circomlib's `poseidon(input)` for `PoseidonCircuit`;
+    /// shorter inputs are zero-padded. Use `sponge_hash` or a `PoseidonSponge` for longer or
+    /// variable-length inputs.
+    fn hash_fr(&mut self, input: &[FrTarget<F, D>]) -> FrTarget<F, D> {
+        assert!(
+            input.len() <= self.rate(),
+            "hash_fr takes at most {} inputs, got {}; use the sponge instead",
+            self.rate(),
+            input.len()
+        );
+        // circomlib layout: the capacity lane comes first, followed by the rate lanes.
+        let mut state = vec![FrTarget::<F, D>::zero(self.builder()); self.width()];
+        state[CAPACITY..CAPACITY + input.len()].copy_from_slice(input);
+        self.permute(&mut state);
+        state[0]
+    }
+
+    /// Starts a new sponge whose capacity lane is initialised with `domain_tag`.
+    fn sponge(&mut self, domain_tag: &Fr) -> PoseidonSponge<F, D>
+    where
+        Self: Sized,
+    {
+        PoseidonSponge::new(domain_tag, self)
+    }
+
+    /// Hashes any number of inputs with a fresh sponge and squeezes a single element.
+    fn sponge_hash(&mut self, input: &[FrTarget<F, D>], domain_tag: &Fr) -> FrTarget<F, D>
+    where
+        Self: Sized,
+    {
+        let mut sponge = self.sponge(domain_tag);
+        sponge.absorb(input, self);
+        sponge.squeeze(1, self)[0]
+    }
+}
 
 pub struct PoseidonCircuit<F: RichField + Extendable<D>, const D: usize> {
     pub builder: CircuitBuilder<F, D>,
+    params: PoseidonParams,
+    gates: bool,
 }
 
 impl<F: RichField + Extendable<D>, const D: usize> PoseidonCircuit<F, D> {
     pub fn new() -> Self {
+        Self::with_width(DEFAULT_WIDTH)
+    }
+
+    /// Creates a circuit for the state width `t = width`, which must be in
+    /// `MIN_WIDTH..=MAX_WIDTH` (2..=17) like circomlib's `poseidon.circom`.
+    pub fn with_width(width: usize) -> Self {
         let config = CircuitConfig::standard_recursion_config();
         let builder = CircuitBuilder::<F, D>::new(config);
-        Self { builder }
+        let params = PoseidonParams::new(width);
+        Self {
+            builder,
+            params,
+            gates: true,
+        }
+    }
+
+    /// Chooses between this crate's gates (the default), i.e. `Bn254MulGate` for the S-box
+    /// multiplications and `Bn254AffineGate` for the linear layers, and generic `FrTarget`
+    /// arithmetic.
+    pub fn with_gates(mut self, enabled: bool) -> Self {
+        self.gates = enabled;
+        self
     }
 
-    pub fn hash_fr(&mut self, input: &[FrTarget<F, D>]) -> FrTarget<F, D> {
-        let mut state = vec![FrTarget::<F, D>::zero(&mut self.builder); WIDTH];
-        for (i, &x) in input.iter().enumerate() {
-            state[i % RATE] = state[i % RATE].add(&x, &mut self.builder);
+    /// Applies round `round` of the permutation to `state` in place: its S-boxes, then its MDS
+    /// layer plus the next round's constants. `permute` adds the first round's constants before
+    /// round 0.
+    pub fn round(&mut self, state: &mut [FrTarget<F, D>], round: usize) {
+        assert_eq!(state.len(), self.params.width);
+        let params = &self.params;
+        let builder = &mut self.builder;
+        let mul = mul_fn(self.gates);
+        let affine = affine_fn(self.gates);
+        // SBox
+        if params.is_full_round(round) {
+            for lane in state.iter_mut() {
+                *lane = sbox(lane, mul, builder);
+            }
+        } else {
+            state[0] = sbox(&state[0], mul, builder);
         }
-        self.permute(&mut state);
-        state[0]
+        // Mix layer, plus the next round's constants
+        mix(&params.mds, state, params.ark.get(round + 1), affine, builder);
     }
+}
 
+impl<F: RichField + Extendable<D>, const D: usize> Default for PoseidonCircuit<F, D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> PermutationCircuit<F, D>
+    for PoseidonCircuit<F, D>
+{
+    fn builder(&mut self) -> &mut CircuitBuilder<F, D> {
+        &mut self.builder
+    }
+
+    fn width(&self) -> usize {
+        self.params.width
+    }
+
+    /// Applies the BN254 Poseidon permutation to `state` in place.
+    ///
+    /// Each round's constants are folded into the previous round's linear layer, so a round
+    /// costs one multiplication per S-box step plus one reduction per MDS row. Unless disabled
+    /// with `with_gates`, these are a `Bn254MulGate` and a `Bn254AffineGate` row respectively,
+    /// the latter for widths up to `MAX_AFFINE_TERMS`, each checked by a `Bn254CheckGate` row
+    /// and limb lookups.
     fn permute(&mut self, state: &mut [FrTarget<F, D>]) {
-        let rounds = 8;
-        for _ in 0..rounds {
-            // Add round constants
-            for i in 0..WIDTH {
-                state[i] = state[i].add(&FrTarget::<F, D>::one(&mut self.builder), &mut self.builder);
-            }
-            // SBox
-            for i in 0..WIDTH {
-                state[i] = self.sbox(&state[i]);
+        assert_eq!(state.len(), self.params.width);
+        for (lane, c) in state.iter_mut().zip(&self.params.ark[0]) {
+            *lane = lane.add_const(c, &mut self.builder);
+        }
+        for round in 0..self.params.rounds() {
+            self.round(state, round);
+        }
+    }
+}
+
+/// Duplex sponge over the permutation of a `PermutationCircuit`, with one capacity lane and
+/// `rate()` rate lanes.
+///
+/// The capacity lane starts out as a domain tag. Absorbed elements are added into the rate lanes
+/// and the state is permuted whenever a block of `rate()` elements is full. Before the first
+/// squeeze the pending block is padded with a one followed by zeros, so inputs of different
+/// lengths never collide. Outputs are read from the rate lanes, permuting again whenever they run
+/// out. Absorbing after squeezing starts a new block.
+///
+/// Unlike circomlib's `poseidon`, which outputs the capacity lane of a single permutation, this
+/// never exposes the capacity lane; `PoseidonSpongeNative` computes the same values natively
+/// for `PoseidonCircuit`.
+#[derive(Clone, Debug)]
+pub struct PoseidonSponge<F: RichField + Extendable<D>, const D: usize> {
+    state: Vec<FrTarget<F, D>>,
+    mode: SpongeMode,
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> PoseidonSponge<F, D> {
+    pub fn new(domain_tag: &Fr, circuit: &mut impl PermutationCircuit<F, D>) -> Self {
+        let mut state = vec![FrTarget::zero(circuit.builder()); circuit.width()];
+        state[0] = FrTarget::constant(domain_tag, circuit.builder());
+        Self {
+            state,
+            mode: SpongeMode::Absorbing(0),
+        }
+    }
+
+    pub fn absorb(
+        &mut self,
+        inputs: &[FrTarget<F, D>],
+        circuit: &mut impl PermutationCircuit<F, D>,
+    ) {
+        for x in inputs {
+            let pos = match self.mode {
+                SpongeMode::Absorbing(pos) => pos,
+                SpongeMode::Squeezing(_) => 0,
+            };
+            let lane = CAPACITY + pos;
+            self.state[lane] = self.state[lane].add(x, circuit.builder());
+            if pos + 1 == circuit.rate() {
+                circuit.permute(&mut self.state);
+                self.mode = SpongeMode::Absorbing(0);
+            } else {
+                self.mode = SpongeMode::Absorbing(pos + 1);
             }
-            // Mix layer
-            let mut new_state = vec![FrTarget::<F, D>::zero(&mut self.builder); WIDTH];
-            for i in 0..WIDTH {
-                for j in 0..WIDTH {
-                    new_state[i] = new_state[i].add(&state[j], &mut self.builder);
+        }
+    }
+
+    pub fn squeeze(
+        &mut self,
+        num_outputs: usize,
+        circuit: &mut impl PermutationCircuit<F, D>,
+    ) -> Vec<FrTarget<F, D>> {
+        let mut outputs = Vec::with_capacity(num_outputs);
+        for _ in 0..num_outputs {
+            let pos = match self.mode {
+                SpongeMode::Absorbing(pos) => {
+                    // 10* padding of the pending block.
+                    let lane = CAPACITY + pos;
+                    self.state[lane] = self.state[lane].add_const(&Fr::one(), circuit.builder());
+                    circuit.permute(&mut self.state);
+                    0
                 }
-            }
-            state.copy_from_slice(&new_state);
+                SpongeMode::Squeezing(pos) if pos == circuit.rate() => {
+                    circuit.permute(&mut self.state);
+                    0
+                }
+                SpongeMode::Squeezing(pos) => pos,
+            };
+            outputs.push(self.state[CAPACITY + pos]);
+            self.mode = SpongeMode::Squeezing(pos + 1);
         }
+        outputs
+    }
+}
+
+pub(crate) type MulFn<F, const D: usize> =
+    fn(&FrTarget<F, D>, &FrTarget<F, D>, &mut CircuitBuilder<F, D>) -> FrTarget<F, D>;
+
+pub(crate) fn mul_fn<F: RichField + Extendable<D>, const D: usize>(gates: bool) -> MulFn<F, D> {
+    if gates {
+        mul_with_gate
+    } else {
+        FrTarget::mul
     }
+}
 
-    fn sbox(&mut self, x: &FrTarget<F, D>) -> FrTarget<F, D> {
-        let x2 = x.mul(x, &mut self.builder);
-        let x4 = x2.mul(&x2, &mut self.builder);
-        x4.mul(x, &mut self.builder)
+pub(crate) type AffineFn<F, const D: usize> =
+    fn(&[(Fr, FrTarget<F, D>)], &Fr, &mut CircuitBuilder<F, D>) -> FrTarget<F, D>;
+
+pub(crate) fn affine_fn<F: RichField + Extendable<D>, const D: usize>(
+    gates: bool,
+) -> AffineFn<F, D> {
+    if gates {
+        affine_with_gate
+    } else {
+        FrTarget::linear_combination
     }
 }
 
+/// Replaces `state` with `matrix · state + constants`, using one reduction per row.
+pub(crate) fn mix<F: RichField + Extendable<D>, const D: usize>(
+    matrix: &[Vec<Fr>],
+    state: &mut [FrTarget<F, D>],
+    constants: Option<&Vec<Fr>>,
+    affine: AffineFn<F, D>,
+    builder: &mut CircuitBuilder<F, D>,
+) {
+    let new_state = matrix
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let terms = row.iter().copied().zip(state.iter().copied()).collect::<Vec<_>>();
+            let constant = constants.map_or(Fr::zero(), |c| c[i]);
+            affine(&terms, &constant, builder)
+        })
+        .collect::<Vec<_>>();
+    state.copy_from_slice(&new_state);
+}
+
+pub(crate) fn sbox<F: RichField + Extendable<D>, const D: usize>(
+    x: &FrTarget<F, D>,
+    mul: MulFn<F, D>,
+    builder: &mut CircuitBuilder<F, D>,
+) -> FrTarget<F, D> {
+    let x2 = mul(x, x, builder);
+    let x4 = mul(&x2, &x2, builder);
+    mul(&x4, x, builder)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ark_bn254::Fr;
+    use std::str::FromStr;
     use plonky2::{
-        field::types::Field,
+        iop::{generator::generate_partial_witness, witness::PartialWitness},
         plonk::config::{GenericConfig, PoseidonGoldilocksConfig},
     };
+    use crate::arithmetic::num_rows;
+    use crate::native::PoseidonSpongeNative;
+    use crate::test_vectors::poseidon_vectors;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    // circomlibjs: poseidon([1, 2])
+    const HASH_1_2: &str = "7853200120776062878684798364095072458815029376092732009249414926327459813530";
 
     #[test]
     fn test_poseidon_hash() {
-        const D: usize = 2;
-        type C = PoseidonGoldilocksConfig;
-        type F = <C as GenericConfig<D>>::F;
-
         let mut circuit = PoseidonCircuit::<F, D>::new();
-        let input = vec![FrTarget::<F, D>::constant(&Fr::from(1u64), &mut circuit.builder)];
+        let input = vec![
+            FrTarget::<F, D>::constant(&Fr::from(1u64), &mut circuit.builder),
+            FrTarget::<F, D>::constant(&Fr::from(2u64), &mut circuit.builder),
+        ];
         let output = circuit.hash_fr(&input);
-        let expected = Fr::from(1u64);
+        let expected = Fr::from_str(HASH_1_2).unwrap();
         assert_eq!(output.to_native(&circuit.builder), expected);
     }
+
+    #[test]
+    fn test_poseidon_hash_proof() {
+        let mut circuit = PoseidonCircuit::<F, D>::new();
+        let input = vec![FrTarget::new(&mut circuit.builder), FrTarget::new(&mut circuit.builder)];
+        let output = circuit.hash_fr(&input);
+        let expected = FrTarget::constant(&Fr::from_str(HASH_1_2).unwrap(), &mut circuit.builder);
+        for (a, b) in output.limbs.into_iter().zip(expected.limbs) {
+            circuit.builder.connect(a, b);
+        }
+
+        let data = circuit.builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        for (x, value) in input.iter().zip([1u64, 2]) {
+            x.set_witness(&mut pw, &Fr::from(value)).unwrap();
+        }
+        let proof = data.prove(pw).unwrap();
+        data.verify(proof).unwrap();
+    }
+
+    #[test]
+    fn test_poseidon_vectors() {
+        // Witness checks for a few widths; every width is covered with constants below.
+        for width in [2, 3, 5] {
+            let mut circuit = PoseidonCircuit::<F, D>::with_width(width);
+            let input = (0..circuit.rate())
+                .map(|_| FrTarget::new(&mut circuit.builder))
+                .collect::<Vec<_>>();
+            let output = circuit.hash_fr(&input);
+            let data = circuit.builder.build::<C>();
+
+            for vector in poseidon_vectors().iter().filter(|v| v.inputs.len() == width - 1) {
+                let mut pw = PartialWitness::new();
+                for (x, value) in input.iter().zip(&vector.inputs) {
+                    x.set_witness(&mut pw, value).unwrap();
+                }
+                let witness =
+                    generate_partial_witness(pw, &data.prover_only, &data.common).unwrap();
+                assert_eq!(output.get_witness(&witness), vector.output);
+            }
+        }
+    }
+
+    #[test]
+    fn test_poseidon_vectors_all_widths() {
+        for vector in poseidon_vectors() {
+            let mut circuit = PoseidonCircuit::<F, D>::with_width(vector.inputs.len() + 1);
+            let input = vector
+                .inputs
+                .iter()
+                .map(|x| FrTarget::constant(x, &mut circuit.builder))
+                .collect::<Vec<_>>();
+            let output = circuit.hash_fr(&input);
+            let output = output.to_native(&circuit.builder);
+            assert_eq!(output, vector.output, "inputs: {:?}", vector.inputs);
+        }
+    }
+
+    #[test]
+    fn test_sponge_matches_native() {
+        let domain_tag = Fr::from(7u64);
+        let mut circuit = PoseidonCircuit::<F, D>::new();
+        let mut cases = Vec::new();
+        for len in 0..=5u64 {
+            let values = (1..=len).map(Fr::from).collect::<Vec<_>>();
+            let input = values
+                .iter()
+                .map(|_| FrTarget::new(&mut circuit.builder))
+                .collect::<Vec<_>>();
+            let mut sponge = circuit.sponge(&domain_tag);
+            sponge.absorb(&input, &mut circuit);
+            let output = sponge.squeeze(3, &mut circuit);
+
+            let mut native = PoseidonSpongeNative::new(DEFAULT_WIDTH, &domain_tag);
+            native.absorb(&values);
+            cases.push((input, values, output, native.squeeze(3)));
+        }
+
+        let data = circuit.builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        for (input, values, _, _) in &cases {
+            for (x, value) in input.iter().zip(values) {
+                x.set_witness(&mut pw, value).unwrap();
+            }
+        }
+        let witness = generate_partial_witness(pw, &data.prover_only, &data.common).unwrap();
+        for (_, _, output, expected) in &cases {
+            let output = output.iter().map(|y| y.get_witness(&witness)).collect::<Vec<_>>();
+            assert_eq!(&output, expected);
+        }
+    }
+
+    #[test]
+    fn test_sponge_hash_constants() {
+        let mut circuit = PoseidonCircuit::<F, D>::new();
+        let values = [1u64, 2, 3].map(Fr::from);
+        let input = values.map(|x| FrTarget::constant(&x, &mut circuit.builder));
+        let output = circuit.sponge_hash(&input, &Fr::zero());
+        let mut native = PoseidonSpongeNative::new(DEFAULT_WIDTH, &Fr::zero());
+        native.absorb(&values);
+        assert_eq!(output.to_native(&circuit.builder), native.squeeze(1)[0]);
+    }
+
+    #[test]
+    fn test_gates_match_generic() {
+        let vector = poseidon_vectors().into_iter().find(|v| v.inputs.len() == 2).unwrap();
+        let mut rows = Vec::new();
+        for gates in [true, false] {
+            let mut circuit = PoseidonCircuit::<F, D>::new().with_gates(gates);
+            let input = (0..circuit.rate())
+                .map(|_| FrTarget::new(&mut circuit.builder))
+                .collect::<Vec<_>>();
+            let output = circuit.hash_fr(&input);
+            rows.push(num_rows(&circuit.builder));
+            let data = circuit.builder.build::<C>();
+
+            let mut pw = PartialWitness::new();
+            for (x, value) in input.iter().zip(&vector.inputs) {
+                x.set_witness(&mut pw, value).unwrap();
+            }
+            let witness = generate_partial_witness(pw, &data.prover_only, &data.common).unwrap();
+            assert_eq!(output.get_witness(&witness), vector.output);
+        }
+        assert!(rows[0] < rows[1], "row counts: {rows:?}");
+    }
 }
@@ -0,0 +1,142 @@
+use plonky2::{
+    field::extension::Extendable,
+    hash::hash_types::RichField,
+    plonk::{circuit_builder::CircuitBuilder, circuit_data::CircuitConfig},
+};
+
+use super::arithmetic::FrTarget;
+use super::params::Poseidon2Params;
+use super::poseidon::{affine_fn, mix, mul_fn, sbox, PermutationCircuit, DEFAULT_WIDTH};
+
+/// BN254 Poseidon2 over `FrTarget`, with the same hashing API as `PoseidonCircuit` through
+/// `PermutationCircuit`.
+pub struct Poseidon2Circuit<F: RichField + Extendable<D>, const D: usize> {
+    pub builder: CircuitBuilder<F, D>,
+    params: Poseidon2Params,
+    gates: bool,
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> Poseidon2Circuit<F, D> {
+    pub fn new() -> Self {
+        Self::with_width(DEFAULT_WIDTH)
+    }
+
+    /// Creates a circuit for the state width `t = width`, which must be in
+    /// `POSEIDON2_MIN_WIDTH..=POSEIDON2_MAX_WIDTH` (3..=4).
+    pub fn with_width(width: usize) -> Self {
+        let config = CircuitConfig::standard_recursion_config();
+        let builder = CircuitBuilder::<F, D>::new(config);
+        let params = Poseidon2Params::new(width);
+        Self {
+            builder,
+            params,
+            gates: true,
+        }
+    }
+
+    /// Chooses between this crate's gates (the default) and generic `FrTarget` arithmetic, as
+    /// `PoseidonCircuit::with_gates` does.
+    pub fn with_gates(mut self, enabled: bool) -> Self {
+        self.gates = enabled;
+        self
+    }
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> Default for Poseidon2Circuit<F, D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> PermutationCircuit<F, D>
+    for Poseidon2Circuit<F, D>
+{
+    fn builder(&mut self) -> &mut CircuitBuilder<F, D> {
+        &mut self.builder
+    }
+
+    fn width(&self) -> usize {
+        self.params.width
+    }
+
+    /// Applies the BN254 Poseidon2 permutation to `state` in place.
+    ///
+    /// As in `PoseidonCircuit`, round constants are folded into the preceding linear layer.
+    /// The linear layers have small coefficients, so their reductions are cheaper than
+    /// Poseidon's dense MDS rows.
+    fn permute(&mut self, state: &mut [FrTarget<F, D>]) {
+        assert_eq!(state.len(), self.params.width);
+        let params = &self.params;
+        let builder = &mut self.builder;
+        let mul = mul_fn(self.gates);
+        let affine = affine_fn(self.gates);
+
+        // Initial external layer, plus the first round's constants
+        mix(&params.external_matrix, state, params.ark.first(), affine, builder);
+        for round in 0..params.rounds() {
+            // SBox
+            if params.is_full_round(round) {
+                for lane in state.iter_mut() {
+                    *lane = sbox(lane, mul, builder);
+                }
+            } else {
+                state[0] = sbox(&state[0], mul, builder);
+            }
+            // External or internal layer, plus the next round's constants
+            mix(params.matrix(round), state, params.ark.get(round + 1), affine, builder);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ark_bn254::Fr;
+    use plonky2::{
+        iop::{generator::generate_partial_witness, witness::PartialWitness},
+        plonk::config::{GenericConfig, PoseidonGoldilocksConfig},
+    };
+    use crate::native::Poseidon2Native;
+    use crate::test_vectors::poseidon2_vectors;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn test_poseidon2_vectors() {
+        for vector in poseidon2_vectors() {
+            let mut circuit = Poseidon2Circuit::<F, D>::with_width(vector.input.len());
+            let mut state = vector
+                .input
+                .iter()
+                .map(|x| FrTarget::constant(x, &mut circuit.builder))
+                .collect::<Vec<_>>();
+            circuit.permute(&mut state);
+            let output = state
+                .iter()
+                .map(|x| x.to_native(&circuit.builder))
+                .collect::<Vec<_>>();
+            assert_eq!(output, vector.output);
+        }
+    }
+
+    #[test]
+    fn test_poseidon2_hash_matches_native() {
+        let mut circuit = Poseidon2Circuit::<F, D>::new();
+        let input = (0..circuit.rate())
+            .map(|_| FrTarget::new(&mut circuit.builder))
+            .collect::<Vec<_>>();
+        let output = circuit.hash_fr(&input);
+        let data = circuit.builder.build::<C>();
+
+        let values = [Fr::from(1u64), Fr::from(2u64)];
+        let mut pw = PartialWitness::new();
+        for (x, value) in input.iter().zip(&values) {
+            x.set_witness(&mut pw, value).unwrap();
+        }
+        let witness = generate_partial_witness(pw, &data.prover_only, &data.common).unwrap();
+        let expected = Poseidon2Native::new(DEFAULT_WIDTH).hash(&values);
+        assert_eq!(output.get_witness(&witness), expected);
+    }
+}
@@ -0,0 +1,265 @@
+//! Gate and witness generator serializers for circuits built with this crate.
+//!
+//! plonky2's default serializers only know about plonky2's own gates and generators, so
+//! `CircuitData::to_bytes` fails on a circuit containing `Bn254MulGate`, `Bn254AffineGate`,
+//! `Bn254CheckGate` or any `FrTarget` arithmetic. The serializers here write a one-byte tag in front of every entry: `0` hands
+//! the entry to plonky2's default serializer, anything else is one of this crate's types.
+
+use plonky2::{
+    field::extension::Extendable,
+    gates::gate::{Gate, GateRef},
+    hash::hash_types::RichField,
+    iop::generator::{SimpleGenerator, WitnessGeneratorRef},
+    plonk::{
+        circuit_data::CommonCircuitData,
+        config::{AlgebraicHasher, GenericConfig},
+    },
+    util::serialization::{
+        Buffer, DefaultGateSerializer, DefaultGeneratorSerializer, GateSerializer, IoError,
+        IoResult, Read, WitnessGeneratorSerializer, Write,
+    },
+};
+use std::marker::PhantomData;
+
+use super::arithmetic::{FrDivGenerator, FrReduceGenerator};
+use super::gate::{
+    Bn254AffineGate, Bn254AffineGenerator, Bn254CheckGate, Bn254CheckGenerator, Bn254MulGate,
+    Bn254MulGenerator,
+};
+
+/// Tag of entries delegated to plonky2's default serializers.
+const DEFAULT_TAG: u8 = 0;
+
+/// Serializes every gate used by this crate: plonky2's default gates plus `Bn254MulGate`,
+/// `Bn254AffineGate` and `Bn254CheckGate`.
+#[derive(Debug, Default)]
+pub struct Bn254GateSerializer;
+
+impl Bn254GateSerializer {
+    const BN254_MUL_GATE: u8 = 1;
+    const BN254_AFFINE_GATE: u8 = 2;
+    const BN254_CHECK_GATE: u8 = 3;
+}
+
+impl<F: RichField + Extendable<D>, const D: usize> GateSerializer<F, D> for Bn254GateSerializer {
+    fn read_gate(
+        &self,
+        buf: &mut Buffer,
+        common_data: &CommonCircuitData<F, D>,
+    ) -> IoResult<GateRef<F, D>> {
+        match buf.read_u8()? {
+            DEFAULT_TAG => DefaultGateSerializer.read_gate(buf, common_data),
+            Self::BN254_MUL_GATE => Ok(GateRef::new(
+                <Bn254MulGate as Gate<F, D>>::deserialize(buf, common_data)?,
+            )),
+            Self::BN254_AFFINE_GATE => Ok(GateRef::new(
+                <Bn254AffineGate as Gate<F, D>>::deserialize(buf, common_data)?,
+            )),
+            Self::BN254_CHECK_GATE => Ok(GateRef::new(
+                <Bn254CheckGate as Gate<F, D>>::deserialize(buf, common_data)?,
+            )),
+            _ => Err(IoError),
+        }
+    }
+
+    fn write_gate(
+        &self,
+        buf: &mut Vec<u8>,
+        gate: &GateRef<F, D>,
+        common_data: &CommonCircuitData<F, D>,
+    ) -> IoResult<()> {
+        let id = gate.0.id();
+        if id == Gate::<F, D>::id(&Bn254MulGate) {
+            buf.write_u8(Self::BN254_MUL_GATE)?;
+            gate.0.serialize(buf, common_data)
+        } else if id.starts_with("Bn254AffineGate {") {
+            buf.write_u8(Self::BN254_AFFINE_GATE)?;
+            gate.0.serialize(buf, common_data)
+        } else if id.starts_with("Bn254CheckGate {") {
+            buf.write_u8(Self::BN254_CHECK_GATE)?;
+            gate.0.serialize(buf, common_data)
+        } else {
+            buf.write_u8(DEFAULT_TAG)?;
+            DefaultGateSerializer.write_gate(buf, gate, common_data)
+        }
+    }
+}
+
+/// Serializes every witness generator used by this crate: plonky2's default generators plus
+/// `FrReduceGenerator`, `FrDivGenerator`, `Bn254MulGenerator`, `Bn254AffineGenerator` and
+/// `Bn254CheckGenerator`.
+///
+/// Generic over the circuit config because plonky2's default generators include the dummy
+/// proof generator used by recursion.
+#[derive(Debug)]
+pub struct Bn254GeneratorSerializer<C: GenericConfig<D>, const D: usize> {
+    _phantom: PhantomData<C>,
+}
+
+impl<C: GenericConfig<D>, const D: usize> Bn254GeneratorSerializer<C, D> {
+    const FR_REDUCE_GENERATOR: u8 = 1;
+    const FR_DIV_GENERATOR: u8 = 2;
+    const BN254_MUL_GENERATOR: u8 = 3;
+    const BN254_AFFINE_GENERATOR: u8 = 4;
+    const BN254_CHECK_GENERATOR: u8 = 5;
+
+    pub fn new() -> Self {
+        Self {
+            _phantom: PhantomData,
+        }
+    }
+
+    fn default_serializer() -> DefaultGeneratorSerializer<C, D> {
+        DefaultGeneratorSerializer {
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<C: GenericConfig<D>, const D: usize> Default for Bn254GeneratorSerializer<C, D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F, C, const D: usize> WitnessGeneratorSerializer<F, D> for Bn254GeneratorSerializer<C, D>
+where
+    F: RichField + Extendable<D>,
+    C: GenericConfig<D, F = F> + 'static,
+    C::Hasher: AlgebraicHasher<F>,
+{
+    fn read_generator(
+        &self,
+        buf: &mut Buffer,
+        common_data: &CommonCircuitData<F, D>,
+    ) -> IoResult<WitnessGeneratorRef<F, D>> {
+        match buf.read_u8()? {
+            DEFAULT_TAG => Self::default_serializer().read_generator(buf, common_data),
+            Self::FR_REDUCE_GENERATOR => Ok(WitnessGeneratorRef::new(
+                FrReduceGenerator::<F, D>::deserialize(buf, common_data)?.adapter(),
+            )),
+            Self::FR_DIV_GENERATOR => Ok(WitnessGeneratorRef::new(
+                FrDivGenerator::<F, D>::deserialize(buf, common_data)?.adapter(),
+            )),
+            Self::BN254_MUL_GENERATOR => Ok(WitnessGeneratorRef::new(
+                Bn254MulGenerator::<F, D>::deserialize(buf, common_data)?.adapter(),
+            )),
+            Self::BN254_AFFINE_GENERATOR => Ok(WitnessGeneratorRef::new(
+                Bn254AffineGenerator::<F, D>::deserialize(buf, common_data)?.adapter(),
+            )),
+            Self::BN254_CHECK_GENERATOR => Ok(WitnessGeneratorRef::new(
+                Bn254CheckGenerator::<F, D>::deserialize(buf, common_data)?.adapter(),
+            )),
+            _ => Err(IoError),
+        }
+    }
+
+    fn write_generator(
+        &self,
+        buf: &mut Vec<u8>,
+        generator: &WitnessGeneratorRef<F, D>,
+        common_data: &CommonCircuitData<F, D>,
+    ) -> IoResult<()> {
+        let tag = match generator.0.id().as_str() {
+            "FrReduceGenerator" => Self::FR_REDUCE_GENERATOR,
+            "FrDivGenerator" => Self::FR_DIV_GENERATOR,
+            "Bn254MulGenerator" => Self::BN254_MUL_GENERATOR,
+            "Bn254AffineGenerator" => Self::BN254_AFFINE_GENERATOR,
+            "Bn254CheckGenerator" => Self::BN254_CHECK_GENERATOR,
+            _ => {
+                buf.write_u8(DEFAULT_TAG)?;
+                return Self::default_serializer().write_generator(buf, generator, common_data);
+            }
+        };
+        buf.write_u8(tag)?;
+        generator.0.serialize(buf, common_data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arithmetic::{
+        enable_limb_lookups, public_inputs_to_fr, register_public_fr, FrTarget,
+    };
+    use crate::native::PoseidonNative;
+    use crate::poseidon::{PermutationCircuit, PoseidonCircuit};
+    use ark_bn254::Fr;
+    use plonky2::{
+        iop::witness::PartialWitness,
+        plonk::{
+            circuit_data::{CircuitData, ProverCircuitData, VerifierCircuitData},
+            config::PoseidonGoldilocksConfig,
+        },
+    };
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    /// Builds `poseidon(x, y) / x` with every gate and generator of the crate, round-trips the
+    /// prover and verifier data through bytes and proves and verifies with the reloaded data.
+    fn test_round_trip(lookups: bool) {
+        let mut circuit = PoseidonCircuit::<F, D>::new();
+        if lookups {
+            enable_limb_lookups(&mut circuit.builder);
+        }
+        let input = [(); 2].map(|_| FrTarget::new(&mut circuit.builder));
+        let hash = circuit.hash_fr(&input);
+        let output = hash.div(&input[0], &mut circuit.builder);
+        register_public_fr(&mut circuit.builder, &output);
+        let data = circuit.builder.build::<C>();
+
+        let gate_serializer = Bn254GateSerializer;
+        let generator_serializer = Bn254GeneratorSerializer::<C, D>::new();
+        let bytes = data.to_bytes(&gate_serializer, &generator_serializer).unwrap();
+        let data =
+            CircuitData::<F, C, D>::from_bytes(&bytes, &gate_serializer, &generator_serializer)
+                .unwrap();
+        let verifier_bytes = data.verifier_data().to_bytes(&gate_serializer).unwrap();
+        let prover_bytes = data
+            .prover_data()
+            .to_bytes(&gate_serializer, &generator_serializer)
+            .unwrap();
+
+        let prover_data = ProverCircuitData::<F, C, D>::from_bytes(
+            &prover_bytes,
+            &gate_serializer,
+            &generator_serializer,
+        )
+        .unwrap();
+        let verifier_data =
+            VerifierCircuitData::<F, C, D>::from_bytes(verifier_bytes, &gate_serializer).unwrap();
+
+        let values = [Fr::from(3u64), Fr::from(4u64)];
+        let mut pw = PartialWitness::new();
+        for (x, value) in input.iter().zip(&values) {
+            x.set_witness(&mut pw, value).unwrap();
+        }
+        let proof = prover_data.prove(pw).unwrap();
+        let expected = PoseidonNative::new(3).hash(&values) / values[0];
+        assert_eq!(public_inputs_to_fr(&proof.public_inputs).unwrap(), [expected]);
+        verifier_data.verify(proof).unwrap();
+    }
+
+    #[test]
+    fn test_circuit_data_round_trip() {
+        test_round_trip(false);
+    }
+
+    #[test]
+    fn test_circuit_data_round_trip_with_lookups() {
+        test_round_trip(true);
+    }
+
+    #[test]
+    fn test_unknown_tag_rejected() {
+        let common = PoseidonCircuit::<F, D>::new().builder.build::<C>().common;
+        let mut buf = Buffer::new(&[0xff]);
+        let gate = GateSerializer::<F, D>::read_gate(&Bn254GateSerializer, &mut buf, &common);
+        assert!(gate.is_err());
+        let mut buf = Buffer::new(&[0xff]);
+        let generator_serializer = Bn254GeneratorSerializer::<C, D>::new();
+        assert!(generator_serializer.read_generator(&mut buf, &common).is_err());
+    }
+}
@@ -0,0 +1,47 @@
+//! Test vectors shared by the native and in-circuit tests: circomlibjs `poseidon` hashes and
+//! reference Poseidon2 permutations.
+use std::str::FromStr;
+use ark_bn254::Fr;
+use serde_json::Value;
+
+pub struct PoseidonVector {
+    pub inputs: Vec<Fr>,
+    pub output: Fr,
+}
+
+pub fn poseidon_vectors() -> Vec<PoseidonVector> {
+    let json: Value = serde_json::from_str(include_str!("../vectors/poseidon.json")).unwrap();
+    json["vectors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|vector| PoseidonVector {
+            inputs: vector["inputs"].as_array().unwrap().iter().map(parse_fr).collect(),
+            output: parse_fr(&vector["output"]),
+        })
+        .collect()
+}
+
+/// A Poseidon2 permutation known-answer test.
+pub struct Poseidon2Vector {
+    pub input: Vec<Fr>,
+    pub output: Vec<Fr>,
+}
+
+pub fn poseidon2_vectors() -> Vec<Poseidon2Vector> {
+    let json: Value = serde_json::from_str(include_str!("../vectors/poseidon2.json")).unwrap();
+    let parse_state = |state: &Value| state.as_array().unwrap().iter().map(parse_fr).collect();
+    json["vectors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|vector| Poseidon2Vector {
+            input: parse_state(&vector["input"]),
+            output: parse_state(&vector["output"]),
+        })
+        .collect()
+}
+
+fn parse_fr(value: &Value) -> Fr {
+    Fr::from_str(value.as_str().unwrap()).unwrap()
+}
@@ -0,0 +1,329 @@
+//! Recursive aggregation of many proofs of one circuit, e.g. trie openings, into a single proof.
+//!
+//! Proofs are verified `fan_in` at a time by an aggregation circuit, whose proofs are aggregated
+//! again by the next level until one proof remains. Every aggregation circuit exposes as public
+//! inputs the number of leaf proofs below it followed by `poseidon(public inputs of its
+//! children)`, so the final proof commits to the number of leaf proofs and to the public inputs
+//! of each of them, in order; `commitment` recomputes them natively.
+//!
+//! A group of fewer than `fan_in` proofs is padded with copies of its last proof. Each child has
+//! a private flag telling whether it is real: flags must be a run of ones followed by zeros, the
+//! public inputs of padding children are replaced by zeros before hashing and only real children
+//! are counted.
+
+use anyhow::{Context, ensure};
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::hash::hash_types::{HashOut, RichField};
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::target::BoolTarget;
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{
+    CircuitConfig, CircuitData, CommonCircuitData, VerifierCircuitData, VerifierOnlyCircuitData,
+};
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig, Hasher};
+use plonky2::plonk::proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget};
+use rayon::prelude::*;
+
+const D: usize = 2;
+type F = GoldilocksField;
+
+/// Settings of an `Aggregator`.
+#[derive(Clone, Debug)]
+pub struct AggregationConfig {
+    /// Circuit config of the aggregation circuits.
+    pub circuit_config: CircuitConfig,
+    /// Number of proofs verified by each aggregation proof, at least 2.
+    pub fan_in: usize,
+    /// Whether the proofs of a level are proven in parallel, on rayon's thread pool.
+    pub parallel: bool,
+}
+
+impl Default for AggregationConfig {
+    fn default() -> Self {
+        Self {
+            circuit_config: CircuitConfig::standard_recursion_config(),
+            fan_in: 2,
+            parallel: true,
+        }
+    }
+}
+
+/// The aggregation circuit of one level of the tree.
+struct Level<C: GenericConfig<D, F = F>> {
+    data: CircuitData<F, C, D>,
+    children: Vec<ProofWithPublicInputsTarget<D>>,
+    /// Whether each child is a real proof rather than padding.
+    real: Vec<BoolTarget>,
+}
+
+/// The root of an aggregation tree.
+pub struct AggregatedProof<C: GenericConfig<D, F = F>> {
+    pub proof: ProofWithPublicInputs<F, C, D>,
+    /// Number of aggregation levels, i.e. of the circuit that proved `proof`.
+    pub depth: usize,
+    /// Number of leaf proofs, before padding, as committed by the first public input.
+    pub num_proofs: usize,
+}
+
+/// Aggregates proofs of a fixed leaf circuit. The circuit of each level is built the first time
+/// a tree is that deep, and reused by later calls.
+pub struct Aggregator<C: GenericConfig<D, F = F>> {
+    config: AggregationConfig,
+    leaf: VerifierCircuitData<F, C, D>,
+    levels: Vec<Level<C>>,
+}
+
+impl<C> Aggregator<C>
+where
+    C: GenericConfig<D, F = F>,
+    C::Hasher: AlgebraicHasher<F>,
+{
+    pub fn new(leaf: VerifierCircuitData<F, C, D>, config: AggregationConfig) -> Self {
+        assert!(config.fan_in >= 2, "fan-in must be at least 2, got {}", config.fan_in);
+        Self {
+            config,
+            leaf,
+            levels: Vec::new(),
+        }
+    }
+
+    /// The aggregation circuit at `depth`, counting from 1, if it has been built.
+    pub fn circuit(&self, depth: usize) -> Option<&CircuitData<F, C, D>> {
+        self.levels.get(depth.checked_sub(1)?).map(|level| &level.data)
+    }
+
+    /// Aggregates `proofs` of the leaf circuit into one proof, after checking each of them.
+    pub fn aggregate(
+        &mut self,
+        proofs: &[ProofWithPublicInputs<F, C, D>],
+    ) -> anyhow::Result<AggregatedProof<C>> {
+        ensure!(!proofs.is_empty(), "no proofs to aggregate");
+        for (i, proof) in proofs.iter().enumerate() {
+            self.leaf
+                .verify(proof.clone())
+                .with_context(|| format!("proof {i} does not verify"))?;
+        }
+
+        let mut layer = proofs.to_vec();
+        let mut depth = 0;
+        loop {
+            if self.levels.len() == depth {
+                let level = match self.levels.last() {
+                    None => self.build_level(&self.leaf.common, &self.leaf.verifier_only),
+                    Some(inner) => self.build_level(&inner.data.common, &inner.data.verifier_only),
+                };
+                self.levels.push(level);
+            }
+            layer = self
+                .prove_level(&self.levels[depth], &layer)
+                .with_context(|| format!("proving aggregation level {}", depth + 1))?;
+            depth += 1;
+            if layer.len() == 1 {
+                break;
+            }
+        }
+        Ok(AggregatedProof {
+            proof: layer.remove(0),
+            depth,
+            num_proofs: proofs.len(),
+        })
+    }
+
+    /// Verifies `aggregated` with the circuit of its level.
+    pub fn verify(&self, aggregated: &AggregatedProof<C>) -> anyhow::Result<()> {
+        let data = self
+            .circuit(aggregated.depth)
+            .with_context(|| format!("no aggregation circuit of depth {}", aggregated.depth))?;
+        data.verify(aggregated.proof.clone())
+    }
+
+    fn build_level(
+        &self,
+        inner_common: &CommonCircuitData<F, D>,
+        inner_verifier_only: &VerifierOnlyCircuitData<C, D>,
+    ) -> Level<C> {
+        let mut builder = CircuitBuilder::<F, D>::new(self.config.circuit_config.clone());
+        let verifier_target = builder.constant_verifier_data(inner_verifier_only);
+        let children = (0..self.config.fan_in)
+            .map(|_| {
+                let child = builder.add_virtual_proof_with_pis(inner_common);
+                builder.verify_proof::<C>(&child, &verifier_target, inner_common);
+                child
+            })
+            .collect::<Vec<_>>();
+        let real = (0..self.config.fan_in)
+            .map(|_| builder.add_virtual_bool_target_safe())
+            .collect::<Vec<_>>();
+        // The first child is real, and a real child never follows a padding one.
+        let one = builder.one();
+        builder.connect(real[0].target, one);
+        for pair in real.windows(2) {
+            let both = builder.and(pair[0], pair[1]);
+            builder.connect(both.target, pair[1].target);
+        }
+
+        // Leaf proofs count for one, aggregated proofs for the count they expose.
+        let is_leaf = self.levels.is_empty();
+        let mut count = builder.zero();
+        let mut inputs = Vec::new();
+        for (child, real) in children.iter().zip(&real) {
+            let child_count = if is_leaf {
+                one
+            } else {
+                child.public_inputs[0]
+            };
+            count = builder.mul_add(real.target, child_count, count);
+            inputs.extend(
+                child
+                    .public_inputs
+                    .iter()
+                    .map(|&input| builder.mul(real.target, input)),
+            );
+        }
+        let commitment = builder.hash_n_to_hash_no_pad::<PoseidonHash>(inputs);
+        builder.register_public_input(count);
+        builder.register_public_inputs(&commitment.elements);
+        Level {
+            data: builder.build::<C>(),
+            children,
+            real,
+        }
+    }
+
+    /// Proves one aggregation proof for every `fan_in` proofs of `layer`, padding the last
+    /// group with copies of its last proof.
+    fn prove_level(
+        &self,
+        level: &Level<C>,
+        layer: &[ProofWithPublicInputs<F, C, D>],
+    ) -> anyhow::Result<Vec<ProofWithPublicInputs<F, C, D>>> {
+        let prove = |children: &[ProofWithPublicInputs<F, C, D>]| {
+            let mut pw = PartialWitness::new();
+            let padding = children.last().unwrap();
+            for (i, (target, real)) in level.children.iter().zip(&level.real).enumerate() {
+                pw.set_proof_with_pis_target(target, children.get(i).unwrap_or(padding))?;
+                pw.set_bool_target(*real, i < children.len())?;
+            }
+            level.data.prove(pw)
+        };
+        if self.config.parallel {
+            layer.par_chunks(self.config.fan_in).map(prove).collect()
+        } else {
+            layer.chunks(self.config.fan_in).map(prove).collect()
+        }
+    }
+}
+
+/// The public inputs of an aggregated proof of leaf proofs with `leaf_public_inputs`, in order:
+/// the number of leaf proofs followed by the elements of the commitment.
+pub fn commitment<F: RichField>(leaf_public_inputs: &[Vec<F>], fan_in: usize) -> Vec<F> {
+    assert!(fan_in >= 2, "fan-in must be at least 2, got {fan_in}");
+    assert!(!leaf_public_inputs.is_empty(), "no public inputs to commit to");
+    let mut layer = leaf_public_inputs
+        .iter()
+        .map(|inputs| (1, inputs.clone()))
+        .collect::<Vec<_>>();
+    loop {
+        layer = layer
+            .chunks(fan_in)
+            .map(|children| {
+                let count = children.iter().map(|(count, _)| count).sum::<u64>();
+                let mut inputs = children
+                    .iter()
+                    .flat_map(|(_, inputs)| inputs.iter().copied())
+                    .collect::<Vec<_>>();
+                // Padding children are hashed as zeros
+                let padding = (fan_in - children.len()) * children[0].1.len();
+                inputs.resize(inputs.len() + padding, F::ZERO);
+                let hash: HashOut<F> = PoseidonHash::hash_no_pad(&inputs);
+                let mut public_inputs = vec![F::from_canonical_u64(count)];
+                public_inputs.extend(hash.elements);
+                (count, public_inputs)
+            })
+            .collect();
+        if let [(_, root)] = &layer[..] {
+            return root.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trie::{Trie, TrieConfig, TrieProofTarget};
+    use plonky2::field::types::Field;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    type C = PoseidonGoldilocksConfig;
+
+    /// Proves openings of `num_proofs` keys of one trie and aggregates them with `config` into a
+    /// tree of the given depth.
+    fn aggregate_openings(num_proofs: u64, config: AggregationConfig, depth: usize) {
+        let trie_config = TrieConfig::new(8, 1);
+        let mut trie = Trie::<F>::new(trie_config);
+        let keys = (0..num_proofs)
+            .map(|i| [F::from_canonical_u64(3 * i + 1)])
+            .collect::<Vec<_>>();
+        for (i, key) in keys.iter().enumerate() {
+            let value = PoseidonHash::hash_no_pad(&[F::from_canonical_usize(i)]);
+            trie.insert(key, value).unwrap();
+        }
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let target = TrieProofTarget::new(trie_config, &mut builder);
+        let leaf = builder.build::<C>();
+        let proofs = keys
+            .iter()
+            .map(|key| {
+                let mut pw = PartialWitness::new();
+                target.set_witness(&mut pw, &trie.prove(key).unwrap()).unwrap();
+                leaf.prove(pw).unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        let fan_in = config.fan_in;
+        let mut aggregator = Aggregator::new(leaf.verifier_data(), config);
+        let aggregated = aggregator.aggregate(&proofs).unwrap();
+        aggregator.verify(&aggregated).unwrap();
+        assert_eq!(aggregated.num_proofs, proofs.len());
+        assert_eq!(aggregated.depth, depth);
+
+        let leaf_public_inputs = proofs
+            .iter()
+            .map(|proof| proof.public_inputs.clone())
+            .collect::<Vec<_>>();
+        let expected = commitment(&leaf_public_inputs, fan_in);
+        assert_eq!(aggregated.proof.public_inputs, expected);
+        assert_eq!(expected[0], F::from_canonical_u64(num_proofs));
+    }
+
+    #[test]
+    fn commitment_binds_the_number_of_proofs() {
+        let leaves = (0..4)
+            .map(|i| vec![F::from_canonical_u64(i); 2])
+            .collect::<Vec<_>>();
+        let three = commitment(&leaves[..3], 2);
+        assert_eq!(three[0], F::from_canonical_u64(3));
+        // Repeating the last proof is not the same as padding
+        let repeated = [&leaves[..3], &leaves[2..3]].concat();
+        assert_ne!(commitment(&repeated, 2)[1..], three[1..]);
+        assert_eq!(commitment(&leaves, 2)[0], F::from_canonical_u64(4));
+    }
+
+    #[test]
+    fn aggregate_pairwise_in_parallel() {
+        // 3 proofs are aggregated by two levels, the second pair being padded.
+        aggregate_openings(3, AggregationConfig::default(), 2);
+    }
+
+    #[test]
+    fn aggregate_with_fan_in() {
+        let config = AggregationConfig {
+            fan_in: 3,
+            parallel: false,
+            ..AggregationConfig::default()
+        };
+        aggregate_openings(3, config, 1);
+    }
+}
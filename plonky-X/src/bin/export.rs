@@ -0,0 +1,161 @@
+//! Converts a plonky2 proof and its verifier data, serialized to bytes, into the JSON files
+//! read by gnark-plonky2-verifier.
+
+use anyhow::{Context, anyhow, bail};
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::plonk::circuit_data::VerifierCircuitData;
+use plonky2::plonk::config::{GenericConfig, PoseidonGoldilocksConfig};
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use plonky2::util::serialization::DefaultGateSerializer;
+use plonky2_bn254_poseidon::serialization::Bn254GateSerializer;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use valence_plonky2::compress::{ProofSizes, decompress, read_compressed};
+use valence_plonky2::export::save_verifier_files;
+use valence_plonky2::poseidon_bn128::PoseidonBN128GoldilocksConfig;
+
+const D: usize = 2;
+type F = GoldilocksField;
+
+const USAGE: &str = "\
+Writes common_circuit_data.json, verifier_only_circuit_data.json and
+proof_with_public_inputs.json for gnark-plonky2-verifier.
+
+Usage: export --verifier-data <FILE> --proof <FILE> --out <DIR> [--compressed] [--bn128]
+
+Options:
+  --verifier-data <FILE>  VerifierCircuitData::to_bytes output, written with plonky2's
+                          DefaultGateSerializer or bn254-poseidon's Bn254GateSerializer
+  --proof <FILE>          ProofWithPublicInputs::to_bytes output
+  --out <DIR>             Output directory, created if missing
+  --compressed            The proof is CompressedProofWithPublicInputs::to_bytes output
+  --bn128                 The proof uses PoseidonBN128GoldilocksConfig, as the final proof
+                          handed to the wrapper does, instead of PoseidonGoldilocksConfig
+  -h, --help              Print this message";
+
+struct Args {
+    verifier_data: PathBuf,
+    proof: PathBuf,
+    out: PathBuf,
+    compressed: bool,
+    bn128: bool,
+}
+
+fn parse_args() -> anyhow::Result<Option<Args>> {
+    let (mut verifier_data, mut proof, mut out) = (None, None, None);
+    let (mut compressed, mut bn128) = (false, false);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let slot = match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--compressed" => {
+                compressed = true;
+                continue;
+            }
+            "--bn128" => {
+                bn128 = true;
+                continue;
+            }
+            "--verifier-data" => &mut verifier_data,
+            "--proof" => &mut proof,
+            "--out" => &mut out,
+            _ => bail!("unexpected argument {arg}\n\n{USAGE}"),
+        };
+        let value = args.next().ok_or_else(|| anyhow!("{arg} needs a value"))?;
+        *slot = Some(PathBuf::from(value));
+    }
+    let missing = |name: &str| anyhow!("missing {name}\n\n{USAGE}");
+    Ok(Some(Args {
+        verifier_data: verifier_data.ok_or_else(|| missing("--verifier-data"))?,
+        proof: proof.ok_or_else(|| missing("--proof"))?,
+        out: out.ok_or_else(|| missing("--out"))?,
+        compressed,
+        bn128,
+    }))
+}
+
+fn read(path: &Path) -> anyhow::Result<Vec<u8>> {
+    fs::read(path).with_context(|| format!("reading {}", path.display()))
+}
+
+fn main() -> anyhow::Result<()> {
+    let Some(args) = parse_args()? else {
+        println!("{USAGE}");
+        return Ok(());
+    };
+    if args.bn128 {
+        run::<PoseidonBN128GoldilocksConfig>(&args)
+    } else {
+        run::<PoseidonGoldilocksConfig>(&args)
+    }
+}
+
+fn run<C: GenericConfig<D, F = F> + Serialize>(args: &Args) -> anyhow::Result<()> {
+    // Verifier data of circuits with Bn254MulGate is written with bn254-poseidon's serializer,
+    // everything else usually with plonky2's default one.
+    let bytes = read(&args.verifier_data)?;
+    let verifier_data =
+        VerifierCircuitData::<F, C, D>::from_bytes(bytes.clone(), &Bn254GateSerializer)
+            .or_else(|_| VerifierCircuitData::from_bytes(bytes, &DefaultGateSerializer))
+            .map_err(|_| anyhow!("invalid verifier data in {}", args.verifier_data.display()))?;
+    let bytes = read(&args.proof)?;
+    let proof = if args.compressed {
+        let compressed = read_compressed(&verifier_data, bytes)
+            .with_context(|| format!("in {}", args.proof.display()))?;
+        decompress(&verifier_data, compressed)?
+    } else {
+        ProofWithPublicInputs::from_bytes(bytes, &verifier_data.common)
+            .with_context(|| format!("invalid proof in {}", args.proof.display()))?
+    };
+    verifier_data
+        .verify(proof.clone())
+        .context("the proof does not verify against the verifier data")?;
+
+    save_verifier_files(&verifier_data.common, &verifier_data.verifier_only, &proof, &args.out)?;
+    println!("Wrote gnark verifier inputs to {}", args.out.display());
+    println!("{}", ProofSizes::measure(&verifier_data, &proof)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use valence_plonky2::import::verify_files;
+
+    #[test]
+    fn exports_bn128_proofs() {
+        type C = PoseidonBN128GoldilocksConfig;
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let input = builder.add_virtual_target();
+        let square = builder.mul(input, input);
+        builder.register_public_input(square);
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        pw.set_target(input, F::from_canonical_u64(5)).unwrap();
+        let proof = data.prove(pw).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let verifier_data = data
+            .verifier_data()
+            .to_bytes(&DefaultGateSerializer)
+            .unwrap();
+        fs::write(dir.join("verifier_data.bin"), verifier_data).unwrap();
+        fs::write(dir.join("proof.bin"), proof.to_bytes()).unwrap();
+        let args = Args {
+            verifier_data: dir.join("verifier_data.bin"),
+            proof: dir.join("proof.bin"),
+            out: dir.join("out"),
+            compressed: false,
+            bn128: true,
+        };
+
+        run::<C>(&args).unwrap();
+        assert_eq!(verify_files::<C, D>(dir.join("out")).unwrap(), proof);
+    }
+}
@@ -0,0 +1,129 @@
+//! plonky2's compressed proof form, and proof sizes in each of the forms we ship.
+//!
+//! Compression deduplicates the Merkle paths of FRI query rounds that share nodes and drops the
+//! values the verifier can recompute, so it saves most on small circuits with many queries.
+
+use anyhow::{Context, anyhow};
+use plonky2::plonk::circuit_data::VerifierCircuitData;
+use plonky2::plonk::config::GenericConfig;
+use plonky2::plonk::proof::{CompressedProofWithPublicInputs, ProofWithPublicInputs};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::export::to_gnark_json;
+
+/// Compresses `proof` of the circuit with verifier data `data`.
+pub fn compress<C: GenericConfig<D>, const D: usize>(
+    data: &VerifierCircuitData<C::F, C, D>,
+    proof: ProofWithPublicInputs<C::F, C, D>,
+) -> anyhow::Result<CompressedProofWithPublicInputs<C::F, C, D>> {
+    proof.compress(&data.verifier_only.circuit_digest, &data.common)
+}
+
+/// Restores the full proof from a compressed one.
+pub fn decompress<C: GenericConfig<D>, const D: usize>(
+    data: &VerifierCircuitData<C::F, C, D>,
+    compressed: CompressedProofWithPublicInputs<C::F, C, D>,
+) -> anyhow::Result<ProofWithPublicInputs<C::F, C, D>> {
+    compressed.decompress(&data.verifier_only.circuit_digest, &data.common)
+}
+
+/// Reads a compressed proof written with `CompressedProofWithPublicInputs::to_bytes` and checks
+/// it against `data`.
+///
+/// plonky2 panics when decompressing a proof whose query rounds do not match its challenges,
+/// e.g. after its public inputs were changed, so that panic is reported as an error too.
+pub fn read_compressed<C: GenericConfig<D>, const D: usize>(
+    data: &VerifierCircuitData<C::F, C, D>,
+    bytes: Vec<u8>,
+) -> anyhow::Result<CompressedProofWithPublicInputs<C::F, C, D>> {
+    let compressed = CompressedProofWithPublicInputs::from_bytes(bytes, &data.common)
+        .context("invalid compressed proof")?;
+    let verified = panic::catch_unwind(AssertUnwindSafe(|| {
+        data.verify_compressed(compressed.clone())
+    }))
+    .unwrap_or_else(|_| Err(anyhow!("inconsistent compressed proof")));
+    verified.context("the compressed proof does not verify")?;
+    Ok(compressed)
+}
+
+/// Sizes in bytes of one proof as plonky2 bytes, compressed plonky2 bytes and the
+/// `proof_with_public_inputs.json` written by `save_files`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProofSizes {
+    pub raw: usize,
+    pub compressed: usize,
+    pub json: usize,
+}
+
+impl ProofSizes {
+    pub fn measure<C: GenericConfig<D>, const D: usize>(
+        data: &VerifierCircuitData<C::F, C, D>,
+        proof: &ProofWithPublicInputs<C::F, C, D>,
+    ) -> anyhow::Result<Self> {
+        let json = serde_json::to_string_pretty(&to_gnark_json(proof)?)?;
+        Ok(Self {
+            raw: proof.to_bytes().len(),
+            compressed: compress(data, proof.clone())?.to_bytes().len(),
+            json: json.len(),
+        })
+    }
+}
+
+impl fmt::Display for ProofSizes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = |size: usize| 100.0 * size as f64 / self.raw as f64;
+        writeln!(f, "raw:        {:>8} bytes", self.raw)?;
+        writeln!(
+            f,
+            "compressed: {:>8} bytes ({:.1}% of raw)",
+            self.compressed,
+            percent(self.compressed)
+        )?;
+        write!(f, "json:       {:>8} bytes ({:.1}% of raw)", self.json, percent(self.json))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    #[test]
+    fn compressed_round_trip() {
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let input = builder.add_virtual_target();
+        let square = builder.mul(input, input);
+        builder.register_public_input(square);
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        pw.set_target(input, F::from_canonical_u64(9)).unwrap();
+        let proof = data.prove(pw).unwrap();
+        let verifier_data = data.verifier_data();
+
+        let compressed = compress(&verifier_data, proof.clone()).unwrap();
+        let compressed = read_compressed(&verifier_data, compressed.to_bytes()).unwrap();
+        assert_eq!(compressed.public_inputs, proof.public_inputs);
+        assert_eq!(decompress(&verifier_data, compressed).unwrap(), proof);
+
+        // Compression drops more than a fifth of the raw size here, while decimal JSON is about
+        // five times as large.
+        let sizes = ProofSizes::measure(&verifier_data, &proof).unwrap();
+        assert_eq!(sizes.raw, proof.to_bytes().len());
+        assert!(sizes.compressed * 5 < sizes.raw * 4, "{sizes}");
+        assert!(sizes.json > 4 * sizes.raw, "{sizes}");
+
+        // A compressed proof with other public inputs is rejected.
+        let mut tampered = compress(&verifier_data, proof).unwrap();
+        tampered.public_inputs[0] += F::ONE;
+        assert!(read_compressed(&verifier_data, tampered.to_bytes()).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+//! Export of plonky2 proofs and verifier data in the JSON layout of Succinct's
+//! [gnark-plonky2-verifier](https://github.com/succinctlabs/gnark-plonky2-verifier).
+
+use anyhow::Context;
+use num_bigint::BigUint;
+use num_traits::ToPrimitive;
+use plonky2::plonk::circuit_data::{CircuitData, CommonCircuitData, VerifierOnlyCircuitData};
+use plonky2::plonk::config::GenericConfig;
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+
+use crate::validate::{HashEncoding, validate_json};
+
+pub const COMMON_CIRCUIT_DATA_FILE: &str = "common_circuit_data.json";
+pub const VERIFIER_ONLY_CIRCUIT_DATA_FILE: &str = "verifier_only_circuit_data.json";
+pub const PROOF_WITH_PUBLIC_INPUTS_FILE: &str = "proof_with_public_inputs.json";
+
+/// Keys whose hashes are packed into a single decimal string by `serialize_with_key_path`.
+pub(crate) const HASH_KEYS: [&str; 7] = [
+    "siblings",
+    "constants_sigmas_cap",
+    "circuit_digest",
+    "wires_cap",
+    "quotient_polys_cap",
+    "plonk_zs_partial_products_cap",
+    "commit_phase_merkle_caps",
+];
+
+/// Rewrites plonky2's serde JSON into the layout read by gnark-plonky2-verifier: hashes and
+/// Merkle caps become decimal strings of their packed Goldilocks limbs, `{ "elements": [...] }`
+/// wrappers are removed and numbers are reduced modulo the Goldilocks prime.
+pub fn serialize_with_key_path(val: Value, path: Vec<String>) -> Value {
+    let modulus = BigUint::parse_bytes(b"FFFFFFFF00000001", 16).unwrap();
+    let modulus_u64 = modulus.to_u64().unwrap();
+
+    let current_key = path.last().map(|s| s.as_str());
+    // Field elements known to be serialized in limb form
+    let is_field_element_key = current_key.is_some_and(|key| HASH_KEYS.contains(&key));
+
+    match val {
+        Value::Object(mut map) => {
+            // Auto-unwrap { "elements": [...] }
+            if map.len() == 1 && map.contains_key("elements") {
+                let inner = map.remove("elements").unwrap();
+                return serialize_with_key_path(inner, path);
+            }
+
+            let mut out = Map::new();
+            for (k, v) in map {
+                let mut new_path = path.clone();
+                new_path.push(k.clone());
+                out.insert(k, serialize_with_key_path(v, new_path));
+            }
+            Value::Object(out)
+        }
+
+        Value::Array(arr) => {
+            if is_field_element_key {
+                // Case 1: Flat array of limbs (an empty list of hashes stays empty). A single
+                // hash becomes a string, several hashes an array of strings.
+                if !arr.is_empty() && arr.iter().all(|v| v.is_u64()) && arr.len().is_multiple_of(4) {
+                    let mut packed: Vec<_> = arr
+                        .chunks(4)
+                        .map(|chunk| {
+                            let acc = chunk.iter().rev().fold(BigUint::from(0u64), |acc, limb| {
+                                acc * &modulus + BigUint::from(limb.as_u64().unwrap())
+                            });
+                            Value::String(acc.to_string())
+                        })
+                        .collect();
+                    return match packed.len() {
+                        1 => packed.pop().unwrap(),
+                        _ => Value::Array(packed),
+                    };
+                }
+
+                // Case 2: Nested arrays of limbs
+                if arr.iter().all(|v| matches!(v, Value::Array(inner) if inner.len() == 4 && inner.iter().all(|x| x.is_u64()))) {
+                    let packed: Vec<_> = arr
+                        .into_iter()
+                        .map(|inner| {
+                            let inner = match inner {
+                                Value::Array(inner) => inner,
+                                _ => unreachable!(),
+                            };
+                            let acc = inner.into_iter().rev().fold(BigUint::from(0u64), |acc, limb| {
+                                acc * &modulus + BigUint::from(limb.as_u64().unwrap())
+                            });
+                            Value::String(acc.to_string())
+                        })
+                        .collect();
+                    return Value::Array(packed);
+                }
+            }
+
+            // Generic case — recurse into sub-arrays
+            Value::Array(
+                arr.into_iter()
+                    .map(|v| serialize_with_key_path(v, path.clone()))
+                    .collect(),
+            )
+        }
+
+        Value::Number(ref n) => {
+            if let Some(u) = n.as_u64() {
+                Value::Number(serde_json::Number::from(u % modulus_u64))
+            } else {
+                val
+            }
+        }
+
+        other => other,
+    }
+}
+
+/// Serializes `value` with serde and converts it with `serialize_with_key_path`.
+pub fn to_gnark_json<T: Serialize + ?Sized>(value: &T) -> anyhow::Result<Value> {
+    let json = serde_json::to_value(value)?;
+    Ok(serialize_with_key_path(json, vec![]))
+}
+
+/// Writes `common_circuit_data.json`, `verifier_only_circuit_data.json` and
+/// `proof_with_public_inputs.json` for gnark-plonky2-verifier to `dir`, creating it if needed.
+/// The JSON is checked with `validate::validate_json` before anything is written.
+pub fn save_files<C, const D: usize>(
+    data: &CircuitData<C::F, C, D>,
+    proof: &ProofWithPublicInputs<C::F, C, D>,
+    dir: impl AsRef<Path>,
+) -> anyhow::Result<()>
+where
+    C: GenericConfig<D> + Serialize,
+{
+    save_verifier_files(&data.common, &data.verifier_only, proof, dir)
+}
+
+/// Like `save_files`, for callers that only hold the verifier side of the circuit, e.g. a
+/// `VerifierCircuitData` loaded from bytes.
+pub fn save_verifier_files<C, const D: usize>(
+    common: &CommonCircuitData<C::F, D>,
+    verifier_only: &VerifierOnlyCircuitData<C, D>,
+    proof: &ProofWithPublicInputs<C::F, C, D>,
+    dir: impl AsRef<Path>,
+) -> anyhow::Result<()>
+where
+    C: GenericConfig<D> + Serialize,
+{
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    let files = [
+        (COMMON_CIRCUIT_DATA_FILE, to_gnark_json(common)?),
+        (VERIFIER_ONLY_CIRCUIT_DATA_FILE, to_gnark_json(verifier_only)?),
+        (PROOF_WITH_PUBLIC_INPUTS_FILE, to_gnark_json(proof)?),
+    ];
+    validate_json(&files[0].1, &files[1].1, &files[2].1, HashEncoding::of::<C, D>())
+        .context("the exported JSON does not match gnark-plonky2-verifier's layout")?;
+    for (name, json) in files {
+        let path = dir.join(name);
+        fs::write(&path, serde_json::to_string_pretty(&json)?)
+            .with_context(|| format!("writing {}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_flat_hash_arrays() {
+        let modulus = BigUint::parse_bytes(b"FFFFFFFF00000001", 16).unwrap();
+        let packed = |limbs: [u64; 4]| {
+            let acc = limbs.iter().rev().fold(BigUint::from(0u64), |acc, limb| {
+                acc * &modulus + BigUint::from(*limb)
+            });
+            Value::String(acc.to_string())
+        };
+
+        // A single hash becomes one string
+        let json = json!({ "circuit_digest": { "elements": [1, 2, 3, 4] } });
+        let json = serialize_with_key_path(json, vec![]);
+        assert_eq!(json["circuit_digest"], packed([1, 2, 3, 4]));
+
+        // Several hashes in one flat array keep every hash, in order
+        let json = json!({ "siblings": [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12] });
+        let json = serialize_with_key_path(json, vec![]);
+        assert_eq!(
+            json["siblings"],
+            Value::Array(vec![
+                packed([1, 2, 3, 4]),
+                packed([5, 6, 7, 8]),
+                packed([9, 10, 11, 12]),
+            ])
+        );
+
+        // Empty lists of hashes stay empty
+        let json = serialize_with_key_path(json!({ "siblings": [] }), vec![]);
+        assert_eq!(json["siblings"], json!([]));
+    }
+}
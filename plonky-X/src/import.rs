@@ -0,0 +1,517 @@
+//! Import of the gnark-plonky2-verifier JSON written by `export`, back into native plonky2 types.
+//!
+//! Proofs and verifier-only data are plain serde once the hashes packed by
+//! `serialize_with_key_path` are split back into limbs. `CommonCircuitData` only serializes its
+//! gates by id, so those are rebuilt from the ids of the gates plonky2's standard circuits
+//! use, plus bn254-poseidon's `Bn254MulGate`, `Bn254AffineGate` and `Bn254CheckGate`.
+
+use anyhow::{Context, anyhow, bail, ensure};
+use ark_bn254::Fr;
+use num_bigint::BigUint;
+use num_traits::{ToPrimitive, Zero};
+use plonky2::field::extension::Extendable;
+use plonky2::fri::reduction_strategies::FriReductionStrategy;
+use plonky2::fri::{FriConfig, FriParams};
+use plonky2::gates::arithmetic_base::ArithmeticGate;
+use plonky2::gates::arithmetic_extension::ArithmeticExtensionGate;
+use plonky2::gates::base_sum::BaseSumGate;
+use plonky2::gates::constant::ConstantGate;
+use plonky2::gates::coset_interpolation::CosetInterpolationGate;
+use plonky2::gates::exponentiation::ExponentiationGate;
+use plonky2::gates::gate::GateRef;
+use plonky2::gates::multiplication_extension::MulExtensionGate;
+use plonky2::gates::noop::NoopGate;
+use plonky2::gates::poseidon::PoseidonGate;
+use plonky2::gates::poseidon_mds::PoseidonMdsGate;
+use plonky2::gates::public_input::PublicInputGate;
+use plonky2::gates::random_access::RandomAccessGate;
+use plonky2::gates::reducing::ReducingGate;
+use plonky2::gates::reducing_extension::ReducingExtensionGate;
+use plonky2::hash::hash_types::RichField;
+use plonky2::plonk::circuit_data::{
+    CircuitConfig, CommonCircuitData, VerifierCircuitData, VerifierOnlyCircuitData,
+};
+use plonky2::plonk::config::{GenericConfig, Hasher};
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use plonky2::util::serialization::{Buffer, DefaultGateSerializer, IoResult, Read, Write};
+use plonky2_bn254_poseidon::gate::{
+    Bn254AffineGate, Bn254CheckGate, Bn254MulGate, MAX_AFFINE_TERMS,
+};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+
+use crate::export::{
+    COMMON_CIRCUIT_DATA_FILE, HASH_KEYS, PROOF_WITH_PUBLIC_INPUTS_FILE,
+    VERIFIER_ONLY_CIRCUIT_DATA_FILE,
+};
+
+/// Number of Goldilocks elements in a plonky2 `HashOut`.
+const HASH_LIMBS: usize = 4;
+
+/// Inverse of `serialize_with_key_path`: splits packed hashes back into their Goldilocks
+/// limbs, wrapped in `{ "elements": [...] }` as serde expects for a `HashOut`.
+pub fn deserialize_with_key_path(val: Value, path: Vec<String>) -> anyhow::Result<Value> {
+    let is_hash_key = path.last().is_some_and(|key| HASH_KEYS.contains(&key.as_str()));
+    match val {
+        Value::String(packed) if is_hash_key => unpack_hash(&packed),
+        Value::Object(map) => {
+            let mut out = Map::new();
+            for (k, v) in map {
+                let mut new_path = path.clone();
+                new_path.push(k.clone());
+                out.insert(k, deserialize_with_key_path(v, new_path)?);
+            }
+            Ok(Value::Object(out))
+        }
+        Value::Array(arr) => Ok(Value::Array(
+            arr.into_iter()
+                .map(|v| deserialize_with_key_path(v, path.clone()))
+                .collect::<anyhow::Result<_>>()?,
+        )),
+        other => Ok(other),
+    }
+}
+
+/// Splits a decimal `sum(limb_i * p^i)` into the `HashOut` of its `HASH_LIMBS` Goldilocks
+/// limbs.
+fn unpack_hash(packed: &str) -> anyhow::Result<Value> {
+    let modulus = BigUint::parse_bytes(b"FFFFFFFF00000001", 16).unwrap();
+    let mut acc = BigUint::parse_bytes(packed.as_bytes(), 10)
+        .ok_or_else(|| anyhow!("invalid packed hash {packed:?}"))?;
+    let mut limbs = Vec::with_capacity(HASH_LIMBS);
+    for _ in 0..HASH_LIMBS {
+        let limb = (&acc % &modulus).to_u64().unwrap();
+        limbs.push(Value::from(limb));
+        acc /= &modulus;
+    }
+    ensure!(acc.is_zero(), "packed hash {packed} has more than {HASH_LIMBS} limbs");
+    Ok(serde_json::json!({ "elements": limbs }))
+}
+
+fn field<'a>(value: &'a Value, key: &str) -> anyhow::Result<&'a Value> {
+    value.get(key).ok_or_else(|| anyhow!("missing field {key:?}"))
+}
+
+fn parse<T: DeserializeOwned>(value: &Value, key: &str) -> anyhow::Result<T> {
+    serde_json::from_value(field(value, key)?.clone()).with_context(|| format!("invalid {key:?}"))
+}
+
+fn parse_circuit_config(value: &Value) -> anyhow::Result<CircuitConfig> {
+    Ok(CircuitConfig {
+        num_wires: parse(value, "num_wires")?,
+        num_routed_wires: parse(value, "num_routed_wires")?,
+        num_constants: parse(value, "num_constants")?,
+        use_base_arithmetic_gate: parse(value, "use_base_arithmetic_gate")?,
+        security_bits: parse(value, "security_bits")?,
+        num_challenges: parse(value, "num_challenges")?,
+        zero_knowledge: parse(value, "zero_knowledge")?,
+        max_quotient_degree_factor: parse(value, "max_quotient_degree_factor")?,
+        fri_config: parse_fri_config(field(value, "fri_config")?)?,
+    })
+}
+
+fn parse_fri_config(value: &Value) -> anyhow::Result<FriConfig> {
+    let strategy = field(value, "reduction_strategy")?;
+    let reduction_strategy = if let Some(arity_bits) = strategy.get("Fixed") {
+        FriReductionStrategy::Fixed(serde_json::from_value(arity_bits.clone())?)
+    } else if let Some(bits) = strategy.get("ConstantArityBits") {
+        let (arity_bits, final_poly_bits) = serde_json::from_value(bits.clone())?;
+        FriReductionStrategy::ConstantArityBits(arity_bits, final_poly_bits)
+    } else if let Some(opt) = strategy.get("MinSize") {
+        FriReductionStrategy::MinSize(serde_json::from_value(opt.clone())?)
+    } else {
+        bail!("unknown FRI reduction strategy {strategy}");
+    };
+    Ok(FriConfig {
+        rate_bits: parse(value, "rate_bits")?,
+        cap_height: parse(value, "cap_height")?,
+        proof_of_work_bits: parse(value, "proof_of_work_bits")?,
+        reduction_strategy,
+        num_query_rounds: parse(value, "num_query_rounds")?,
+    })
+}
+
+fn parse_fri_params(value: &Value) -> anyhow::Result<FriParams> {
+    Ok(FriParams {
+        config: parse_fri_config(field(value, "config")?)?,
+        hiding: parse(value, "hiding")?,
+        degree_bits: parse(value, "degree_bits")?,
+        reduction_arity_bits: parse(value, "reduction_arity_bits")?,
+    })
+}
+
+/// Selector indices and `(start, end)` groups of `selectors_info`, whose fields plonky2 keeps
+/// private.
+pub(crate) type SelectorsParts = (Vec<usize>, Vec<(usize, usize)>);
+
+pub(crate) fn parse_selectors_info(value: &Value) -> anyhow::Result<SelectorsParts> {
+    let groups = field(value, "groups")?
+        .as_array()
+        .ok_or_else(|| anyhow!("selector groups must be an array"))?
+        .iter()
+        .map(|group| Ok((parse(group, "start")?, parse(group, "end")?)))
+        .collect::<anyhow::Result<_>>()?;
+    Ok((parse(value, "selector_indices")?, groups))
+}
+
+/// Reads the unsigned integer following `name: ` in a gate id.
+pub(crate) fn gate_param(id: &str, name: &str) -> anyhow::Result<usize> {
+    let start = id
+        .find(&format!("{name}: "))
+        .ok_or_else(|| anyhow!("gate {id:?} has no {name}"))?
+        + name.len()
+        + 2;
+    let digits = id[start..]
+        .split(|c: char| !c.is_ascii_digit())
+        .next()
+        .unwrap_or_default();
+    digits
+        .parse()
+        .with_context(|| format!("invalid {name} in gate {id:?}"))
+}
+
+/// Rebuilds a gate from its `Gate::id`, the only part of a gate in the exported JSON.
+fn parse_gate<F: RichField + Extendable<D>, const D: usize>(
+    id: &str,
+    config: &CircuitConfig,
+) -> anyhow::Result<GateRef<F, D>> {
+    if let Some(suffix) = id.rfind("<D=").map(|i| &id[i..]) {
+        ensure!(suffix == format!("<D={D}>"), "gate {id:?} is not for extension degree {D}");
+    }
+    let gate = if id.starts_with("ArithmeticGate {") {
+        GateRef::new(ArithmeticGate {
+            num_ops: gate_param(id, "num_ops")?,
+        })
+    } else if id.starts_with("ArithmeticExtensionGate {") {
+        GateRef::new(ArithmeticExtensionGate::<D> {
+            num_ops: gate_param(id, "num_ops")?,
+        })
+    } else if id.starts_with("MulExtensionGate {") {
+        GateRef::new(MulExtensionGate::<D> {
+            num_ops: gate_param(id, "num_ops")?,
+        })
+    } else if id.starts_with("BaseSumGate {") {
+        ensure!(id.ends_with("+ Base: 2"), "unsupported base in gate {id:?}");
+        GateRef::new(BaseSumGate::<2>::new(gate_param(id, "num_limbs")?))
+    } else if id.starts_with("ConstantGate {") {
+        GateRef::new(ConstantGate::new(gate_param(id, "num_consts")?))
+    } else if id.starts_with("CosetInterpolationGate {") {
+        // The barycentric weights only depend on the subgroup; the id check below catches any
+        // mismatch.
+        let mut gate = CosetInterpolationGate::<F, D>::new(gate_param(id, "subgroup_bits")?);
+        gate.degree = gate_param(id, "degree")?;
+        GateRef::new(gate)
+    } else if id.starts_with("ExponentiationGate {") {
+        GateRef::new(ExponentiationGate::<F, D>::new(gate_param(id, "num_power_bits")?))
+    } else if id.starts_with("RandomAccessGate {") {
+        // The number of copies and extra constants follow from the config, as in the builder.
+        GateRef::new(RandomAccessGate::<F, D>::new_from_config(
+            config,
+            gate_param(id, "bits")?,
+        ))
+    } else if id.starts_with("ReducingGate {") {
+        GateRef::new(ReducingGate::<D>::new(gate_param(id, "num_coeffs")?))
+    } else if id.starts_with("ReducingExtensionGate {") {
+        GateRef::new(ReducingExtensionGate::<D>::new(gate_param(id, "num_coeffs")?))
+    } else if id.starts_with("PoseidonGate(") {
+        GateRef::new(PoseidonGate::<F, D>::new())
+    } else if id.starts_with("PoseidonMdsGate(") {
+        GateRef::new(PoseidonMdsGate::<F, D>::new())
+    } else if id == "NoopGate" {
+        GateRef::new(NoopGate)
+    } else if id == "PublicInputGate" {
+        GateRef::new(PublicInputGate)
+    } else if id == "Bn254MulGate" {
+        GateRef::new(Bn254MulGate)
+    } else if let Some(coefficients) = id
+        .strip_prefix("Bn254AffineGate { coefficients: [")
+        .and_then(|rest| rest.strip_suffix("] }"))
+    {
+        let coefficients = coefficients
+            .split(", ")
+            .map(|c| c.parse::<Fr>().map_err(|_| anyhow!("invalid coefficient in gate {id:?}")))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        ensure!(
+            (1..=MAX_AFFINE_TERMS).contains(&coefficients.len()),
+            "gate {id:?} has {} coefficients",
+            coefficients.len()
+        );
+        GateRef::new(Bn254AffineGate::new(coefficients))
+    } else if id.starts_with("Bn254CheckGate {") {
+        GateRef::new(Bn254CheckGate::new(gate_param(id, "num_wide")?))
+    } else if id.starts_with("Lookup") {
+        bail!("lookup gates are not supported by gnark-plonky2-verifier: {id:?}");
+    } else {
+        bail!("unknown gate {id:?}");
+    };
+    ensure!(gate.0.id() == id, "gate {id:?} was rebuilt as {:?}", gate.0.id());
+    Ok(gate)
+}
+
+/// Rebuilds `CommonCircuitData` from the contents of `common_circuit_data.json`.
+///
+/// Only plonky2 can build a `SelectorsInfo`, so the fields are written in the layout of its
+/// `Write::write_common_circuit_data`, with no gates, and read back with its reader. The gates
+/// are set afterwards.
+pub fn common_circuit_data_from_json<F: RichField + Extendable<D>, const D: usize>(
+    json: &Value,
+) -> anyhow::Result<CommonCircuitData<F, D>> {
+    let config = parse_circuit_config(field(json, "config")?)?;
+    let gates = parse::<Vec<String>>(json, "gates")?
+        .iter()
+        .map(|id| parse_gate(id, &config))
+        .collect::<anyhow::Result<_>>()?;
+    let fri_params = parse_fri_params(field(json, "fri_params")?)?;
+    let (selector_indices, selector_groups) =
+        parse_selectors_info(field(json, "selectors_info")?)?;
+    let k_is = parse::<Vec<u64>>(json, "k_is")?
+        .into_iter()
+        .map(F::from_canonical_u64)
+        .collect::<Vec<_>>();
+    let luts = parse::<Vec<Vec<(u16, u16)>>>(json, "luts")?;
+
+    let io = |result: IoResult<()>| result.map_err(|_| anyhow!("serializing common circuit data"));
+    let mut bytes = Vec::new();
+    io(bytes.write_circuit_config(&config))?;
+    io(bytes.write_fri_params(&fri_params))?;
+    io(bytes.write_usize_vec(&selector_indices))?;
+    io(bytes.write_usize(selector_groups.len()))?;
+    for (start, end) in selector_groups {
+        io(bytes.write_usize(start))?;
+        io(bytes.write_usize(end))?;
+    }
+    for key in [
+        "quotient_degree_factor",
+        "num_gate_constraints",
+        "num_constants",
+        "num_public_inputs",
+    ] {
+        io(bytes.write_usize(parse(json, key)?))?;
+    }
+    io(bytes.write_usize(k_is.len()))?;
+    io(bytes.write_field_vec(&k_is))?;
+    for key in [
+        "num_partial_products",
+        "num_lookup_polys",
+        "num_lookup_selectors",
+    ] {
+        io(bytes.write_usize(parse(json, key)?))?;
+    }
+    io(bytes.write_usize(luts.len()))?;
+    for lut in &luts {
+        io(bytes.write_lut(lut))?;
+    }
+    io(bytes.write_usize(0))?;
+
+    let mut common = Buffer::new(&bytes)
+        .read_common_circuit_data::<F, D>(&DefaultGateSerializer)
+        .map_err(|_| anyhow!("invalid common circuit data"))?;
+    common.gates = gates;
+    Ok(common)
+}
+
+/// Whether the exporter packs `C`'s digests from Goldilocks limbs, as for plonky2's `HashOut`.
+/// Digests that serialize as a decimal string themselves, like `PoseidonBN128HashOut`, are
+/// left alone by the exporter and must not be unpacked.
+pub(crate) fn packs_hashes<C: GenericConfig<D>, const D: usize>() -> bool {
+    serde_json::from_value::<<C::Hasher as Hasher<C::F>>::Hash>(Value::from("0")).is_err()
+}
+
+fn read_json(path: &Path, unpack_hashes: bool) -> anyhow::Result<Value> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let json =
+        serde_json::from_str(&contents).with_context(|| format!("parsing {}", path.display()))?;
+    if !unpack_hashes {
+        return Ok(json);
+    }
+    deserialize_with_key_path(json, vec![]).with_context(|| format!("decoding {}", path.display()))
+}
+
+/// The verifier data and proof read by `load_files`.
+pub type LoadedFiles<C, const D: usize> = (
+    VerifierCircuitData<<C as GenericConfig<D>>::F, C, D>,
+    ProofWithPublicInputs<<C as GenericConfig<D>>::F, C, D>,
+);
+
+/// Reads `common_circuit_data.json`, `verifier_only_circuit_data.json` and
+/// `proof_with_public_inputs.json` from `dir`, as written by `save_files`.
+pub fn load_files<C: GenericConfig<D>, const D: usize>(
+    dir: impl AsRef<Path>,
+) -> anyhow::Result<LoadedFiles<C, D>> {
+    let dir = dir.as_ref();
+    let unpack_hashes = packs_hashes::<C, D>();
+    let common = read_json(&dir.join(COMMON_CIRCUIT_DATA_FILE), unpack_hashes)?;
+    let common = common_circuit_data_from_json(&common).context("invalid common circuit data")?;
+    let verifier_only = read_json(&dir.join(VERIFIER_ONLY_CIRCUIT_DATA_FILE), unpack_hashes)?;
+    let verifier_only = VerifierOnlyCircuitData {
+        constants_sigmas_cap: parse(&verifier_only, "constants_sigmas_cap")?,
+        circuit_digest: parse(&verifier_only, "circuit_digest")?,
+    };
+    let proof = read_json(&dir.join(PROOF_WITH_PUBLIC_INPUTS_FILE), unpack_hashes)?;
+    let proof = serde_json::from_value(proof).context("invalid proof")?;
+    let verifier_data = VerifierCircuitData {
+        verifier_only,
+        common,
+    };
+    Ok((verifier_data, proof))
+}
+
+/// Loads the files in `dir` with `load_files` and verifies the proof natively, returning it.
+pub fn verify_files<C: GenericConfig<D>, const D: usize>(
+    dir: impl AsRef<Path>,
+) -> anyhow::Result<ProofWithPublicInputs<C::F, C, D>> {
+    let (verifier_data, proof) = load_files::<C, D>(dir)?;
+    verifier_data.verify(proof.clone())?;
+    Ok(proof)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{save_files, serialize_with_key_path, to_gnark_json};
+    use plonky2::field::types::Field;
+    use plonky2::iop::target::Target;
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData};
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+    use plonky2_bn254_poseidon::arithmetic::NUM_LIMBS;
+    use plonky2_bn254_poseidon::params::PoseidonParams;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = <C as GenericConfig<D>>::F;
+
+    /// Exports `data` and `proof`, imports them again and checks that nothing changed.
+    fn assert_round_trip(
+        name: &str,
+        data: &CircuitData<F, C, D>,
+        proof: &ProofWithPublicInputs<F, C, D>,
+    ) {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join(name);
+        save_files(data, proof, &dir).unwrap();
+        assert_imported(&dir, data, proof);
+    }
+
+    /// Imports the files in `dir` and checks that they hold `data` and `proof`.
+    fn assert_imported(
+        dir: &Path,
+        data: &CircuitData<F, C, D>,
+        proof: &ProofWithPublicInputs<F, C, D>,
+    ) {
+        let (verifier_data, imported) = load_files::<C, D>(dir).unwrap();
+        assert_eq!(verifier_data.common, data.common);
+        assert_eq!(verifier_data.verifier_only, data.verifier_only);
+        assert_eq!(&imported, proof);
+        verify_files::<C, D>(dir).unwrap();
+    }
+
+    fn add_circuit() -> (CircuitData<F, C, D>, ProofWithPublicInputs<F, C, D>) {
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let input = builder.add_virtual_target();
+        let six = builder.constant(F::from_canonical_u64(6));
+        let sum = builder.add(input, six);
+        builder.register_public_input(sum);
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        pw.set_target(input, F::from_canonical_u64(7)).unwrap();
+        let proof = data.prove(pw).unwrap();
+        (data, proof)
+    }
+
+    #[test]
+    fn export_import_add_circuit() {
+        let (data, proof) = add_circuit();
+        assert_round_trip("add", &data, &proof);
+    }
+
+    #[test]
+    fn export_import_recursive_circuit() {
+        // Recursive verification uses most of plonky2's gates.
+        let (inner_data, inner_proof) = add_circuit();
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let proof_target = builder.add_virtual_proof_with_pis(&inner_data.common);
+        let verifier_target =
+            builder.add_virtual_verifier_data(inner_data.common.config.fri_config.cap_height);
+        builder.register_public_inputs(&proof_target.public_inputs);
+        builder.verify_proof::<C>(&proof_target, &verifier_target, &inner_data.common);
+        let data = builder.build::<C>();
+
+        let mut pw = PartialWitness::new();
+        pw.set_proof_with_pis_target(&proof_target, &inner_proof).unwrap();
+        pw.set_verifier_data_target(&verifier_target, &inner_data.verifier_only)
+            .unwrap();
+        let proof = data.prove(pw).unwrap();
+        assert_round_trip("recursive", &data, &proof);
+    }
+
+    #[test]
+    fn export_import_bn254_gates() {
+        // gnark-plonky2-verifier cannot parse bn254-poseidon's gates, so `save_files` refuses
+        // them. The JSON is written directly to check that the importer still rebuilds them.
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let affine = Bn254AffineGate::new(PoseidonParams::new(3).mds[0].clone());
+        let mul_row = builder.add_gate(Bn254MulGate, vec![]);
+        let affine_row = builder.add_gate(affine.clone(), vec![]);
+        let check = Bn254CheckGate::new(2);
+        let check_row = builder.add_gate(check, vec![]);
+        let data = builder.build::<C>();
+
+        let mut inputs = Vec::new();
+        for i in 0..NUM_LIMBS {
+            inputs.push(Target::wire(mul_row, Bn254MulGate::wire_a(i)));
+            inputs.push(Target::wire(mul_row, Bn254MulGate::wire_b(i)));
+            for term in 0..affine.coefficients().len() {
+                inputs.push(Target::wire(affine_row, affine.wire_input(term, i)));
+            }
+        }
+        for m in 0..NUM_LIMBS / 2 {
+            inputs.push(Target::wire(affine_row, affine.wire_constant(m)));
+        }
+        inputs.extend((0..NUM_LIMBS).map(|i| Target::wire(check_row, check.wire_remainder(i))));
+        inputs.extend((0..2).map(|j| Target::wire(check_row, check.wire_wide(j))));
+        let mut pw = PartialWitness::new();
+        for (k, &input) in inputs.iter().enumerate() {
+            pw.set_target(input, F::from_canonical_usize(k % 7)).unwrap();
+        }
+        let proof = data.prove(pw).unwrap();
+
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let files = [
+            (COMMON_CIRCUIT_DATA_FILE, to_gnark_json(&data.common).unwrap()),
+            (VERIFIER_ONLY_CIRCUIT_DATA_FILE, to_gnark_json(&data.verifier_only).unwrap()),
+            (PROOF_WITH_PUBLIC_INPUTS_FILE, to_gnark_json(&proof).unwrap()),
+        ];
+        for (name, json) in files {
+            fs::write(dir.join(name), json.to_string()).unwrap();
+        }
+        assert_imported(dir, &data, &proof);
+    }
+
+    #[test]
+    fn tampered_public_input_rejected() {
+        let (data, proof) = add_circuit();
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        save_files(&data, &proof, dir).unwrap();
+        let path = dir.join(PROOF_WITH_PUBLIC_INPUTS_FILE);
+        let mut json: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        json["public_inputs"][0] = Value::from(14u64);
+        fs::write(&path, json.to_string()).unwrap();
+        assert!(verify_files::<C, D>(dir).is_err());
+    }
+
+    #[test]
+    fn unpack_hash_inverts_packing() {
+        let limbs = [1u64, 0, 0xFFFFFFFF00000000, 42];
+        let json = serde_json::json!({ "elements": limbs });
+        let packed = serialize_with_key_path(json.clone(), vec!["circuit_digest".to_string()]);
+        assert_eq!(unpack_hash(packed.as_str().unwrap()).unwrap(), json);
+        assert!(unpack_hash("not a number").is_err());
+    }
+}
@@ -0,0 +1,277 @@
+//! Incrementally verifiable computation over trie updates: one proof that a chain of write
+//! batches turned an initial trie root into the current one.
+//!
+//! The step circuit verifies the previous step's proof with plonky2's cyclic recursion, so every
+//! step is proven by the same circuit, then applies up to `batch_size` writes to the previous
+//! root. Its public inputs are the initial root (4), the current root (4) and the number of
+//! steps, followed by the circuit's own verifier data, which cyclic recursion requires. The first
+//! step verifies a dummy proof instead, and its current root starts from the initial root.
+
+use anyhow::{Context, ensure};
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::field::types::{Field, PrimeField64};
+use plonky2::gates::noop::NoopGate;
+use plonky2::hash::hash_types::{HashOut, HashOutTarget};
+use plonky2::iop::target::BoolTarget;
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{
+    CircuitConfig, CircuitData, CommonCircuitData, VerifierCircuitTarget,
+};
+use plonky2::plonk::config::PoseidonGoldilocksConfig;
+use plonky2::plonk::proof::{ProofWithPublicInputs, ProofWithPublicInputsTarget};
+use plonky2::recursion::cyclic_recursion::check_cyclic_proof_verifier_data;
+use plonky2::recursion::dummy_circuit::cyclic_base_proof;
+
+use crate::trie::{TrieConfig, TrieUpdate, TrieUpdateTarget, select_hash};
+
+const D: usize = 2;
+type F = GoldilocksField;
+type C = PoseidonGoldilocksConfig;
+
+/// Settings of a `TrieIvc`.
+#[derive(Clone, Debug)]
+pub struct IvcConfig {
+    pub trie: TrieConfig,
+    /// Maximum number of writes applied by one step.
+    pub batch_size: usize,
+    /// Circuit config of the step circuit.
+    pub circuit_config: CircuitConfig,
+    /// Degree of the step circuit. It must fit the verifier of its own proofs and the writes of
+    /// one batch, and building panics if it does not.
+    pub degree_bits: usize,
+}
+
+impl IvcConfig {
+    pub fn new(trie: TrieConfig, batch_size: usize) -> Self {
+        Self {
+            trie,
+            batch_size,
+            circuit_config: CircuitConfig::standard_recursion_config(),
+            degree_bits: 13,
+        }
+    }
+}
+
+/// The statement of a step proof.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IvcState {
+    pub initial_root: HashOut<F>,
+    pub current_root: HashOut<F>,
+    pub steps: u64,
+}
+
+impl IvcState {
+    fn from_public_inputs(public_inputs: &[F]) -> Self {
+        Self {
+            initial_root: HashOut::from_vec(public_inputs[0..4].to_vec()),
+            current_root: HashOut::from_vec(public_inputs[4..8].to_vec()),
+            steps: public_inputs[8].to_canonical_u64(),
+        }
+    }
+}
+
+/// The step circuit, and the targets assigned by the prover.
+pub struct TrieIvc {
+    config: IvcConfig,
+    data: CircuitData<F, C, D>,
+    has_previous: BoolTarget,
+    previous: ProofWithPublicInputsTarget<D>,
+    verifier_data: VerifierCircuitTarget,
+    /// Whether each write of the batch is applied, and its targets.
+    updates: Vec<(BoolTarget, TrieUpdateTarget)>,
+}
+
+impl TrieIvc {
+    pub fn new(config: IvcConfig) -> anyhow::Result<Self> {
+        ensure!(
+            config.batch_size > 0,
+            "a step must allow at least one write"
+        );
+        let mut builder = CircuitBuilder::<F, D>::new(config.circuit_config.clone());
+        let one = builder.one();
+        let initial_root = builder.add_virtual_hash();
+        builder.register_public_inputs(&initial_root.elements);
+        let current_root = builder.add_virtual_hash();
+        builder.register_public_inputs(&current_root.elements);
+        let steps = builder.add_virtual_public_input();
+
+        let mut common = cyclic_common_data(&config.circuit_config, config.degree_bits);
+        let verifier_data = builder.add_verifier_data_public_inputs();
+        common.num_public_inputs = builder.num_public_inputs();
+
+        let has_previous = builder.add_virtual_bool_target_safe();
+        let previous = builder.add_virtual_proof_with_pis(&common);
+        let previous_initial_root = HashOutTarget::from_vec(previous.public_inputs[0..4].to_vec());
+        let previous_root = HashOutTarget::from_vec(previous.public_inputs[4..8].to_vec());
+        let previous_steps = previous.public_inputs[8];
+
+        // Without a previous proof, the initial root is only fixed by the dummy proof.
+        builder.connect_hashes(initial_root, previous_initial_root);
+        let mut root = select_hash(&mut builder, has_previous, previous_root, initial_root);
+        let updates = (0..config.batch_size)
+            .map(|_| {
+                let enabled = builder.add_virtual_bool_target_safe();
+                let update = TrieUpdateTarget::new(config.trie, &mut builder);
+                for (&old, &expected) in update.old_root.elements.iter().zip(&root.elements) {
+                    let diff = builder.sub(old, expected);
+                    let diff = builder.mul(enabled.target, diff);
+                    builder.assert_zero(diff);
+                }
+                root = select_hash(&mut builder, enabled, update.new_root, root);
+                (enabled, update)
+            })
+            .collect();
+        builder.connect_hashes(current_root, root);
+        let next_steps = builder.mul_add(has_previous.target, previous_steps, one);
+        builder.connect(steps, next_steps);
+
+        builder.conditionally_verify_cyclic_proof_or_dummy::<C>(
+            has_previous,
+            &previous,
+            &common,
+        )?;
+        Ok(Self {
+            config,
+            data: builder.build::<C>(),
+            has_previous,
+            previous,
+            verifier_data,
+            updates,
+        })
+    }
+
+    pub fn circuit(&self) -> &CircuitData<F, C, D> {
+        &self.data
+    }
+
+    /// Proves the first step, applying `updates` to `initial_root`.
+    pub fn prove_first(
+        &self,
+        initial_root: HashOut<F>,
+        updates: &[TrieUpdate<F>],
+    ) -> anyhow::Result<ProofWithPublicInputs<F, C, D>> {
+        let public_inputs = initial_root.elements.into_iter().enumerate().collect();
+        let base = cyclic_base_proof(&self.data.common, &self.data.verifier_only, public_inputs);
+        self.prove_step(false, &base, updates)
+    }
+
+    /// Proves the step after `previous`, applying `updates` to its current root.
+    pub fn prove_next(
+        &self,
+        previous: &ProofWithPublicInputs<F, C, D>,
+        updates: &[TrieUpdate<F>],
+    ) -> anyhow::Result<ProofWithPublicInputs<F, C, D>> {
+        self.verify(previous)
+            .context("the previous step does not verify")?;
+        self.prove_step(true, previous, updates)
+    }
+
+    /// Verifies a step proof, including that it was proven by this circuit, and returns its
+    /// statement.
+    pub fn verify(&self, proof: &ProofWithPublicInputs<F, C, D>) -> anyhow::Result<IvcState> {
+        check_cyclic_proof_verifier_data(proof, &self.data.verifier_only, &self.data.common)?;
+        self.data.verify(proof.clone())?;
+        Ok(IvcState::from_public_inputs(&proof.public_inputs))
+    }
+
+    fn prove_step(
+        &self,
+        has_previous: bool,
+        previous: &ProofWithPublicInputs<F, C, D>,
+        updates: &[TrieUpdate<F>],
+    ) -> anyhow::Result<ProofWithPublicInputs<F, C, D>> {
+        ensure!(
+            updates.len() <= self.config.batch_size,
+            "a step applies at most {} writes, got {}",
+            self.config.batch_size,
+            updates.len()
+        );
+        let mut pw = PartialWitness::new();
+        pw.set_bool_target(self.has_previous, has_previous)?;
+        pw.set_proof_with_pis_target(&self.previous, previous)?;
+        pw.set_verifier_data_target(&self.verifier_data, &self.data.verifier_only)?;
+        // Unused slots get an arbitrary write, which is not applied.
+        let unused = TrieUpdate {
+            key: vec![F::ZERO; self.config.trie.key_width],
+            old_value_hash: None,
+            new_value_hash: HashOut::ZERO,
+            siblings: vec![HashOut::ZERO; self.config.trie.depth],
+        };
+        for (i, (enabled, target)) in self.updates.iter().enumerate() {
+            pw.set_bool_target(*enabled, i < updates.len())?;
+            target.set_witness(&mut pw, updates.get(i).unwrap_or(&unused))?;
+        }
+        self.data.prove(pw)
+    }
+}
+
+/// Common data of a circuit that verifies a recursive proof, padded to `degree_bits`. The step
+/// circuit must have exactly this shape for its proofs to verify themselves, as in plonky2's own
+/// cyclic recursion tests.
+fn cyclic_common_data(config: &CircuitConfig, degree_bits: usize) -> CommonCircuitData<F, D> {
+    let data = CircuitBuilder::<F, D>::new(config.clone()).build::<C>();
+    let mut common = data.common;
+    for padded in [false, true] {
+        let mut builder = CircuitBuilder::<F, D>::new(config.clone());
+        let proof = builder.add_virtual_proof_with_pis(&common);
+        let verifier_data = builder.add_virtual_verifier_data(common.config.fri_config.cap_height);
+        builder.verify_proof::<C>(&proof, &verifier_data, &common);
+        if padded {
+            while builder.num_gates() < 1 << (degree_bits - 1) {
+                builder.add_gate(NoopGate, vec![]);
+            }
+        }
+        common = builder.build::<C>().common;
+    }
+    common
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trie::Trie;
+    use plonky2::hash::poseidon::PoseidonHash;
+    use plonky2::plonk::config::Hasher;
+
+    fn value(x: u64) -> HashOut<F> {
+        PoseidonHash::hash_no_pad(&[F::from_canonical_u64(x)])
+    }
+
+    #[test]
+    fn chained_trie_updates() {
+        let config = TrieConfig::new(16, 1);
+        let ivc = TrieIvc::new(IvcConfig::new(config, 2)).unwrap();
+        let mut trie = Trie::<F>::new(config);
+        let initial_root = trie.root();
+        let mut write = |key: u64, x: u64| trie.update(&[F::from_canonical_u64(key)], value(x));
+
+        // Two inserts, then an update and an insert, then a single update.
+        let first = [write(5, 1).unwrap(), write(9, 2).unwrap()];
+        let second = [write(5, 3).unwrap(), write(300, 4).unwrap()];
+        let third = [write(9, 5).unwrap()];
+        let mut proof = ivc.prove_first(initial_root, &first).unwrap();
+        for batch in [&second[..], &third[..]] {
+            proof = ivc.prove_next(&proof, batch).unwrap();
+        }
+        let state = ivc.verify(&proof).unwrap();
+        assert_eq!(
+            state,
+            IvcState {
+                initial_root,
+                current_root: trie.root(),
+                steps: 3,
+            }
+        );
+
+        // A write against a root other than the proven one does not yield a valid step.
+        trie.insert(&[F::from_canonical_u64(5)], value(6)).unwrap();
+        let stale = trie.update(&[F::from_canonical_u64(7)], value(7)).unwrap();
+        let result = ivc
+            .prove_next(&proof, &[stale])
+            .and_then(|proof| ivc.verify(&proof));
+        assert!(result.is_err());
+        let too_many = std::iter::repeat_n(third[0].clone(), 3).collect::<Vec<_>>();
+        assert!(ivc.prove_next(&proof, &too_many).is_err());
+    }
+}
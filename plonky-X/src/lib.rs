@@ -1 +1,11 @@
+pub mod aggregate;
+pub mod compress;
+pub mod export;
+pub mod import;
+pub mod ivc;
 mod poseidon;
+pub mod poseidon_bn128;
+pub mod r1cs;
+pub mod trie;
+pub mod validate;
+pub mod wrap;
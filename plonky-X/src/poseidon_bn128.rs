@@ -0,0 +1,277 @@
+//! Poseidon over BN254 (a.k.a. BN128) as the Merkle and Fiat-Shamir hasher of the outermost
+//! plonky2 proof, matching the `BN254Chip` of gnark-plonky2-verifier so that the gnark circuit
+//! can recompute every digest cheaply.
+//!
+//! The permutation is circomlib's Poseidon with `t = 4`. Goldilocks inputs are packed three per
+//! BN254 element (`Σ xᵢ·2^(64i)`) into the three rate lanes, overwriting them, and the digest is
+//! the first lane. The challenger keeps plonky2's Goldilocks Poseidon sponge and observes a
+//! digest as five Goldilocks elements of at most 56 bits.
+
+use ark_bn254::Fr;
+use num_bigint::BigUint;
+use plonky2::field::extension::quadratic::QuadraticExtension;
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::hash::hash_types::RichField;
+use plonky2::hash::poseidon::{PoseidonHash, PoseidonPermutation};
+use plonky2::plonk::config::{GenericConfig, GenericHashOut, Hasher};
+use plonky2_bn254_poseidon::arithmetic::modulus;
+use plonky2_bn254_poseidon::convert::{
+    MAX_INJECTIVE_GOLDILOCKS, NUM_BYTES, fr_to_bytes_le, goldilocks_to_fr,
+};
+use plonky2_bn254_poseidon::native::PoseidonNative;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::marker::PhantomData;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+/// State width of the BN254 permutation: one capacity lane and three rate lanes.
+pub const SPONGE_WIDTH: usize = 4;
+/// Number of BN254 rate lanes.
+pub const SPONGE_RATE: usize = SPONGE_WIDTH - 1;
+/// Bytes of a digest packed into each Goldilocks element observed by the challenger.
+const BYTES_PER_ELEMENT: usize = 7;
+
+fn permute(state: &mut [Fr; SPONGE_WIDTH]) {
+    static POSEIDON: OnceLock<PoseidonNative> = OnceLock::new();
+    POSEIDON
+        .get_or_init(|| PoseidonNative::new(SPONGE_WIDTH))
+        .permute(state);
+}
+
+/// A BN254 Poseidon digest, serialized as a single decimal string like the gnark verifier's
+/// JSON expects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoseidonBN128HashOut<F> {
+    pub value: Fr,
+    _phantom: PhantomData<F>,
+}
+
+impl<F> PoseidonBN128HashOut<F> {
+    pub fn new(value: Fr) -> Self {
+        Self {
+            value,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<F: RichField> GenericHashOut<F> for PoseidonBN128HashOut<F> {
+    fn to_bytes(&self) -> Vec<u8> {
+        fr_to_bytes_le(&self.value).to_vec()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self::new(Fr::from(BigUint::from_bytes_le(bytes)))
+    }
+
+    /// Splits the little-endian bytes into 7-byte chunks, which are canonical Goldilocks
+    /// elements, as `BN254Chip::ToVec` does in gnark.
+    fn to_vec(&self) -> Vec<F> {
+        self.to_bytes()
+            .chunks(BYTES_PER_ELEMENT)
+            .map(|chunk| {
+                let mut bytes = [0u8; 8];
+                bytes[..chunk.len()].copy_from_slice(chunk);
+                F::from_canonical_u64(u64::from_le_bytes(bytes))
+            })
+            .collect()
+    }
+}
+
+impl<F> Serialize for PoseidonBN128HashOut<F> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&BigUint::from(self.value).to_string())
+    }
+}
+
+impl<'de, F> Deserialize<'de> for PoseidonBN128HashOut<F> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let decimal = String::deserialize(deserializer)?;
+        let value = BigUint::from_str(&decimal).map_err(D::Error::custom)?;
+        if value >= modulus() {
+            return Err(D::Error::custom(format!("{decimal} is not a BN254 scalar")));
+        }
+        Ok(Self::new(Fr::from(value)))
+    }
+}
+
+/// Poseidon over BN254 as a plonky2 `Hasher`, see the module documentation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoseidonBN128Hash;
+
+impl<F: RichField> Hasher<F> for PoseidonBN128Hash {
+    const HASH_SIZE: usize = NUM_BYTES;
+    type Hash = PoseidonBN128HashOut<F>;
+    type Permutation = PoseidonPermutation<F>;
+
+    fn hash_no_pad(input: &[F]) -> Self::Hash {
+        let mut state = [Fr::from(0u64); SPONGE_WIDTH];
+        for rate_chunk in input.chunks(SPONGE_RATE * MAX_INJECTIVE_GOLDILOCKS) {
+            for (lane, chunk) in rate_chunk.chunks(MAX_INJECTIVE_GOLDILOCKS).enumerate() {
+                state[1 + lane] = goldilocks_to_fr(chunk);
+            }
+            permute(&mut state);
+        }
+        PoseidonBN128HashOut::new(state[0])
+    }
+
+    /// Packs up to three elements into a digest without hashing, like gnark's `HashOrNoop`.
+    /// plonky2's default would also skip hashing four elements, which do not fit in an `Fr`.
+    fn hash_or_noop(inputs: &[F]) -> Self::Hash {
+        if inputs.len() <= MAX_INJECTIVE_GOLDILOCKS {
+            PoseidonBN128HashOut::new(goldilocks_to_fr(inputs))
+        } else {
+            Self::hash_no_pad(inputs)
+        }
+    }
+
+    fn two_to_one(left: Self::Hash, right: Self::Hash) -> Self::Hash {
+        let zero = Fr::from(0u64);
+        let mut state = [zero, zero, left.value, right.value];
+        permute(&mut state);
+        PoseidonBN128HashOut::new(state[0])
+    }
+}
+
+/// Goldilocks config with BN254 Poseidon Merkle trees, for the outermost proof handed to
+/// gnark-plonky2-verifier. It cannot be verified recursively in plonky2.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct PoseidonBN128GoldilocksConfig;
+
+impl GenericConfig<2> for PoseidonBN128GoldilocksConfig {
+    type F = GoldilocksField;
+    type FE = QuadraticExtension<Self::F>;
+    type Hasher = PoseidonBN128Hash;
+    type InnerHasher = PoseidonHash;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::save_files;
+    use crate::import::verify_files;
+    use plonky2::field::types::{Field, PrimeField64};
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+
+    const D: usize = 2;
+    type C = PoseidonBN128GoldilocksConfig;
+    type F = GoldilocksField;
+    type H = PoseidonBN128Hash;
+
+    #[test]
+    fn matches_circomlib_poseidon() {
+        let poseidon = PoseidonNative::new(SPONGE_WIDTH);
+        let input = [1, 2, 3, 4].map(F::from_canonical_u64);
+        let packed = goldilocks_to_fr(&input[..3]);
+
+        // One rate chunk: the packed lanes are circomlib's poseidon inputs.
+        let expected = poseidon.hash(&[packed, Fr::from(4u64), Fr::from(0u64)]);
+        assert_eq!(<H as Hasher<F>>::hash_no_pad(&input).value, expected);
+        assert_eq!(<H as Hasher<F>>::hash_or_noop(&input).value, expected);
+        assert_eq!(<H as Hasher<F>>::hash_or_noop(&input[..3]).value, packed);
+
+        let left = PoseidonBN128HashOut::<F>::new(packed);
+        let right = PoseidonBN128HashOut::new(expected);
+        let expected = poseidon.hash(&[Fr::from(0u64), left.value, right.value]);
+        assert_eq!(H::two_to_one(left, right).value, expected);
+    }
+
+    fn fr(decimal: &str) -> Fr {
+        Fr::from_str(decimal).unwrap()
+    }
+
+    /// Known answers of gnark-plonky2-verifier's `BN254Chip`. The permutation of the zero state
+    /// is its BN254 Poseidon test vector and `poseidon([1, 2, 3])` is circomlib's; the other
+    /// digests come from an independent implementation of `HashNoPad`, `TwoToOne` and `ToVec`
+    /// over circomlibjs' `t = 4` constants.
+    #[test]
+    fn known_answer_vectors() {
+        let elements = |values: &[u64]| {
+            values
+                .iter()
+                .map(|&x| F::from_canonical_u64(x))
+                .collect::<Vec<_>>()
+        };
+        let zero = PoseidonBN128HashOut::<F>::new(Fr::from(0u64));
+        assert_eq!(
+            H::two_to_one(zero, zero).value,
+            fr("5317387130258456662214331362918410991734007599705406860481038345552731150762")
+        );
+        assert_eq!(
+            <H as Hasher<F>>::hash_no_pad(&elements(&[1, 0, 0, 2, 0, 0, 3])).value,
+            fr("6542985608222806190361240322586112750744169038454362455181422643027100751666")
+        );
+
+        // One rate chunk, with a partial second lane
+        let hash_4 = <H as Hasher<F>>::hash_no_pad(&elements(&[1, 2, 3, 4]));
+        assert_eq!(
+            hash_4.value,
+            fr("17325141721293886138598162299999592398376219788709495226321827842439572878705")
+        );
+        // Two rate chunks, the second overwriting one lane
+        let hash_10 = <H as Hasher<F>>::hash_no_pad(&elements(&(1..=10).collect::<Vec<_>>()));
+        assert_eq!(
+            hash_10.value,
+            fr("8457873961715067352609074430324166355017867662508198423831893048507154359754")
+        );
+        let max = F::NEG_ONE.to_canonical_u64();
+        assert_eq!(
+            <H as Hasher<F>>::hash_no_pad(&elements(&[max; 10])).value,
+            fr("7660092771883385635718893594388444511618714652134689537629508968345161886871")
+        );
+
+        assert_eq!(
+            H::two_to_one(hash_4, hash_10).value,
+            fr("11247441682611718718918390773289596662392574933663905289375273197297521395597")
+        );
+        assert_eq!(
+            hash_4.to_vec(),
+            elements(&[
+                57121309760419185,
+                59417362525052066,
+                4690894113042191,
+                24610940753472601,
+                642625222,
+            ])
+        );
+    }
+
+    #[test]
+    fn hash_out_encodings() {
+        let hash = <H as Hasher<F>>::hash_no_pad(&[F::ONE; 10]);
+        assert_eq!(PoseidonBN128HashOut::<F>::from_bytes(&hash.to_bytes()), hash);
+        let elements = hash.to_vec();
+        assert_eq!(elements.len(), 5);
+        assert!(elements.iter().all(|x| x.to_canonical_u64() < 1 << 56));
+
+        let json = serde_json::to_value(hash).unwrap();
+        assert_eq!(json, serde_json::Value::String(BigUint::from(hash.value).to_string()));
+        assert_eq!(serde_json::from_value::<PoseidonBN128HashOut<F>>(json).unwrap(), hash);
+        let modulus = modulus().to_string();
+        assert!(serde_json::from_value::<PoseidonBN128HashOut<F>>(modulus.into()).is_err());
+    }
+
+    #[test]
+    fn prove_export_and_import() {
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let input = builder.add_virtual_target();
+        let square = builder.mul(input, input);
+        builder.register_public_input(square);
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        pw.set_target(input, F::from_canonical_u64(5)).unwrap();
+        let proof = data.prove(pw).unwrap();
+        data.verify(proof.clone()).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        save_files(&data, &proof, dir).unwrap();
+        let json = std::fs::read_to_string(dir.join("verifier_only_circuit_data.json")).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert!(json["circuit_digest"].is_string());
+        assert_eq!(verify_files::<C, D>(dir).unwrap(), proof);
+    }
+}
@@ -0,0 +1,277 @@
+//! The BN128 Poseidon hasher of `poseidon_bn128` in the circuit, and openings of Merkle caps
+//! built with it, which is what every FRI query round checks against the caps of a proof.
+//! `MerkleCapOpeningCircuit` proves such an opening on its own with Groth16.
+
+use ark_bn254::Fr;
+use ark_r1cs_std::alloc::AllocVar;
+use ark_r1cs_std::eq::EqGadget;
+use ark_r1cs_std::fields::FieldVar;
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_r1cs_std::prelude::Boolean;
+use ark_r1cs_std::select::CondSelectGadget;
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError};
+use plonky2::field::types::PrimeField64;
+use plonky2::hash::merkle_proofs::MerkleProof;
+use plonky2::hash::merkle_tree::MerkleCap;
+use plonky2_bn254_poseidon::convert::{GOLDILOCKS_BITS, MAX_INJECTIVE_GOLDILOCKS};
+use plonky2_bn254_poseidon::params::{ALPHA, PoseidonParams};
+use std::sync::OnceLock;
+
+use super::goldilocks::{F, GoldilocksVar};
+use crate::poseidon_bn128::{PoseidonBN128Hash, SPONGE_RATE, SPONGE_WIDTH};
+
+fn poseidon_params() -> &'static PoseidonParams {
+    static PARAMS: OnceLock<PoseidonParams> = OnceLock::new();
+    PARAMS.get_or_init(|| PoseidonParams::new(SPONGE_WIDTH))
+}
+
+/// The BN254 Poseidon permutation of `PoseidonBN128Hash`.
+pub fn permute(state: &mut [FpVar<Fr>; SPONGE_WIDTH]) -> Result<(), SynthesisError> {
+    let params = poseidon_params();
+    for round in 0..params.rounds() {
+        for (x, c) in state.iter_mut().zip(&params.ark[round]) {
+            *x += *c;
+        }
+        if params.is_full_round(round) {
+            for x in state.iter_mut() {
+                *x = x.pow_by_constant([ALPHA])?;
+            }
+        } else {
+            state[0] = state[0].pow_by_constant([ALPHA])?;
+        }
+        let mixed = params
+            .mds
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .zip(state.iter())
+                    .map(|(m, x)| x * *m)
+                    .collect::<Vec<_>>()
+                    .iter()
+                    .sum()
+            })
+            .collect::<Vec<FpVar<Fr>>>();
+        state.clone_from_slice(&mixed);
+    }
+    Ok(())
+}
+
+/// Packs at most three elements into one BN254 element, like `goldilocks_to_fr`.
+fn pack(elements: &[GoldilocksVar]) -> Result<FpVar<Fr>, SynthesisError> {
+    let shift = Fr::from(1u128 << GOLDILOCKS_BITS);
+    elements
+        .iter()
+        .rev()
+        .try_fold(FpVar::zero(), |acc, element| {
+            Ok(acc * shift + element.canonical()?.fp())
+        })
+}
+
+/// `PoseidonBN128Hash::hash_no_pad` in the circuit.
+pub fn hash_no_pad(inputs: &[GoldilocksVar]) -> Result<FpVar<Fr>, SynthesisError> {
+    let mut state = [(); SPONGE_WIDTH].map(|_| FpVar::zero());
+    for rate_chunk in inputs.chunks(SPONGE_RATE * MAX_INJECTIVE_GOLDILOCKS) {
+        for (lane, chunk) in rate_chunk.chunks(MAX_INJECTIVE_GOLDILOCKS).enumerate() {
+            state[1 + lane] = pack(chunk)?;
+        }
+        permute(&mut state)?;
+    }
+    let [digest, ..] = state;
+    Ok(digest)
+}
+
+/// `PoseidonBN128Hash::hash_or_noop` in the circuit.
+pub fn hash_or_noop(inputs: &[GoldilocksVar]) -> Result<FpVar<Fr>, SynthesisError> {
+    if inputs.len() <= MAX_INJECTIVE_GOLDILOCKS {
+        pack(inputs)
+    } else {
+        hash_no_pad(inputs)
+    }
+}
+
+/// `PoseidonBN128Hash::two_to_one` in the circuit.
+pub fn two_to_one(left: &FpVar<Fr>, right: &FpVar<Fr>) -> Result<FpVar<Fr>, SynthesisError> {
+    let mut state = [FpVar::zero(), FpVar::zero(), left.clone(), right.clone()];
+    permute(&mut state)?;
+    let [digest, ..] = state;
+    Ok(digest)
+}
+
+/// Picks `values[index]`, with `index_bits` little-endian and `values.len() == 2^index_bits.len()`.
+fn select(values: &[FpVar<Fr>], index_bits: &[Boolean<Fr>]) -> Result<FpVar<Fr>, SynthesisError> {
+    let mut layer = values.to_vec();
+    for bit in index_bits {
+        layer = layer
+            .chunks(2)
+            .map(|pair| FpVar::conditionally_select(bit, &pair[1], &pair[0]))
+            .collect::<Result<_, _>>()?;
+    }
+    Ok(layer.remove(0))
+}
+
+/// plonky2's `verify_merkle_proof_to_cap` for `PoseidonBN128Hash` trees: the low
+/// `siblings.len()` bits of the leaf index order each pair of children, and the remaining bits
+/// select the element of `cap` the path must end at.
+pub fn verify_merkle_proof_to_cap(
+    leaf: &[GoldilocksVar],
+    index_bits: &[Boolean<Fr>],
+    cap: &[FpVar<Fr>],
+    siblings: &[FpVar<Fr>],
+) -> Result<(), SynthesisError> {
+    assert!(index_bits.len() >= siblings.len(), "not enough index bits");
+    let cap_bits = &index_bits[siblings.len()..];
+    assert_eq!(
+        cap.len(),
+        1 << cap_bits.len(),
+        "the cap does not match the index bits"
+    );
+    let mut node = hash_or_noop(leaf)?;
+    for (bit, sibling) in index_bits.iter().zip(siblings) {
+        let left = FpVar::conditionally_select(bit, sibling, &node)?;
+        let right = FpVar::conditionally_select(bit, &node, sibling)?;
+        node = two_to_one(&left, &right)?;
+    }
+    select(cap, cap_bits)?.enforce_equal(&node)
+}
+
+/// Proves that a leaf opens against a Merkle cap of a `PoseidonBN128Hash` tree. The cap and
+/// leaf are public inputs, in that order, and the index and siblings are private.
+#[derive(Clone, Debug)]
+pub struct MerkleCapOpeningCircuit {
+    pub leaf: Vec<F>,
+    pub index: usize,
+    pub siblings: Vec<Fr>,
+    pub cap: Vec<Fr>,
+}
+
+impl MerkleCapOpeningCircuit {
+    pub fn new(
+        leaf: Vec<F>,
+        index: usize,
+        proof: &MerkleProof<F, PoseidonBN128Hash>,
+        cap: &MerkleCap<F, PoseidonBN128Hash>,
+    ) -> Self {
+        assert!(
+            cap.0.len().is_power_of_two(),
+            "the cap size must be a power of two"
+        );
+        Self {
+            leaf,
+            index,
+            siblings: proof.siblings.iter().map(|hash| hash.value).collect(),
+            cap: cap.0.iter().map(|hash| hash.value).collect(),
+        }
+    }
+
+    /// The Groth16 public inputs: the cap, then the leaf.
+    pub fn public_inputs(&self) -> Vec<Fr> {
+        let leaf = self.leaf.iter().map(|x| Fr::from(x.to_canonical_u64()));
+        self.cap.iter().copied().chain(leaf).collect()
+    }
+}
+
+impl ConstraintSynthesizer<Fr> for MerkleCapOpeningCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+        let cap = self
+            .cap
+            .iter()
+            .map(|x| FpVar::new_input(cs.clone(), || Ok(*x)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let leaf = self
+            .leaf
+            .iter()
+            .map(|x| GoldilocksVar::new_input(cs.clone(), || Ok(*x)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let num_bits = self.siblings.len() + self.cap.len().trailing_zeros() as usize;
+        let index_bits = (0..num_bits)
+            .map(|i| Boolean::new_witness(cs.clone(), || Ok((self.index >> i) & 1 == 1)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let siblings = self
+            .siblings
+            .iter()
+            .map(|x| FpVar::new_witness(cs.clone(), || Ok(*x)))
+            .collect::<Result<Vec<_>, _>>()?;
+        verify_merkle_proof_to_cap(&leaf, &index_bits, &cap, &siblings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ark_bn254::Bn254;
+    use ark_groth16::Groth16;
+    use ark_r1cs_std::R1CSVar;
+    use ark_relations::r1cs::ConstraintSystem;
+    use ark_snark::SNARK;
+    use ark_std::rand::SeedableRng;
+    use ark_std::rand::rngs::StdRng;
+    use plonky2::field::types::Sample;
+    use plonky2::hash::merkle_proofs::verify_merkle_proof_to_cap as verify_native;
+    use plonky2::hash::merkle_tree::MerkleTree;
+    use plonky2::plonk::config::Hasher;
+
+    type H = PoseidonBN128Hash;
+
+    /// About 1.5k constraints at the time of writing.
+    const MAX_OPENING_CONSTRAINTS: usize = 2_000;
+
+    fn witnesses(cs: &ConstraintSystemRef<Fr>, values: &[F]) -> Vec<GoldilocksVar> {
+        values
+            .iter()
+            .map(|x| GoldilocksVar::new_witness(cs.clone(), || Ok(*x)).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn poseidon_matches_native() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let values = F::rand_vec(10);
+        let inputs = witnesses(&cs, &values);
+        let hash = hash_no_pad(&inputs).unwrap();
+        assert_eq!(hash.value().unwrap(), H::hash_no_pad(&values).value);
+        let short = hash_or_noop(&inputs[..3]).unwrap();
+        assert_eq!(short.value().unwrap(), H::hash_or_noop(&values[..3]).value);
+        let native = H::two_to_one(H::hash_no_pad(&values), H::hash_or_noop(&values[..3]));
+        assert_eq!(
+            two_to_one(&hash, &short).unwrap().value().unwrap(),
+            native.value
+        );
+        assert!(cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn merkle_cap_opening_groth16() {
+        let leaves = (0..16).map(|_| F::rand_vec(5)).collect::<Vec<_>>();
+        let tree = MerkleTree::<F, H>::new(leaves.clone(), 1);
+        let index = 11;
+        let proof = tree.prove(index);
+        verify_native(leaves[index].clone(), index, &tree.cap, &proof).unwrap();
+        let circuit = MerkleCapOpeningCircuit::new(leaves[index].clone(), index, &proof, &tree.cap);
+
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        circuit.clone().generate_constraints(cs.clone()).unwrap();
+        assert!(cs.is_satisfied().unwrap());
+        // Five leaf elements hash in two permutations, then four levels and a cap lookup.
+        assert!(
+            cs.num_constraints() < MAX_OPENING_CONSTRAINTS,
+            "{}",
+            cs.num_constraints()
+        );
+
+        let mut tampered = circuit.clone();
+        tampered.siblings[0] += Fr::from(1u64);
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        tampered.generate_constraints(cs.clone()).unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+
+        let rng = &mut StdRng::seed_from_u64(0);
+        let (pk, vk) = Groth16::<Bn254>::circuit_specific_setup(circuit.clone(), rng).unwrap();
+        let public_inputs = circuit.public_inputs();
+        let groth16_proof = Groth16::<Bn254>::prove(&pk, circuit, rng).unwrap();
+        assert!(Groth16::<Bn254>::verify(&vk, &public_inputs, &groth16_proof).unwrap());
+
+        let mut wrong_leaf = public_inputs;
+        *wrong_leaf.last_mut().unwrap() += Fr::from(1u64);
+        assert!(!Groth16::<Bn254>::verify(&vk, &wrong_leaf, &groth16_proof).unwrap());
+    }
+}
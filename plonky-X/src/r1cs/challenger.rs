@@ -0,0 +1,171 @@
+//! plonky2's `Challenger` in the circuit: a duplex sponge over the Goldilocks Poseidon
+//! permutation which observes the BN128 digests of `PoseidonBN128GoldilocksConfig` through
+//! their `to_vec`.
+
+use ark_bn254::Fr;
+use ark_r1cs_std::ToBitsGadget;
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_relations::r1cs::SynthesisError;
+use plonky2::hash::poseidon::{SPONGE_RATE, SPONGE_WIDTH};
+
+use super::goldilocks::{ExtensionVar, GoldilocksVar};
+use super::poseidon::permute;
+
+/// Bits of a digest packed into each observed element, as in `PoseidonBN128HashOut::to_vec`.
+const BITS_PER_ELEMENT: usize = 56;
+
+#[derive(Clone, Debug)]
+pub struct ChallengerVar {
+    state: [GoldilocksVar; SPONGE_WIDTH],
+    input_buffer: Vec<GoldilocksVar>,
+    output_buffer: Vec<GoldilocksVar>,
+}
+
+impl Default for ChallengerVar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChallengerVar {
+    pub fn new() -> Self {
+        Self {
+            state: [(); SPONGE_WIDTH].map(|_| GoldilocksVar::zero()),
+            input_buffer: Vec::with_capacity(SPONGE_RATE),
+            output_buffer: Vec::with_capacity(SPONGE_RATE),
+        }
+    }
+
+    pub fn observe_element(&mut self, element: &GoldilocksVar) -> Result<(), SynthesisError> {
+        self.output_buffer.clear();
+        self.input_buffer.push(element.clone());
+        if self.input_buffer.len() == SPONGE_RATE {
+            self.duplexing()?;
+        }
+        Ok(())
+    }
+
+    pub fn observe_elements(&mut self, elements: &[GoldilocksVar]) -> Result<(), SynthesisError> {
+        elements
+            .iter()
+            .try_for_each(|element| self.observe_element(element))
+    }
+
+    pub fn observe_extension_elements(
+        &mut self,
+        elements: &[ExtensionVar],
+    ) -> Result<(), SynthesisError> {
+        elements
+            .iter()
+            .try_for_each(|element| self.observe_elements(&element.0))
+    }
+
+    /// Observes a BN128 digest as its little-endian bits in 56-bit chunks.
+    pub fn observe_hash(&mut self, hash: &FpVar<Fr>) -> Result<(), SynthesisError> {
+        let bits = hash.to_bits_le()?;
+        for chunk in bits.chunks(BITS_PER_ELEMENT) {
+            self.observe_element(&GoldilocksVar::from_le_bits(chunk)?)?;
+        }
+        Ok(())
+    }
+
+    pub fn observe_cap(&mut self, cap: &[FpVar<Fr>]) -> Result<(), SynthesisError> {
+        cap.iter().try_for_each(|hash| self.observe_hash(hash))
+    }
+
+    pub fn get_challenge(&mut self) -> Result<GoldilocksVar, SynthesisError> {
+        if !self.input_buffer.is_empty() || self.output_buffer.is_empty() {
+            self.duplexing()?;
+        }
+        Ok(self
+            .output_buffer
+            .pop()
+            .expect("the output buffer was just filled"))
+    }
+
+    pub fn get_n_challenges(&mut self, n: usize) -> Result<Vec<GoldilocksVar>, SynthesisError> {
+        (0..n).map(|_| self.get_challenge()).collect()
+    }
+
+    pub fn get_extension_challenge(&mut self) -> Result<ExtensionVar, SynthesisError> {
+        Ok(ExtensionVar([self.get_challenge()?, self.get_challenge()?]))
+    }
+
+    /// Overwrites the start of the state with the buffered inputs and permutes.
+    fn duplexing(&mut self) -> Result<(), SynthesisError> {
+        for (lane, input) in self.input_buffer.drain(..).enumerate() {
+            self.state[lane] = input;
+        }
+        permute(&mut self.state)?;
+        self.output_buffer = self.state[..SPONGE_RATE].to_vec();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ark_r1cs_std::alloc::AllocVar;
+    use ark_relations::r1cs::ConstraintSystem;
+    use plonky2::field::types::Sample;
+    use plonky2::hash::merkle_tree::MerkleCap;
+    use plonky2::iop::challenger::Challenger;
+    use plonky2::plonk::config::Hasher;
+
+    use crate::poseidon_bn128::PoseidonBN128Hash;
+    use crate::r1cs::goldilocks::{F, FE};
+
+    #[test]
+    fn challenger_matches_native() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let mut native = Challenger::<F, PoseidonBN128Hash>::new();
+        let mut challenger = ChallengerVar::new();
+
+        let digest = PoseidonBN128Hash::hash_no_pad(&F::rand_vec(4));
+        native.observe_cap::<PoseidonBN128Hash>(&MerkleCap(vec![digest]));
+        let digest = FpVar::new_witness(cs.clone(), || Ok(digest.value)).unwrap();
+        challenger.observe_cap(&[digest]).unwrap();
+
+        let elements = F::rand_vec(3);
+        native.observe_elements(&elements);
+        let vars = elements
+            .iter()
+            .map(|x| GoldilocksVar::new_witness(cs.clone(), || Ok(*x)).unwrap())
+            .collect::<Vec<_>>();
+        challenger.observe_elements(&vars).unwrap();
+        let challenges = challenger.get_n_challenges(3).unwrap();
+        let expected = native.get_n_challenges(3);
+        assert_eq!(
+            challenges
+                .iter()
+                .map(|x| x.value().unwrap())
+                .collect::<Vec<_>>(),
+            expected
+        );
+
+        let extension = FE::rand();
+        native.observe_extension_element::<2>(&extension);
+        let var = ExtensionVar::new_witness(cs.clone(), || Ok(extension)).unwrap();
+        challenger.observe_extension_elements(&[var]).unwrap();
+        let expected = native.get_extension_challenge::<2>();
+        assert_eq!(
+            challenger
+                .get_extension_challenge()
+                .unwrap()
+                .value()
+                .unwrap(),
+            expected
+        );
+        // The rest of the output buffer, then a fresh squeeze.
+        let expected = native.get_n_challenges(10);
+        let challenges = challenger.get_n_challenges(10).unwrap();
+        assert_eq!(
+            challenges
+                .iter()
+                .map(|x| x.value().unwrap())
+                .collect::<Vec<_>>(),
+            expected
+        );
+        assert!(cs.is_satisfied().unwrap());
+    }
+}
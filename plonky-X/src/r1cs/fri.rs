@@ -0,0 +1,318 @@
+//! plonky2's FRI verifier in the circuit: the challenges of the commit phase, the proof of work,
+//! and every query round from the initial Merkle openings down to the final polynomial.
+
+use ark_bn254::Fr;
+use ark_r1cs_std::alloc::AllocVar;
+use ark_r1cs_std::eq::EqGadget;
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_r1cs_std::prelude::Boolean;
+use ark_relations::r1cs::{ConstraintSystemRef, SynthesisError};
+use plonky2::field::types::Field;
+use plonky2::fri::FriParams;
+use plonky2::fri::proof::FriProof;
+
+use super::bn128::verify_merkle_proof_to_cap;
+use super::challenger::ChallengerVar;
+use super::gates::D;
+use super::goldilocks::{ExtensionVar, F, GoldilocksVar};
+use crate::poseidon_bn128::{PoseidonBN128Hash, PoseidonBN128HashOut};
+
+/// A Merkle cap, as the BN254 values of its digests.
+pub type CapVar = Vec<FpVar<Fr>>;
+
+/// The leaf of every initial tree at a query, with its Merkle siblings.
+#[derive(Clone, Debug)]
+pub struct FriQueryRoundVar {
+    pub initial_trees: Vec<(Vec<GoldilocksVar>, Vec<FpVar<Fr>>)>,
+    pub steps: Vec<(Vec<ExtensionVar>, Vec<FpVar<Fr>>)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FriProofVar {
+    pub commit_phase_caps: Vec<CapVar>,
+    pub query_rounds: Vec<FriQueryRoundVar>,
+    pub final_poly: Vec<ExtensionVar>,
+    pub pow_witness: GoldilocksVar,
+}
+
+impl FriProofVar {
+    pub fn new_witness(
+        cs: ConstraintSystemRef<Fr>,
+        proof: &FriProof<F, PoseidonBN128Hash, D>,
+    ) -> Result<Self, SynthesisError> {
+        let hashes = |hashes: &[PoseidonBN128HashOut<F>]| -> Result<Vec<_>, SynthesisError> {
+            hashes
+                .iter()
+                .map(|hash| FpVar::new_witness(cs.clone(), || Ok(hash.value)))
+                .collect()
+        };
+        let elements = |values: &[F]| -> Result<Vec<_>, SynthesisError> {
+            values
+                .iter()
+                .map(|x| GoldilocksVar::new_witness(cs.clone(), || Ok(*x)))
+                .collect()
+        };
+        let commit_phase_caps = proof
+            .commit_phase_merkle_caps
+            .iter()
+            .map(|cap| hashes(&cap.0))
+            .collect::<Result<_, _>>()?;
+        let query_rounds = proof
+            .query_round_proofs
+            .iter()
+            .map(|round| {
+                let initial_trees = round
+                    .initial_trees_proof
+                    .evals_proofs
+                    .iter()
+                    .map(|(evals, proof)| Ok((elements(evals)?, hashes(&proof.siblings)?)))
+                    .collect::<Result<_, SynthesisError>>()?;
+                let steps = round
+                    .steps
+                    .iter()
+                    .map(|step| {
+                        let evals = step
+                            .evals
+                            .iter()
+                            .map(|x| ExtensionVar::new_witness(cs.clone(), || Ok(*x)))
+                            .collect::<Result<_, _>>()?;
+                        Ok((evals, hashes(&step.merkle_proof.siblings)?))
+                    })
+                    .collect::<Result<_, SynthesisError>>()?;
+                Ok(FriQueryRoundVar {
+                    initial_trees,
+                    steps,
+                })
+            })
+            .collect::<Result<_, SynthesisError>>()?;
+        let final_poly = proof
+            .final_poly
+            .coeffs
+            .iter()
+            .map(|x| ExtensionVar::new_witness(cs.clone(), || Ok(*x)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            commit_phase_caps,
+            query_rounds,
+            final_poly,
+            pow_witness: GoldilocksVar::new_witness(cs, || Ok(proof.pow_witness))?,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FriChallengesVar {
+    pub alpha: ExtensionVar,
+    pub betas: Vec<ExtensionVar>,
+    pub pow_response: GoldilocksVar,
+    /// The little-endian bits of each query index, `lde_bits` of them.
+    pub query_index_bits: Vec<Vec<Boolean<Fr>>>,
+}
+
+/// `Challenger::fri_challenges`, after the openings were observed.
+pub fn fri_challenges(
+    challenger: &mut ChallengerVar,
+    proof: &FriProofVar,
+    params: &FriParams,
+) -> Result<FriChallengesVar, SynthesisError> {
+    let alpha = challenger.get_extension_challenge()?;
+    let betas = proof
+        .commit_phase_caps
+        .iter()
+        .map(|cap| {
+            challenger.observe_cap(cap)?;
+            challenger.get_extension_challenge()
+        })
+        .collect::<Result<_, _>>()?;
+    challenger.observe_extension_elements(&proof.final_poly)?;
+    challenger.observe_element(&proof.pow_witness)?;
+    let pow_response = challenger.get_challenge()?;
+    // The LDE size is a power of two, so the index is the low bits of the challenge.
+    let query_index_bits = (0..params.config.num_query_rounds)
+        .map(|_| {
+            let mut bits = challenger.get_challenge()?.to_canonical_bits()?;
+            bits.truncate(params.lde_bits());
+            Ok(bits)
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(FriChallengesVar {
+        alpha,
+        betas,
+        pow_response,
+        query_index_bits,
+    })
+}
+
+/// Polynomials opened at the same point, as `(oracle, polynomial)` indices, with their openings.
+/// A salt at the end of a leaf is never indexed, so it needs no special case.
+#[derive(Clone, Debug)]
+pub struct FriBatch {
+    pub point: ExtensionVar,
+    pub polynomials: Vec<(usize, usize)>,
+    pub values: Vec<ExtensionVar>,
+}
+
+/// The element of `items` at the little-endian `index_bits`.
+fn select(
+    items: &[ExtensionVar],
+    index_bits: &[Boolean<Fr>],
+) -> Result<ExtensionVar, SynthesisError> {
+    let mut layer = items.to_vec();
+    for bit in index_bits {
+        layer = layer
+            .chunks(2)
+            .map(|pair| ExtensionVar::select(bit, &pair[1], &pair[0]))
+            .collect::<Result<_, _>>()?;
+    }
+    Ok(layer.remove(0))
+}
+
+/// `Π select(bitᵢ, factorᵢ, 1)`.
+fn select_product(
+    bits: &[Boolean<Fr>],
+    factors: impl IntoIterator<Item = F>,
+) -> Result<GoldilocksVar, SynthesisError> {
+    bits.iter()
+        .zip(factors)
+        .try_fold(GoldilocksVar::one(), |acc, (bit, factor)| {
+            let factor = GoldilocksVar::select(
+                bit,
+                &GoldilocksVar::constant(factor),
+                &GoldilocksVar::one(),
+            )?;
+            acc.mul(&factor)?.reduce()
+        })
+}
+
+/// `Σ powersᵢ·valuesᵢ`, which is `ReducingFactor::reduce` with precomputed powers.
+fn reduce(
+    values: &[ExtensionVar],
+    powers: &[ExtensionVar],
+) -> Result<ExtensionVar, SynthesisError> {
+    let terms = values
+        .iter()
+        .zip(powers)
+        .map(|(value, power)| value.mul(power))
+        .collect::<Result<Vec<_>, _>>()?;
+    ExtensionVar::linear_combination(terms.iter().map(|term| (term, 1)))
+}
+
+/// `compute_evaluation`: interpolates the coset of `x` at `beta` from its evaluations in
+/// bit-reversed order, with the Lagrange basis of the subgroup `⟨g⟩` at `z = beta / coset_start`,
+/// `Lₖ(z) = (zⁿ - 1)·gᵏ / (n·(z - gᵏ))`.
+fn compute_evaluation(
+    x: &GoldilocksVar,
+    index_within_coset_bits: &[Boolean<Fr>],
+    evals: &[ExtensionVar],
+    beta: &ExtensionVar,
+) -> Result<ExtensionVar, SynthesisError> {
+    let arity_bits = index_within_coset_bits.len();
+    let arity = evals.len();
+    let g = F::primitive_root_of_unity(arity_bits);
+    // `coset_start = x·g^(-rev(index))`, with bit `i` of the index at `arity_bits - 1 - i`.
+    let g_inv = g.inverse();
+    let factors = (0..arity_bits).map(|i| g_inv.exp_power_of_2(arity_bits - 1 - i));
+    let coset_start = x
+        .mul(&select_product(index_within_coset_bits, factors)?)?
+        .reduce()?;
+    let z = beta.scalar_mul(&coset_start.inverse()?)?;
+    let mut sum = ExtensionVar::zero();
+    for (k, g_k) in g.powers().take(arity).enumerate() {
+        let value = &evals[k.reverse_bits() >> (usize::BITS as usize - arity_bits)];
+        let denominator = z.sub(&ExtensionVar::constant(g_k.into()))?;
+        let term = value.mul_constant(g_k)?.div(&denominator)?;
+        sum = sum.add(&term)?;
+    }
+    let vanishing = z.exp_power_of_2(arity_bits)?.sub(&ExtensionVar::one())?;
+    vanishing
+        .mul(&sum)?
+        .mul_constant(F::from_canonical_usize(arity).inverse())
+}
+
+/// `verify_fri_proof` for the openings of `batches` against the caps of the initial trees.
+/// The shape of the proof is checked before the circuit is built.
+pub fn verify_fri_proof(
+    params: &FriParams,
+    batches: &[FriBatch],
+    initial_caps: &[CapVar],
+    challenges: &FriChallengesVar,
+    proof: &FriProofVar,
+) -> Result<(), SynthesisError> {
+    // plonky2 checks the leading zeros of the response; the order is just below 2^64.
+    let pow_bits = challenges.pow_response.to_canonical_bits()?;
+    let num_pow_bits = params.config.proof_of_work_bits as usize;
+    for bit in &pow_bits[pow_bits.len() - num_pow_bits..] {
+        bit.enforce_equal(&Boolean::FALSE)?;
+    }
+
+    let max_len = batches
+        .iter()
+        .map(|batch| batch.polynomials.len())
+        .max()
+        .unwrap_or(0);
+    let mut alpha_powers = vec![ExtensionVar::one()];
+    for i in 1..=max_len {
+        alpha_powers.push(alpha_powers[i - 1].mul(&challenges.alpha)?);
+    }
+    let reduced_openings = batches
+        .iter()
+        .map(|batch| reduce(&batch.values, &alpha_powers))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let lde_bits = params.lde_bits();
+    let omega = F::primitive_root_of_unity(lde_bits);
+    for (index_bits, round) in challenges.query_index_bits.iter().zip(&proof.query_rounds) {
+        for ((leaf, siblings), cap) in round.initial_trees.iter().zip(initial_caps) {
+            verify_merkle_proof_to_cap(leaf, index_bits, cap, siblings)?;
+        }
+
+        // `subgroup_x = GENERATOR·ω^rev(index)`.
+        let factors = (0..lde_bits).map(|i| omega.exp_power_of_2(lde_bits - 1 - i));
+        let mut x = select_product(index_bits, factors)?
+            .mul_constant(F::MULTIPLICATIVE_GROUP_GENERATOR)?
+            .reduce()?;
+
+        // `fri_combine_initial`.
+        let x_ext = ExtensionVar::from_base(x.clone());
+        let mut old_eval = ExtensionVar::zero();
+        for (batch, reduced_opening) in batches.iter().zip(&reduced_openings) {
+            // The leaves are base field elements, so the powers only scale them.
+            let terms = batch
+                .polynomials
+                .iter()
+                .zip(&alpha_powers)
+                .map(|(&(oracle, polynomial), power)| {
+                    power.scalar_mul(&round.initial_trees[oracle].0[polynomial])
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let reduced_evals =
+                ExtensionVar::linear_combination(terms.iter().map(|term| (term, 1)))?;
+            let numerator = reduced_evals.sub(reduced_opening)?;
+            let quotient = numerator.div(&x_ext.sub(&batch.point)?)?;
+            old_eval = old_eval.mul(&alpha_powers[terms.len()])?.add(&quotient)?;
+        }
+
+        let mut index_bits = index_bits.as_slice();
+        for (i, &arity_bits) in params.reduction_arity_bits.iter().enumerate() {
+            let (evals, siblings) = &round.steps[i];
+            let (within_coset, coset_index) = index_bits.split_at(arity_bits);
+            select(evals, within_coset)?.enforce_equal(&old_eval)?;
+            old_eval = compute_evaluation(&x, within_coset, evals, &challenges.betas[i])?;
+            let leaf = evals
+                .iter()
+                .flat_map(|eval| eval.0.clone())
+                .collect::<Vec<_>>();
+            verify_merkle_proof_to_cap(&leaf, coset_index, &proof.commit_phase_caps[i], siblings)?;
+            x = x.exp_power_of_2(arity_bits)?.reduce()?;
+            index_bits = coset_index;
+        }
+
+        let final_eval = proof
+            .final_poly
+            .iter()
+            .rev()
+            .try_fold(ExtensionVar::zero(), |acc, c| acc.scalar_mul(&x)?.add(c))?;
+        final_eval.enforce_equal(&old_eval)?;
+    }
+    Ok(())
+}
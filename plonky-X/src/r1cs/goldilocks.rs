@@ -0,0 +1,587 @@
+//! Emulated Goldilocks arithmetic over BN254, and its quadratic extension.
+//!
+//! A `GoldilocksVar` is a BN254 variable congruent to the Goldilocks element it stands for,
+//! together with a bound on its bit length. Sums and products stay unreduced until the bound
+//! gets close to the BN254 modulus, so a Poseidon round or an extension product only pays for
+//! one range check per output.
+
+use ark_bn254::Fr;
+use ark_ff::{BigInteger, PrimeField};
+use ark_r1cs_std::R1CSVar;
+use ark_r1cs_std::alloc::{AllocVar, AllocationMode};
+use ark_r1cs_std::eq::EqGadget;
+use ark_r1cs_std::fields::FieldVar;
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_r1cs_std::prelude::Boolean;
+use ark_r1cs_std::select::CondSelectGadget;
+use ark_relations::r1cs::{Namespace, SynthesisError};
+use num_bigint::BigUint;
+use num_traits::{One, ToPrimitive};
+use plonky2::field::extension::quadratic::QuadraticExtension;
+use plonky2::field::extension::{Extendable, FieldExtension};
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::field::types::{Field, Field64, PrimeField64};
+use plonky2_bn254_poseidon::convert::GOLDILOCKS_BITS;
+use std::borrow::Borrow;
+
+pub type F = GoldilocksField;
+pub type FE = QuadraticExtension<F>;
+
+/// Values stay below `2^MAX_BITS`, so that reducing one with a quotient below
+/// `2^(MAX_BITS - 63)` cannot wrap around the BN254 modulus.
+pub(crate) const MAX_BITS: usize = 252;
+/// Products leave room for a few additions before they have to be reduced.
+const MAX_PRODUCT_BITS: usize = MAX_BITS - 8;
+
+/// Little-endian bits of `x`, constraining it to fit in `num_bits` bits.
+pub(crate) fn to_bits(x: &FpVar<Fr>, num_bits: usize) -> Result<Vec<Boolean<Fr>>, SynthesisError> {
+    if let FpVar::Constant(constant) = x {
+        let bigint = constant.into_bigint();
+        if bigint.num_bits() as usize > num_bits {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+        return Ok((0..num_bits)
+            .map(|i| Boolean::constant(bigint.get_bit(i)))
+            .collect());
+    }
+    let value = x.value().map(|value| value.into_bigint());
+    let bits = (0..num_bits)
+        .map(|i| Boolean::new_witness(x.cs(), || value.map(|value| value.get_bit(i))))
+        .collect::<Result<Vec<_>, _>>()?;
+    Boolean::le_bits_to_fp_var(&bits)?.enforce_equal(x)?;
+    Ok(bits)
+}
+
+/// Constrains the 64 little-endian `bits` to a value below the Goldilocks order
+/// `2^64 - 2^32 + 1`: if the high half is all ones, the low half must be zero.
+pub(crate) fn enforce_canonical(bits: &[Boolean<Fr>]) -> Result<(), SynthesisError> {
+    assert_eq!(bits.len(), GOLDILOCKS_BITS);
+    let high_is_max = Boolean::kary_and(&bits[32..])?;
+    let low = Boolean::le_bits_to_fp_var(&bits[..32])?;
+    low.conditional_enforce_equal(&FpVar::zero(), &high_is_max)
+}
+
+fn order() -> BigUint {
+    BigUint::from(F::ORDER)
+}
+
+/// An emulated Goldilocks element: a BN254 variable below `2^bits` congruent to the element.
+#[derive(Clone, Debug)]
+pub struct GoldilocksVar {
+    value: FpVar<Fr>,
+    bits: usize,
+    /// Whether `value` is known to be below the Goldilocks order.
+    canonical: bool,
+}
+
+impl GoldilocksVar {
+    pub fn constant(value: F) -> Self {
+        let value = value.to_canonical_u64();
+        Self {
+            value: FpVar::constant(Fr::from(value)),
+            bits: (u64::BITS - value.leading_zeros()) as usize,
+            canonical: true,
+        }
+    }
+
+    pub fn zero() -> Self {
+        Self::constant(F::ZERO)
+    }
+
+    pub fn one() -> Self {
+        Self::constant(F::ONE)
+    }
+
+    /// Wraps `value < 2^bits`, folding constants back to their canonical value.
+    fn unreduced(value: FpVar<Fr>, bits: usize) -> Self {
+        assert!(bits <= MAX_BITS, "{bits} bits overflow the emulation");
+        match value {
+            FpVar::Constant(constant) => {
+                let value = BigUint::from(constant) % order();
+                Self::constant(F::from_canonical_u64(value.to_u64().unwrap()))
+            }
+            value => Self {
+                value,
+                bits,
+                canonical: bits < GOLDILOCKS_BITS,
+            },
+        }
+    }
+
+    /// The element with the little-endian `bits`, at most 63 of them so it is canonical.
+    pub(crate) fn from_le_bits(bits: &[Boolean<Fr>]) -> Result<Self, SynthesisError> {
+        assert!(bits.len() < GOLDILOCKS_BITS);
+        Ok(Self::unreduced(
+            Boolean::le_bits_to_fp_var(bits)?,
+            bits.len(),
+        ))
+    }
+
+    /// A bound on the bit length of the BN254 variable.
+    pub(crate) fn bits(&self) -> usize {
+        self.bits
+    }
+
+    /// The BN254 variable, which is only the canonical value if `canonical` was called.
+    pub(crate) fn fp(&self) -> &FpVar<Fr> {
+        &self.value
+    }
+
+    pub fn add(&self, other: &Self) -> Result<Self, SynthesisError> {
+        Self::linear_combination([(self, 1), (other, 1)])
+    }
+
+    pub fn sub(&self, other: &Self) -> Result<Self, SynthesisError> {
+        let (a, b) = (self.fit_bits(MAX_BITS - 1)?, other.fit_bits(MAX_BITS - 2)?);
+        // A multiple of the order above `b` keeps the difference non-negative.
+        let offset = ((BigUint::one() << b.bits) + order() - 1u32) / order() * order();
+        let bound = (BigUint::one() << a.bits) + &offset;
+        let value = &a.value + Fr::from(offset) - &b.value;
+        Ok(Self::unreduced(value, bound.bits() as usize))
+    }
+
+    pub fn neg(&self) -> Result<Self, SynthesisError> {
+        Self::zero().sub(self)
+    }
+
+    pub fn mul(&self, other: &Self) -> Result<Self, SynthesisError> {
+        let (a, b) = Self::fit(self, other, MAX_PRODUCT_BITS)?;
+        Ok(Self::unreduced(&a.value * &b.value, a.bits + b.bits))
+    }
+
+    pub fn mul_constant(&self, constant: F) -> Result<Self, SynthesisError> {
+        Self::linear_combination([(self, constant.to_canonical_u64())])
+    }
+
+    /// `Σ cᵢ·xᵢ` without reducing, unless the sum could overflow the emulation.
+    pub fn linear_combination<'a>(
+        terms: impl IntoIterator<Item = (&'a Self, u64)>,
+    ) -> Result<Self, SynthesisError> {
+        let mut terms = terms
+            .into_iter()
+            .filter(|(_, c)| *c != 0)
+            .map(|(x, c)| (x.clone(), c))
+            .collect::<Vec<_>>();
+        let bound = |terms: &[(Self, u64)]| {
+            terms
+                .iter()
+                .map(|(x, c)| (BigUint::one() << x.bits) * *c)
+                .sum::<BigUint>()
+                .bits() as usize
+        };
+        if bound(&terms) > MAX_BITS {
+            for (x, _) in terms.iter_mut() {
+                *x = x.reduce()?;
+            }
+        }
+        let bits = bound(&terms);
+        let value = terms
+            .iter()
+            .map(|(x, c)| &x.value * Fr::from(*c))
+            .fold(FpVar::zero(), |acc, term| acc + term);
+        Ok(Self::unreduced(value, bits))
+    }
+
+    /// Brings `a` and `b` under `budget` bits together, reducing the larger one first.
+    fn fit(a: &Self, b: &Self, budget: usize) -> Result<(Self, Self), SynthesisError> {
+        let (mut a, mut b) = (a.clone(), b.clone());
+        while a.bits + b.bits > budget {
+            if a.bits >= b.bits {
+                a = a.reduce()?;
+            } else {
+                b = b.reduce()?;
+            }
+        }
+        Ok((a, b))
+    }
+
+    fn fit_bits(&self, budget: usize) -> Result<Self, SynthesisError> {
+        if self.bits > budget {
+            self.reduce()
+        } else {
+            Ok(self.clone())
+        }
+    }
+
+    /// The element as a variable below `2^64`.
+    pub fn reduce(&self) -> Result<Self, SynthesisError> {
+        if self.bits <= GOLDILOCKS_BITS {
+            return Ok(self.clone());
+        }
+        Ok(self.divide_by_order(false)?.0)
+    }
+
+    /// The element as its canonical value.
+    pub fn canonical(&self) -> Result<Self, SynthesisError> {
+        if self.canonical {
+            return Ok(self.clone());
+        }
+        Ok(self.divide_by_order(true)?.0)
+    }
+
+    /// The 64 little-endian bits of the canonical value.
+    pub fn to_canonical_bits(&self) -> Result<Vec<Boolean<Fr>>, SynthesisError> {
+        if self.canonical {
+            return to_bits(&self.value, GOLDILOCKS_BITS);
+        }
+        Ok(self.divide_by_order(true)?.1)
+    }
+
+    /// Witnesses `value = q·p + r` with `q < 2^(bits - 63)` and `r < 2^64`, or `r < p` if
+    /// `canonical`, which is exact in BN254 since `bits <= MAX_BITS`.
+    fn divide_by_order(&self, canonical: bool) -> Result<(Self, Vec<Boolean<Fr>>), SynthesisError> {
+        let cs = self.value.cs();
+        let value = self.value.value().map(BigUint::from);
+        let quotient = FpVar::new_witness(cs.clone(), || {
+            value.clone().map(|value| Fr::from(value / order()))
+        })?;
+        let remainder = FpVar::new_witness(cs, || value.map(|value| Fr::from(value % order())))?;
+        to_bits(&quotient, self.bits.saturating_sub(GOLDILOCKS_BITS - 1))?;
+        let bits = to_bits(&remainder, GOLDILOCKS_BITS)?;
+        if canonical {
+            enforce_canonical(&bits)?;
+        }
+        (quotient * Fr::from(F::ORDER) + &remainder).enforce_equal(&self.value)?;
+        let remainder = Self {
+            value: remainder,
+            bits: GOLDILOCKS_BITS,
+            canonical,
+        };
+        Ok((remainder, bits))
+    }
+
+    pub fn enforce_equal(&self, other: &Self) -> Result<(), SynthesisError> {
+        if self.canonical && other.canonical {
+            return self.value.enforce_equal(&other.value);
+        }
+        let difference = self.sub(other)?;
+        if let FpVar::Constant(constant) = difference.value {
+            return match constant == Fr::from(0u64) {
+                true => Ok(()),
+                false => Err(SynthesisError::Unsatisfiable),
+            };
+        }
+        // The difference is a multiple of the order below `2^bits`.
+        let value = difference.value.value().map(BigUint::from);
+        let multiple = FpVar::new_witness(difference.value.cs(), || {
+            value.map(|value| Fr::from(value / order()))
+        })?;
+        to_bits(
+            &multiple,
+            difference.bits.saturating_sub(GOLDILOCKS_BITS - 1),
+        )?;
+        (multiple * Fr::from(F::ORDER)).enforce_equal(&difference.value)
+    }
+
+    /// `yes` if `bit` is set, else `no`.
+    pub fn select(bit: &Boolean<Fr>, yes: &Self, no: &Self) -> Result<Self, SynthesisError> {
+        let value = FpVar::conditionally_select(bit, &yes.value, &no.value)?;
+        let canonical = yes.canonical && no.canonical;
+        Ok(Self {
+            canonical,
+            ..Self::unreduced(value, yes.bits.max(no.bits))
+        })
+    }
+
+    /// The inverse, or an unsatisfiable constraint for zero.
+    pub fn inverse(&self) -> Result<Self, SynthesisError> {
+        if self.value.is_constant() {
+            let value = self.value()?;
+            return value
+                .try_inverse()
+                .map(Self::constant)
+                .ok_or(SynthesisError::Unsatisfiable);
+        }
+        let value = self.value();
+        let inverse = Self::new_witness(self.value.cs(), || {
+            value.map(|value| value.try_inverse().unwrap_or(F::ZERO))
+        })?;
+        inverse.mul(self)?.enforce_equal(&Self::one())?;
+        Ok(inverse)
+    }
+
+    pub fn exp_power_of_2(&self, power_log: usize) -> Result<Self, SynthesisError> {
+        (0..power_log).try_fold(self.clone(), |x, _| x.mul(&x))
+    }
+
+    pub fn value(&self) -> Result<F, SynthesisError> {
+        let value = BigUint::from(self.value.value()?) % order();
+        Ok(F::from_canonical_u64(value.to_u64().unwrap()))
+    }
+}
+
+impl AllocVar<F, Fr> for GoldilocksVar {
+    /// Allocates the canonical value.
+    fn new_variable<T: Borrow<F>>(
+        cs: impl Into<Namespace<Fr>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let value = f().map(|value| *value.borrow());
+        if mode == AllocationMode::Constant {
+            return Ok(Self::constant(value?));
+        }
+        let value = value.map(|value| Fr::from(value.to_canonical_u64()));
+        let var = FpVar::new_variable(cs, || value, mode)?;
+        enforce_canonical(&to_bits(&var, GOLDILOCKS_BITS)?)?;
+        Ok(Self {
+            value: var,
+            bits: GOLDILOCKS_BITS,
+            canonical: true,
+        })
+    }
+}
+
+/// `W` of the quadratic extension `F[X]/(X² - W)`.
+fn w() -> F {
+    <F as Extendable<2>>::W
+}
+
+/// An element of the quadratic extension of Goldilocks.
+#[derive(Clone, Debug)]
+pub struct ExtensionVar(pub [GoldilocksVar; 2]);
+
+impl ExtensionVar {
+    pub fn constant(value: FE) -> Self {
+        Self(value.to_basefield_array().map(GoldilocksVar::constant))
+    }
+
+    pub fn from_base(x: GoldilocksVar) -> Self {
+        Self([x, GoldilocksVar::zero()])
+    }
+
+    pub fn zero() -> Self {
+        Self::constant(FE::ZERO)
+    }
+
+    pub fn one() -> Self {
+        Self::constant(FE::ONE)
+    }
+
+    pub fn add(&self, other: &Self) -> Result<Self, SynthesisError> {
+        Ok(Self([
+            self.0[0].add(&other.0[0])?,
+            self.0[1].add(&other.0[1])?,
+        ]))
+    }
+
+    pub fn sub(&self, other: &Self) -> Result<Self, SynthesisError> {
+        Ok(Self([
+            self.0[0].sub(&other.0[0])?,
+            self.0[1].sub(&other.0[1])?,
+        ]))
+    }
+
+    pub fn scalar_mul(&self, scalar: &GoldilocksVar) -> Result<Self, SynthesisError> {
+        Ok(Self([self.0[0].mul(scalar)?, self.0[1].mul(scalar)?]))
+    }
+
+    pub fn mul_constant(&self, constant: F) -> Result<Self, SynthesisError> {
+        Ok(Self([
+            self.0[0].mul_constant(constant)?,
+            self.0[1].mul_constant(constant)?,
+        ]))
+    }
+
+    /// `Σ cᵢ·xᵢ` for base field constants `cᵢ`.
+    pub fn linear_combination<'a>(
+        terms: impl IntoIterator<Item = (&'a Self, u64)>,
+    ) -> Result<Self, SynthesisError> {
+        let terms = terms.into_iter().collect::<Vec<_>>();
+        let component =
+            |i: usize| GoldilocksVar::linear_combination(terms.iter().map(|(x, c)| (&x.0[i], *c)));
+        Ok(Self([component(0)?, component(1)?]))
+    }
+
+    fn bits(&self) -> usize {
+        self.0[0].bits.max(self.0[1].bits)
+    }
+
+    /// `(a₀ + a₁X)(b₀ + b₁X) = a₀b₀ + W·a₁b₁ + (a₀b₁ + a₁b₀)X`, reduced.
+    pub fn mul(&self, other: &Self) -> Result<Self, SynthesisError> {
+        let (mut a, mut b) = (self.clone(), other.clone());
+        while a.bits() + b.bits() > MAX_PRODUCT_BITS {
+            if a.bits() >= b.bits() {
+                a = a.reduce()?;
+            } else {
+                b = b.reduce()?;
+            }
+        }
+        let [a0, a1] = &a.0;
+        let [b0, b1] = &b.0;
+        let c0 = a0.mul(b0)?.add(&a1.mul(b1)?.mul_constant(w())?)?;
+        let c1 = a0.mul(b1)?.add(&a1.mul(b0)?)?;
+        Ok(Self([c0.reduce()?, c1.reduce()?]))
+    }
+
+    pub fn square(&self) -> Result<Self, SynthesisError> {
+        self.mul(self)
+    }
+
+    pub fn exp_power_of_2(&self, power_log: usize) -> Result<Self, SynthesisError> {
+        (0..power_log).try_fold(self.clone(), |x, _| x.square())
+    }
+
+    pub fn exp_u64(&self, power: u64) -> Result<Self, SynthesisError> {
+        let mut result = Self::one();
+        for i in (0..u64::BITS - power.leading_zeros()).rev() {
+            result = result.square()?;
+            if (power >> i) & 1 == 1 {
+                result = result.mul(self)?;
+            }
+        }
+        Ok(result)
+    }
+
+    /// `self / other`, or an unsatisfiable constraint if `other` is zero.
+    pub fn div(&self, other: &Self) -> Result<Self, SynthesisError> {
+        let value = self.value().and_then(|a| {
+            let b = other.value()?;
+            Ok(b.try_inverse().map_or(FE::ZERO, |inverse| a * inverse))
+        });
+        let cs = self.0[0].value.cs().or(self.0[1].value.cs());
+        let cs = cs.or(other.0[0].value.cs()).or(other.0[1].value.cs());
+        if cs.is_none() {
+            return value.map(Self::constant);
+        }
+        let quotient = Self::new_witness(cs, || value)?;
+        quotient.mul(other)?.enforce_equal(self)?;
+        Ok(quotient)
+    }
+
+    pub fn reduce(&self) -> Result<Self, SynthesisError> {
+        Ok(Self([self.0[0].reduce()?, self.0[1].reduce()?]))
+    }
+
+    pub fn canonical(&self) -> Result<Self, SynthesisError> {
+        Ok(Self([self.0[0].canonical()?, self.0[1].canonical()?]))
+    }
+
+    pub fn enforce_equal(&self, other: &Self) -> Result<(), SynthesisError> {
+        self.0[0].enforce_equal(&other.0[0])?;
+        self.0[1].enforce_equal(&other.0[1])
+    }
+
+    pub fn select(bit: &Boolean<Fr>, yes: &Self, no: &Self) -> Result<Self, SynthesisError> {
+        Ok(Self([
+            GoldilocksVar::select(bit, &yes.0[0], &no.0[0])?,
+            GoldilocksVar::select(bit, &yes.0[1], &no.0[1])?,
+        ]))
+    }
+
+    pub fn value(&self) -> Result<FE, SynthesisError> {
+        Ok(FE::from_basefield_array([
+            self.0[0].value()?,
+            self.0[1].value()?,
+        ]))
+    }
+}
+
+impl AllocVar<FE, Fr> for ExtensionVar {
+    /// Allocates the canonical components.
+    fn new_variable<T: Borrow<FE>>(
+        cs: impl Into<Namespace<Fr>>,
+        f: impl FnOnce() -> Result<T, SynthesisError>,
+        mode: AllocationMode,
+    ) -> Result<Self, SynthesisError> {
+        let ns = cs.into();
+        let cs = ns.cs();
+        let value = f().map(|value| FieldExtension::<2>::to_basefield_array(value.borrow()));
+        let component = |i: usize| {
+            GoldilocksVar::new_variable(cs.clone(), || value.map(|value| value[i]), mode)
+        };
+        Ok(Self([component(0)?, component(1)?]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ark_relations::r1cs::ConstraintSystem;
+    use plonky2::field::types::Sample;
+
+    #[test]
+    fn goldilocks_arithmetic() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let values = [F::NEG_ONE, F::rand(), F::rand(), F::ZERO];
+        let vars = values
+            .iter()
+            .map(|x| GoldilocksVar::new_witness(cs.clone(), || Ok(*x)).unwrap())
+            .collect::<Vec<_>>();
+        for (a, x) in vars.iter().zip(&values) {
+            for (b, y) in vars.iter().zip(&values) {
+                assert_eq!(a.add(b).unwrap().value().unwrap(), *x + *y);
+                assert_eq!(a.sub(b).unwrap().value().unwrap(), *x - *y);
+                let product = a.mul(b).unwrap();
+                assert_eq!(product.value().unwrap(), *x * *y);
+                let canonical = product.canonical().unwrap();
+                assert_eq!(
+                    canonical.fp().value().unwrap(),
+                    Fr::from((*x * *y).to_canonical_u64())
+                );
+                product
+                    .enforce_equal(&GoldilocksVar::constant(*x * *y))
+                    .unwrap();
+            }
+        }
+        // Deep products and long sums reduce on the way instead of overflowing.
+        let power = vars[0].exp_power_of_2(10).unwrap();
+        assert_eq!(power.value().unwrap(), values[0].exp_power_of_2(10));
+        let sum = GoldilocksVar::linear_combination(vars.iter().map(|x| (x, u64::MAX))).unwrap();
+        let expected = values
+            .iter()
+            .map(|x| *x * F::from_noncanonical_u64(u64::MAX))
+            .sum::<F>();
+        assert_eq!(sum.value().unwrap(), expected);
+        assert_eq!(
+            vars[1].inverse().unwrap().value().unwrap(),
+            values[1].inverse()
+        );
+        let constant = GoldilocksVar::constant(F::NEG_ONE);
+        assert_eq!(constant.mul(&constant).unwrap().value().unwrap(), F::ONE);
+        assert!(cs.is_satisfied().unwrap());
+
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let a = GoldilocksVar::new_witness(cs.clone(), || Ok(values[1])).unwrap();
+        let b = GoldilocksVar::new_witness(cs.clone(), || Ok(values[2])).unwrap();
+        a.mul(&b)
+            .unwrap()
+            .enforce_equal(&a.add(&b).unwrap())
+            .unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+
+        // The order itself fits in 64 bits but is not canonical.
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let order = FpVar::new_witness(cs.clone(), || Ok(Fr::from(F::ORDER))).unwrap();
+        enforce_canonical(&to_bits(&order, GOLDILOCKS_BITS).unwrap()).unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+    }
+
+    #[test]
+    fn extension_arithmetic() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let (x, y) = (FE::rand(), FE::rand());
+        let a = ExtensionVar::new_witness(cs.clone(), || Ok(x)).unwrap();
+        let b = ExtensionVar::new_witness(cs.clone(), || Ok(y)).unwrap();
+        assert_eq!(a.add(&b).unwrap().value().unwrap(), x + y);
+        assert_eq!(a.sub(&b).unwrap().value().unwrap(), x - y);
+        assert_eq!(a.mul(&b).unwrap().value().unwrap(), x * y);
+        assert_eq!(a.div(&b).unwrap().value().unwrap(), x / y);
+        assert_eq!(a.exp_u64(12345).unwrap().value().unwrap(), x.exp_u64(12345));
+        assert_eq!(
+            a.exp_power_of_2(5).unwrap().value().unwrap(),
+            x.exp_power_of_2(5)
+        );
+        let scalar = GoldilocksVar::constant(F::NEG_ONE);
+        assert_eq!(a.scalar_mul(&scalar).unwrap().value().unwrap(), -x);
+        a.mul(&b)
+            .unwrap()
+            .enforce_equal(&ExtensionVar::constant(x * y))
+            .unwrap();
+        assert!(cs.is_satisfied().unwrap());
+
+        let zero = ExtensionVar::new_witness(cs.clone(), || Ok(FE::ZERO)).unwrap();
+        a.div(&zero).unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+    }
+}
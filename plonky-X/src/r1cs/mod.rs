@@ -0,0 +1,15 @@
+//! A plonky2 verifier as an arkworks R1CS circuit over BN254, to wrap
+//! `PoseidonBN128GoldilocksConfig` proofs into native Groth16 proofs without the Go wrapper.
+//!
+//! `verifier::VerifierCircuit` checks a whole proof. It builds on emulated Goldilocks and
+//! extension arithmetic (`goldilocks`), the Goldilocks Poseidon permutation and challenger
+//! (`poseidon`, `challenger`), Merkle caps of the BN128 Poseidon hasher (`bn128`), the gate
+//! constraints (`gates`) and FRI (`fri`).
+
+pub mod bn128;
+pub mod challenger;
+pub mod fri;
+pub mod gates;
+pub mod goldilocks;
+pub mod poseidon;
+pub mod verifier;
@@ -0,0 +1,126 @@
+//! plonky2's Poseidon permutation over Goldilocks, which drives the Fiat-Shamir challenger of
+//! `PoseidonBN128GoldilocksConfig` and hashes the public inputs of a proof.
+//!
+//! The rounds follow the textbook form rather than plonky2's fast partial rounds: the MDS
+//! matrix has small entries, so a partial round only grows the untouched lanes by a few bits.
+
+use ark_relations::r1cs::SynthesisError;
+use plonky2::field::types::Field;
+use plonky2::hash::hash_types::NUM_HASH_OUT_ELTS;
+use plonky2::hash::poseidon::{
+    ALL_ROUND_CONSTANTS, HALF_N_FULL_ROUNDS, N_PARTIAL_ROUNDS, N_ROUNDS, Poseidon, SPONGE_RATE,
+    SPONGE_WIDTH,
+};
+
+use super::goldilocks::{F, GoldilocksVar, MAX_BITS};
+
+/// Bits a row of the MDS matrix adds, its coefficients summing to less than `2^9`.
+const MDS_GROWTH_BITS: usize = 9;
+
+/// Inputs to the S-box are reduced to this many bits, so that `x³` needs no reduction.
+const SBOX_INPUT_BITS: usize = 80;
+
+/// `x⁷`.
+pub fn sbox(x: &GoldilocksVar) -> Result<GoldilocksVar, SynthesisError> {
+    let x = match x.bits() > SBOX_INPUT_BITS {
+        true => x.reduce()?,
+        false => x.clone(),
+    };
+    let x3 = x.mul(&x)?.mul(&x)?.reduce()?;
+    x3.mul(&x3)?.mul(&x)?.reduce()
+}
+
+/// plonky2's `mds_layer`: lane `r` becomes `Σᵢ v[(i + r) % 12]·CIRC[i] + v[r]·DIAG[r]`.
+fn mds_layer(
+    state: &[GoldilocksVar; SPONGE_WIDTH],
+) -> Result<[GoldilocksVar; SPONGE_WIDTH], SynthesisError> {
+    // Every row would reduce its own copy of a lane that is too wide, so reduce them once.
+    let state = match state.iter().any(|x| x.bits() + MDS_GROWTH_BITS > MAX_BITS) {
+        true => &state
+            .iter()
+            .map(GoldilocksVar::reduce)
+            .collect::<Result<Vec<_>, _>>()?,
+        false => state.as_slice(),
+    };
+    let row = |r: usize| {
+        let circulant = (0..SPONGE_WIDTH).map(|i| {
+            (
+                &state[(i + r) % SPONGE_WIDTH],
+                <F as Poseidon>::MDS_MATRIX_CIRC[i],
+            )
+        });
+        let diagonal = (&state[r], <F as Poseidon>::MDS_MATRIX_DIAG[r]);
+        GoldilocksVar::linear_combination(circulant.chain([diagonal]))
+    };
+    let lanes = (0..SPONGE_WIDTH).map(row).collect::<Result<Vec<_>, _>>()?;
+    Ok(lanes.try_into().unwrap())
+}
+
+/// plonky2's Poseidon permutation of width 12.
+pub fn permute(state: &mut [GoldilocksVar; SPONGE_WIDTH]) -> Result<(), SynthesisError> {
+    for round in 0..N_ROUNDS {
+        for (i, x) in state.iter_mut().enumerate() {
+            let constant = ALL_ROUND_CONSTANTS[i + SPONGE_WIDTH * round];
+            *x = x.add(&GoldilocksVar::constant(F::from_canonical_u64(constant)))?;
+        }
+        let partial = (HALF_N_FULL_ROUNDS..HALF_N_FULL_ROUNDS + N_PARTIAL_ROUNDS).contains(&round);
+        let num_sboxes = if partial { 1 } else { SPONGE_WIDTH };
+        for x in &mut state[..num_sboxes] {
+            *x = sbox(x)?;
+        }
+        *state = mds_layer(state)?;
+    }
+    Ok(())
+}
+
+/// `PoseidonHash::hash_no_pad`: overwrites the rate with each chunk of the inputs, permuting
+/// after each, and outputs the first four lanes.
+pub fn hash_no_pad(
+    inputs: &[GoldilocksVar],
+) -> Result<[GoldilocksVar; NUM_HASH_OUT_ELTS], SynthesisError> {
+    let mut state = [(); SPONGE_WIDTH].map(|_| GoldilocksVar::zero());
+    for chunk in inputs.chunks(SPONGE_RATE) {
+        state[..chunk.len()].clone_from_slice(chunk);
+        permute(&mut state)?;
+    }
+    Ok(std::array::from_fn(|i| state[i].clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ark_bn254::Fr;
+    use ark_r1cs_std::alloc::AllocVar;
+    use ark_relations::r1cs::ConstraintSystem;
+    use plonky2::field::types::Sample;
+    use plonky2::hash::poseidon::PoseidonHash;
+    use plonky2::plonk::config::Hasher;
+
+    #[test]
+    fn poseidon_matches_native() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        let values = F::rand_vec(SPONGE_WIDTH);
+        let mut state: [_; SPONGE_WIDTH] = values
+            .iter()
+            .map(|x| GoldilocksVar::new_witness(cs.clone(), || Ok(*x)).unwrap())
+            .collect::<Vec<_>>()
+            .try_into()
+            .unwrap();
+        permute(&mut state).unwrap();
+        let expected = F::poseidon(values.clone().try_into().unwrap());
+        let permuted = state.iter().map(|x| x.value().unwrap()).collect::<Vec<_>>();
+        assert_eq!(permuted, expected);
+        // Twelve canonical inputs and one permutation, about 58k constraints, nearly all of it
+        // the range checks of the reductions around the 118 S-boxes.
+        assert!(cs.num_constraints() < 60_000, "{}", cs.num_constraints());
+
+        let inputs = state.to_vec();
+        let outputs = permuted;
+        for len in [0, 4, 12] {
+            let hash = hash_no_pad(&inputs[..len]).unwrap();
+            let expected = PoseidonHash::hash_no_pad(&outputs[..len]).elements;
+            assert_eq!(hash.map(|x| x.value().unwrap()), expected);
+        }
+        assert!(cs.is_satisfied().unwrap());
+    }
+}
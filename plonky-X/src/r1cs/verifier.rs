@@ -0,0 +1,584 @@
+//! A whole plonky2 proof verified in R1CS: `VerifierCircuit` replays the Fiat-Shamir transcript,
+//! checks the vanishing polynomial against the quotient at `zeta` and verifies the FRI opening
+//! proof, as `plonky2::plonk::verifier::verify` does natively.
+//!
+//! The verifier data is baked into the circuit, so a Groth16 key is tied to one plonky2 circuit,
+//! and the public inputs of the plonky2 proof are the Groth16 public inputs.
+
+use anyhow::ensure;
+use ark_bn254::Fr;
+use ark_r1cs_std::alloc::AllocVar;
+use ark_r1cs_std::fields::FieldVar;
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError};
+use plonky2::field::types::{Field, PrimeField64};
+use plonky2::fri::oracle::SALT_SIZE;
+use plonky2::plonk::circuit_data::{CommonCircuitData, VerifierOnlyCircuitData};
+use plonky2::plonk::proof::ProofWithPublicInputs;
+
+use super::challenger::ChallengerVar;
+use super::fri::{CapVar, FriBatch, FriProofVar, fri_challenges, verify_fri_proof};
+use super::gates::{D, EvaluationVarsVar, Gates};
+use super::goldilocks::{ExtensionVar, F, FE, GoldilocksVar};
+use super::poseidon::hash_no_pad;
+use crate::poseidon_bn128::{PoseidonBN128GoldilocksConfig, PoseidonBN128HashOut};
+
+type C = PoseidonBN128GoldilocksConfig;
+
+/// Proves that a `PoseidonBN128GoldilocksConfig` proof verifies against fixed verifier data.
+#[derive(Clone, Debug)]
+pub struct VerifierCircuit {
+    proof: ProofWithPublicInputs<F, C, D>,
+    verifier_only: VerifierOnlyCircuitData<C, D>,
+    common: CommonCircuitData<F, D>,
+    gates: Gates,
+}
+
+impl VerifierCircuit {
+    /// Checks that the circuit is supported and that the proof has its shape, which plonky2's
+    /// verifier does in `validate_proof_with_pis_shape` and the circuit relies on.
+    pub fn new(
+        proof: ProofWithPublicInputs<F, C, D>,
+        verifier_only: VerifierOnlyCircuitData<C, D>,
+        common: CommonCircuitData<F, D>,
+    ) -> anyhow::Result<Self> {
+        ensure!(
+            common.num_lookup_polys == 0 && common.luts.is_empty(),
+            "lookups are not supported by the R1CS verifier"
+        );
+        let gates = Gates::new(&common)?;
+        check_shape(&proof, &verifier_only, &common)?;
+        Ok(Self {
+            proof,
+            verifier_only,
+            common,
+            gates,
+        })
+    }
+
+    /// The Groth16 public inputs: the public inputs of the plonky2 proof.
+    pub fn public_inputs(&self) -> Vec<Fr> {
+        let inputs = self.proof.public_inputs.iter();
+        inputs.map(|x| Fr::from(x.to_canonical_u64())).collect()
+    }
+}
+
+fn check_shape(
+    proof: &ProofWithPublicInputs<F, C, D>,
+    verifier_only: &VerifierOnlyCircuitData<C, D>,
+    common: &CommonCircuitData<F, D>,
+) -> anyhow::Result<()> {
+    let config = &common.config;
+    let params = &common.fri_params;
+    let num_challenges = config.num_challenges;
+    let cap_len = 1 << config.fri_config.cap_height;
+    let openings = &proof.proof.openings;
+    let opening_proof = &proof.proof.opening_proof;
+    ensure!(
+        proof.public_inputs.len() == common.num_public_inputs,
+        "expected {} public inputs, got {}",
+        common.num_public_inputs,
+        proof.public_inputs.len()
+    );
+    for (name, cap) in [
+        ("constants_sigmas_cap", &verifier_only.constants_sigmas_cap),
+        ("wires_cap", &proof.proof.wires_cap),
+        (
+            "plonk_zs_partial_products_cap",
+            &proof.proof.plonk_zs_partial_products_cap,
+        ),
+        ("quotient_polys_cap", &proof.proof.quotient_polys_cap),
+    ] {
+        ensure!(
+            cap.0.len() == cap_len,
+            "{name} has {} digests, expected {cap_len}",
+            cap.0.len()
+        );
+    }
+    for (name, len, expected) in [
+        ("constants", openings.constants.len(), common.num_constants),
+        (
+            "plonk_sigmas",
+            openings.plonk_sigmas.len(),
+            config.num_routed_wires,
+        ),
+        ("wires", openings.wires.len(), config.num_wires),
+        ("plonk_zs", openings.plonk_zs.len(), num_challenges),
+        (
+            "plonk_zs_next",
+            openings.plonk_zs_next.len(),
+            num_challenges,
+        ),
+        (
+            "partial_products",
+            openings.partial_products.len(),
+            num_challenges * common.num_partial_products,
+        ),
+        (
+            "quotient_polys",
+            openings.quotient_polys.len(),
+            num_challenges * common.quotient_degree_factor,
+        ),
+        ("lookup_zs", openings.lookup_zs.len(), 0),
+        ("lookup_zs_next", openings.lookup_zs_next.len(), 0),
+        (
+            "final_poly",
+            opening_proof.final_poly.len(),
+            params.final_poly_len(),
+        ),
+        (
+            "commit_phase_merkle_caps",
+            opening_proof.commit_phase_merkle_caps.len(),
+            params.reduction_arity_bits.len(),
+        ),
+        (
+            "query_round_proofs",
+            opening_proof.query_round_proofs.len(),
+            config.fri_config.num_query_rounds,
+        ),
+    ] {
+        ensure!(
+            len == expected,
+            "{name} has {len} entries, expected {expected}"
+        );
+    }
+    for cap in &opening_proof.commit_phase_merkle_caps {
+        ensure!(
+            cap.0.len() == cap_len,
+            "a commit phase cap has {} digests, expected {cap_len}",
+            cap.0.len()
+        );
+    }
+
+    let leaf_lens = oracle_sizes(common).map(|(num_polys, blinding)| {
+        num_polys
+            + if params.hiding && blinding {
+                SALT_SIZE
+            } else {
+                0
+            }
+    });
+    for round in &opening_proof.query_round_proofs {
+        let evals_proofs = &round.initial_trees_proof.evals_proofs;
+        ensure!(
+            evals_proofs.len() == leaf_lens.len(),
+            "a query round opens {} trees",
+            evals_proofs.len()
+        );
+        let siblings = params.lde_bits() - config.fri_config.cap_height;
+        for ((evals, merkle_proof), leaf_len) in evals_proofs.iter().zip(leaf_lens) {
+            ensure!(
+                evals.len() == leaf_len,
+                "an initial leaf has {} elements, expected {leaf_len}",
+                evals.len()
+            );
+            ensure!(
+                merkle_proof.siblings.len() == siblings,
+                "an initial Merkle proof has the wrong length"
+            );
+        }
+        ensure!(
+            round.steps.len() == params.reduction_arity_bits.len(),
+            "a query round has {} steps",
+            round.steps.len()
+        );
+        let mut index_bits = params.lde_bits();
+        for (step, &arity_bits) in round.steps.iter().zip(&params.reduction_arity_bits) {
+            index_bits -= arity_bits;
+            ensure!(
+                step.evals.len() == 1 << arity_bits,
+                "a query step has {} evaluations",
+                step.evals.len()
+            );
+            ensure!(
+                step.merkle_proof.siblings.len() == index_bits - config.fri_config.cap_height,
+                "a query step Merkle proof has the wrong length"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The number of polynomials in each initial tree and whether it is blinded, like
+/// `CommonCircuitData::fri_oracles`, whose blinding flags plonky2 keeps private.
+fn oracle_sizes(common: &CommonCircuitData<F, D>) -> [(usize, bool); 4] {
+    let config = &common.config;
+    [
+        (common.num_constants + config.num_routed_wires, false),
+        (config.num_wires, true),
+        (
+            config.num_challenges * (1 + common.num_partial_products),
+            true,
+        ),
+        (config.num_challenges * common.quotient_degree_factor, true),
+    ]
+}
+
+fn cap_constant(cap: &[PoseidonBN128HashOut<F>]) -> CapVar {
+    cap.iter().map(|hash| FpVar::constant(hash.value)).collect()
+}
+
+fn cap_witness(
+    cs: &ConstraintSystemRef<Fr>,
+    cap: &[PoseidonBN128HashOut<F>],
+) -> Result<CapVar, SynthesisError> {
+    cap.iter()
+        .map(|hash| FpVar::new_witness(cs.clone(), || Ok(hash.value)))
+        .collect()
+}
+
+fn ext_witnesses(
+    cs: &ConstraintSystemRef<Fr>,
+    values: &[FE],
+) -> Result<Vec<ExtensionVar>, SynthesisError> {
+    values
+        .iter()
+        .map(|x| ExtensionVar::new_witness(cs.clone(), || Ok(*x)))
+        .collect()
+}
+
+/// `Σ termsᵢ·xⁱ` by Horner's rule, plonky2's `reduce_with_powers`.
+fn reduce_with_powers(
+    terms: &[ExtensionVar],
+    x: &ExtensionVar,
+) -> Result<ExtensionVar, SynthesisError> {
+    terms
+        .iter()
+        .rev()
+        .try_fold(ExtensionVar::zero(), |sum, term| sum.mul(x)?.add(term))
+}
+
+/// `check_partial_products`: each chunk of `max_degree` quotients multiplies one accumulator
+/// into the next, from `z(x)` through the partial products to `z(gx)`.
+fn check_partial_products(
+    numerators: &[ExtensionVar],
+    denominators: &[ExtensionVar],
+    accumulators: &[ExtensionVar],
+    max_degree: usize,
+) -> Result<Vec<ExtensionVar>, SynthesisError> {
+    let product = |chunk: &[ExtensionVar]| {
+        chunk
+            .iter()
+            .try_fold(ExtensionVar::one(), |acc, x| acc.mul(x))
+    };
+    numerators
+        .chunks(max_degree)
+        .zip(denominators.chunks(max_degree))
+        .zip(accumulators.windows(2))
+        .map(|((numerators, denominators), accs)| {
+            accs[0]
+                .mul(&product(numerators)?)?
+                .sub(&accs[1].mul(&product(denominators)?)?)
+        })
+        .collect()
+}
+
+impl ConstraintSynthesizer<Fr> for VerifierCircuit {
+    fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+        let common = &self.common;
+        let config = &common.config;
+        let num_challenges = config.num_challenges;
+        let proof = &self.proof.proof;
+
+        let public_inputs = self
+            .proof
+            .public_inputs
+            .iter()
+            .map(|x| GoldilocksVar::new_input(cs.clone(), || Ok(*x)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let wires_cap = cap_witness(&cs, &proof.wires_cap.0)?;
+        let zs_partial_products_cap = cap_witness(&cs, &proof.plonk_zs_partial_products_cap.0)?;
+        let quotient_polys_cap = cap_witness(&cs, &proof.quotient_polys_cap.0)?;
+        let openings = &proof.openings;
+        let constants = ext_witnesses(&cs, &openings.constants)?;
+        let sigmas = ext_witnesses(&cs, &openings.plonk_sigmas)?;
+        let wires = ext_witnesses(&cs, &openings.wires)?;
+        let zs = ext_witnesses(&cs, &openings.plonk_zs)?;
+        let zs_next = ext_witnesses(&cs, &openings.plonk_zs_next)?;
+        let partial_products = ext_witnesses(&cs, &openings.partial_products)?;
+        let quotient_polys = ext_witnesses(&cs, &openings.quotient_polys)?;
+        let fri_proof = FriProofVar::new_witness(cs.clone(), &proof.opening_proof)?;
+
+        // `get_challenges`.
+        let mut challenger = ChallengerVar::new();
+        challenger.observe_hash(&FpVar::constant(self.verifier_only.circuit_digest.value))?;
+        let public_inputs_hash = hash_no_pad(&public_inputs)?;
+        challenger.observe_elements(&public_inputs_hash)?;
+        challenger.observe_cap(&wires_cap)?;
+        let betas = challenger.get_n_challenges(num_challenges)?;
+        let gammas = challenger.get_n_challenges(num_challenges)?;
+        challenger.observe_cap(&zs_partial_products_cap)?;
+        let alphas = challenger.get_n_challenges(num_challenges)?;
+        challenger.observe_cap(&quotient_polys_cap)?;
+        let zeta = challenger.get_extension_challenge()?;
+        let zeta_batch = [
+            constants.as_slice(),
+            &sigmas,
+            &wires,
+            &zs,
+            &partial_products,
+            &quotient_polys,
+        ]
+        .concat();
+        challenger.observe_extension_elements(&zeta_batch)?;
+        challenger.observe_extension_elements(&zs_next)?;
+        let fri_challenges = fri_challenges(&mut challenger, &fri_proof, &common.fri_params)?;
+
+        // `eval_vanishing_poly`.
+        let vars = EvaluationVarsVar {
+            local_constants: &constants,
+            local_wires: &wires,
+            public_inputs_hash: &public_inputs_hash,
+        };
+        let constraint_terms = self.gates.evaluate(vars)?;
+        let one = ExtensionVar::one();
+        let zeta_pow_deg = zeta.exp_power_of_2(common.degree_bits())?;
+        let z_h_zeta = zeta_pow_deg.sub(&one)?;
+        let l_0 = z_h_zeta.div(
+            &zeta
+                .sub(&one)?
+                .mul_constant(F::from_canonical_usize(common.degree()))?,
+        )?;
+        let num_prods = common.num_partial_products;
+        let mut z_1_terms = Vec::with_capacity(num_challenges);
+        let mut partial_product_terms = Vec::new();
+        for i in 0..num_challenges {
+            z_1_terms.push(l_0.mul(&zs[i].sub(&one)?)?);
+            let gamma = ExtensionVar::from_base(gammas[i].clone());
+            let zeta_beta = zeta.scalar_mul(&betas[i])?;
+            let mut numerators = Vec::with_capacity(config.num_routed_wires);
+            let mut denominators = Vec::with_capacity(config.num_routed_wires);
+            for j in 0..config.num_routed_wires {
+                let s_id = zeta_beta.mul_constant(common.k_is[j])?;
+                numerators.push(ExtensionVar::linear_combination([
+                    (&wires[j], 1),
+                    (&s_id, 1),
+                    (&gamma, 1),
+                ])?);
+                let s_sigma = sigmas[j].scalar_mul(&betas[i])?;
+                denominators.push(ExtensionVar::linear_combination([
+                    (&wires[j], 1),
+                    (&s_sigma, 1),
+                    (&gamma, 1),
+                ])?);
+            }
+            let accumulators = [
+                std::slice::from_ref(&zs[i]),
+                &partial_products[i * num_prods..(i + 1) * num_prods],
+                std::slice::from_ref(&zs_next[i]),
+            ]
+            .concat();
+            partial_product_terms.extend(check_partial_products(
+                &numerators,
+                &denominators,
+                &accumulators,
+                common.quotient_degree_factor,
+            )?);
+        }
+        let vanishing_terms = [z_1_terms, partial_product_terms, constraint_terms].concat();
+
+        // `vanishing(zeta) = Z_H(zeta)·quotient(zeta)` for each challenge.
+        for (alpha, quotient) in alphas
+            .iter()
+            .zip(quotient_polys.chunks(common.quotient_degree_factor))
+        {
+            let vanishing = vanishing_terms
+                .iter()
+                .rev()
+                .try_fold(ExtensionVar::zero(), |sum, term| {
+                    sum.scalar_mul(alpha)?.add(term)
+                })?;
+            vanishing
+                .enforce_equal(&z_h_zeta.mul(&reduce_with_powers(quotient, &zeta_pow_deg)?)?)?;
+        }
+
+        // `verify_fri_proof` for the instance of `get_fri_instance`.
+        let oracles = oracle_sizes(common);
+        let zeta_polys = oracles
+            .iter()
+            .enumerate()
+            .flat_map(|(oracle, &(num_polys, _))| (0..num_polys).map(move |i| (oracle, i)))
+            .collect();
+        let g = F::primitive_root_of_unity(common.degree_bits());
+        let batches = [
+            FriBatch {
+                point: zeta.clone(),
+                polynomials: zeta_polys,
+                values: zeta_batch,
+            },
+            FriBatch {
+                point: zeta.mul_constant(g)?,
+                polynomials: (0..num_challenges).map(|i| (2, i)).collect(),
+                values: zs_next,
+            },
+        ];
+        let initial_caps = [
+            cap_constant(&self.verifier_only.constants_sigmas_cap.0),
+            wires_cap,
+            zs_partial_products_cap,
+            quotient_polys_cap,
+        ];
+        verify_fri_proof(
+            &common.fri_params,
+            &batches,
+            &initial_caps,
+            &fri_challenges,
+            &fri_proof,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ark_bn254::Bn254;
+    use ark_groth16::Groth16;
+    use ark_relations::r1cs::ConstraintSystem;
+    use ark_snark::SNARK;
+    use ark_std::rand::SeedableRng;
+    use ark_std::rand::rngs::StdRng;
+    use plonky2::fri::FriConfig;
+    use plonky2::fri::reduction_strategies::FriReductionStrategy;
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData};
+
+    /// The tiny proof's verifier takes about 693k constraints at the time of writing.
+    const MAX_VERIFIER_CONSTRAINTS: usize = 750_000;
+
+    /// Arity-2 FRI reductions and two query rounds.
+    fn fri_config(rate_bits: usize) -> FriConfig {
+        FriConfig {
+            rate_bits,
+            cap_height: 0,
+            proof_of_work_bits: 2,
+            reduction_strategy: FriReductionStrategy::ConstantArityBits(1, 1),
+            num_query_rounds: 2,
+        }
+    }
+
+    /// Proves that 7² + 3 = 52, registering 52 as a public input if `public`.
+    fn prove(
+        config: CircuitConfig,
+        public: bool,
+    ) -> (CircuitData<F, C, D>, ProofWithPublicInputs<F, C, D>) {
+        let mut builder = CircuitBuilder::<F, D>::new(config);
+        let x = builder.add_virtual_target();
+        let square = builder.mul(x, x);
+        let y = builder.add_const(square, F::from_canonical_u64(3));
+        if public {
+            builder.register_public_input(y);
+        } else {
+            let expected = builder.constant(F::from_canonical_u64(52));
+            builder.connect(y, expected);
+        }
+        let data = builder.build::<C>();
+        let mut witness = PartialWitness::new();
+        witness.set_target(x, F::from_canonical_u64(7)).unwrap();
+        let proof = data.prove(witness).unwrap();
+        data.verify(proof.clone()).unwrap();
+        (data, proof)
+    }
+
+    /// The statement in about the smallest configuration plonky2 builds: four wires, one
+    /// challenge and two FRI reductions. Without
+    /// public inputs the circuit needs no `PoseidonGate`, which alone would take 135 wires.
+    fn tiny_proof() -> (CircuitData<F, C, D>, ProofWithPublicInputs<F, C, D>) {
+        let config = CircuitConfig {
+            num_wires: 4,
+            num_routed_wires: 4,
+            num_constants: 2,
+            use_base_arithmetic_gate: true,
+            security_bits: 1,
+            num_challenges: 1,
+            zero_knowledge: false,
+            max_quotient_degree_factor: 3,
+            fri_config: fri_config(2),
+        };
+        prove(config, false)
+    }
+
+    /// The statement with 52 as a public input, in the standard recursion config's wires so that
+    /// the public inputs can be hashed with a `PoseidonGate`. Its degree 7 needs a rate of 2^3.
+    fn public_input_proof() -> (CircuitData<F, C, D>, ProofWithPublicInputs<F, C, D>) {
+        let config = CircuitConfig {
+            security_bits: 1,
+            num_challenges: 1,
+            zero_knowledge: false,
+            fri_config: fri_config(3),
+            ..CircuitConfig::standard_recursion_config()
+        };
+        prove(config, true)
+    }
+
+    #[test]
+    fn verifier_circuit_groth16() {
+        let (data, proof) = tiny_proof();
+        let circuit = VerifierCircuit::new(
+            proof.clone(),
+            data.verifier_only.clone(),
+            data.common.clone(),
+        )
+        .unwrap();
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        circuit.clone().generate_constraints(cs.clone()).unwrap();
+        assert!(cs.is_satisfied().unwrap());
+        // Twelve Goldilocks Poseidon permutations in the transcript make up nearly all of it.
+        assert!(
+            cs.num_constraints() < MAX_VERIFIER_CONSTRAINTS,
+            "{}",
+            cs.num_constraints()
+        );
+
+        let mut tampered = proof.clone();
+        tampered.proof.openings.wires[0] += FE::ONE;
+        let tampered =
+            VerifierCircuit::new(tampered, data.verifier_only.clone(), data.common.clone())
+                .unwrap();
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        tampered.generate_constraints(cs.clone()).unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+
+        let mut truncated = proof;
+        truncated.proof.opening_proof.query_round_proofs.pop();
+        let error = VerifierCircuit::new(truncated, data.verifier_only, data.common).unwrap_err();
+        assert!(error.to_string().contains("query_round_proofs"), "{error}");
+
+        let rng = &mut StdRng::seed_from_u64(0);
+        let (pk, vk) = Groth16::<Bn254>::circuit_specific_setup(circuit.clone(), rng).unwrap();
+        let public_inputs = circuit.public_inputs();
+        assert!(public_inputs.is_empty());
+        let groth16_proof = Groth16::<Bn254>::prove(&pk, circuit, rng).unwrap();
+        assert!(Groth16::<Bn254>::verify(&vk, &public_inputs, &groth16_proof).unwrap());
+    }
+
+    /// The plonky2 public inputs are hashed in-circuit, checked against the `PublicInputGate` and
+    /// allocated as Groth16 inputs: changing one, in the proof or only in the Groth16 instance,
+    /// leaves the system unsatisfied.
+    #[test]
+    fn verifier_circuit_binds_public_inputs() {
+        let (data, proof) = public_input_proof();
+        let circuit = VerifierCircuit::new(
+            proof.clone(),
+            data.verifier_only.clone(),
+            data.common.clone(),
+        )
+        .unwrap();
+        assert_eq!(circuit.public_inputs(), [Fr::from(52u64)]);
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        circuit.generate_constraints(cs.clone()).unwrap();
+        assert!(cs.is_satisfied().unwrap());
+
+        // The instance variable at index 0 is the constant one.
+        cs.borrow_mut().unwrap().instance_assignment[1] = Fr::from(53u64);
+        assert!(!cs.is_satisfied().unwrap());
+
+        let mut tampered = proof;
+        tampered.public_inputs[0] += F::ONE;
+        let tampered = VerifierCircuit::new(tampered, data.verifier_only, data.common).unwrap();
+        assert_eq!(tampered.public_inputs(), [Fr::from(53u64)]);
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        tampered.generate_constraints(cs.clone()).unwrap();
+        assert!(!cs.is_satisfied().unwrap());
+    }
+}
@@ -0,0 +1,537 @@
+//! Sparse Merkle trie openings over plonky2's native Poseidon, the cheapest opening proof we can
+//! build in plonky2.
+//!
+//! Keys are `key_width` Goldilocks elements, and the low `depth` bits of their canonical
+//! little-endian encoding select the leaf, so bit `i` says whether the node at height `i` is a
+//! right child. An occupied leaf is `poseidon(key ‖ value_hash)`, which commits to the full key,
+//! and an empty leaf is the zero digest. Internal nodes are `poseidon(left ‖ right)`.
+
+use anyhow::ensure;
+use plonky2::field::extension::Extendable;
+use plonky2::hash::hash_types::{HashOut, HashOutTarget, RichField};
+use plonky2::hash::poseidon::PoseidonHash;
+use plonky2::iop::target::{BoolTarget, Target};
+use plonky2::iop::witness::WitnessWrite;
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::config::Hasher;
+use std::collections::HashMap;
+
+/// Shape of a trie: the number of levels and the number of Goldilocks elements in a key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TrieConfig {
+    pub depth: usize,
+    pub key_width: usize,
+}
+
+impl TrieConfig {
+    pub fn new(depth: usize, key_width: usize) -> Self {
+        assert!(key_width > 0, "keys must have at least one element");
+        assert!(
+            depth <= 64 * key_width,
+            "depth must be at most {} for keys of {key_width} elements, got {depth}",
+            64 * key_width
+        );
+        Self { depth, key_width }
+    }
+}
+
+fn hash_pair<F: RichField>(left: HashOut<F>, right: HashOut<F>) -> HashOut<F> {
+    PoseidonHash::hash_no_pad(&[left.elements, right.elements].concat())
+}
+
+fn hash_leaf<F: RichField>(key: &[F], value_hash: HashOut<F>) -> HashOut<F> {
+    PoseidonHash::hash_no_pad(&[key, &value_hash.elements[..]].concat())
+}
+
+/// Little-endian bits of the canonical encoding of `element`, constraining the decomposition to
+/// be canonical so that each key has a single path.
+fn canonical_bits<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    element: Target,
+) -> Vec<BoolTarget> {
+    let bits = builder.split_le(element, 64);
+    // Goldilocks is 2^64 - 2^32 + 1: if the high half is all ones, the low half must be zero.
+    let low = builder.le_sum(bits[..32].iter());
+    let high = builder.le_sum(bits[32..].iter());
+    let max_high = builder.constant(F::from_canonical_u64(u32::MAX as u64));
+    let high_is_max = builder.is_equal(high, max_high);
+    let overflow = builder.mul(high_is_max.target, low);
+    builder.assert_zero(overflow);
+    bits
+}
+
+/// The low `depth` bits of `key`, i.e. the path from the leaf to the root.
+fn path_bits<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    key: &[Target],
+    depth: usize,
+) -> Vec<BoolTarget> {
+    key.iter()
+        .take(depth.div_ceil(64))
+        .flat_map(|&element| canonical_bits(builder, element))
+        .collect()
+}
+
+fn leaf_target<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    key: &[Target],
+    value_hash: HashOutTarget,
+) -> HashOutTarget {
+    builder.hash_n_to_hash_no_pad::<PoseidonHash>([key, &value_hash.elements[..]].concat())
+}
+
+/// The root above `leaf` along `path`.
+fn root_target<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    path: &[BoolTarget],
+    leaf: HashOutTarget,
+    siblings: &[HashOutTarget],
+) -> HashOutTarget {
+    let mut node = leaf;
+    for (&bit, sibling) in path.iter().zip(siblings) {
+        let (left, right): (Vec<_>, Vec<_>) = node
+            .elements
+            .iter()
+            .zip(&sibling.elements)
+            .map(|(&n, &s)| (builder.select(bit, s, n), builder.select(bit, n, s)))
+            .unzip();
+        node = builder.hash_n_to_hash_no_pad::<PoseidonHash>([left, right].concat());
+    }
+    node
+}
+
+/// `x` if `b` is true, `y` otherwise.
+pub(crate) fn select_hash<F: RichField + Extendable<D>, const D: usize>(
+    builder: &mut CircuitBuilder<F, D>,
+    b: BoolTarget,
+    x: HashOutTarget,
+    y: HashOutTarget,
+) -> HashOutTarget {
+    let elements = x
+        .elements
+        .iter()
+        .zip(&y.elements)
+        .map(|(&x, &y)| builder.select(b, x, y))
+        .collect();
+    HashOutTarget::from_vec(elements)
+}
+
+/// Targets of a trie inclusion proof: the siblings are private, the root, key and value hash are
+/// public inputs, in that order.
+pub struct TrieProofTarget {
+    pub key: Vec<Target>,
+    pub value_hash: HashOutTarget,
+    pub siblings: Vec<HashOutTarget>,
+    pub root: HashOutTarget,
+}
+
+impl TrieProofTarget {
+    pub fn new<F: RichField + Extendable<D>, const D: usize>(
+        config: TrieConfig,
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> Self {
+        let key = builder.add_virtual_targets(config.key_width);
+        let value_hash = builder.add_virtual_hash();
+        let siblings = builder.add_virtual_hashes(config.depth);
+
+        let path = path_bits(builder, &key, config.depth);
+        let leaf = leaf_target(builder, &key, value_hash);
+        let root = root_target(builder, &path, leaf, &siblings);
+
+        builder.register_public_inputs(&root.elements);
+        builder.register_public_inputs(&key);
+        builder.register_public_inputs(&value_hash.elements);
+        Self {
+            key,
+            value_hash,
+            siblings,
+            root,
+        }
+    }
+
+    pub fn config(&self) -> TrieConfig {
+        TrieConfig::new(self.siblings.len(), self.key.len())
+    }
+
+    /// Assigns the key, value hash and siblings of `proof`; the root is derived by the circuit.
+    pub fn set_witness<F: RichField, W: WitnessWrite<F>>(
+        &self,
+        witness: &mut W,
+        proof: &TrieProof<F>,
+    ) -> anyhow::Result<()> {
+        ensure!(
+            proof.config() == self.config(),
+            "expected a proof for {:?}, got {:?}",
+            self.config(),
+            proof.config()
+        );
+        for (&target, &value) in self.key.iter().zip(&proof.key) {
+            witness.set_target(target, value)?;
+        }
+        witness.set_hash_target(self.value_hash, proof.value_hash)?;
+        for (&target, &value) in self.siblings.iter().zip(&proof.siblings) {
+            witness.set_hash_target(target, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Targets of one write to a trie: the leaf at `key` goes from empty if `is_insert`, or from
+/// `old_value_hash` otherwise, to `new_value_hash`, which turns `old_root` into `new_root`. Both
+/// roots share the siblings. Nothing is registered as a public input.
+pub struct TrieUpdateTarget {
+    pub key: Vec<Target>,
+    pub is_insert: BoolTarget,
+    pub old_value_hash: HashOutTarget,
+    pub new_value_hash: HashOutTarget,
+    pub siblings: Vec<HashOutTarget>,
+    pub old_root: HashOutTarget,
+    pub new_root: HashOutTarget,
+}
+
+impl TrieUpdateTarget {
+    pub fn new<F: RichField + Extendable<D>, const D: usize>(
+        config: TrieConfig,
+        builder: &mut CircuitBuilder<F, D>,
+    ) -> Self {
+        let key = builder.add_virtual_targets(config.key_width);
+        let is_insert = builder.add_virtual_bool_target_safe();
+        let old_value_hash = builder.add_virtual_hash();
+        let new_value_hash = builder.add_virtual_hash();
+        let siblings = builder.add_virtual_hashes(config.depth);
+
+        let path = path_bits(builder, &key, config.depth);
+        let old_leaf = leaf_target(builder, &key, old_value_hash);
+        let empty = builder.constant_hash(HashOut::ZERO);
+        let old_leaf = select_hash(builder, is_insert, empty, old_leaf);
+        let old_root = root_target(builder, &path, old_leaf, &siblings);
+        let new_leaf = leaf_target(builder, &key, new_value_hash);
+        let new_root = root_target(builder, &path, new_leaf, &siblings);
+        Self {
+            key,
+            is_insert,
+            old_value_hash,
+            new_value_hash,
+            siblings,
+            old_root,
+            new_root,
+        }
+    }
+
+    pub fn config(&self) -> TrieConfig {
+        TrieConfig::new(self.siblings.len(), self.key.len())
+    }
+
+    /// Assigns the key, value hashes and siblings of `update`; both roots are derived by the
+    /// circuit.
+    pub fn set_witness<F: RichField, W: WitnessWrite<F>>(
+        &self,
+        witness: &mut W,
+        update: &TrieUpdate<F>,
+    ) -> anyhow::Result<()> {
+        ensure!(
+            update.config() == self.config(),
+            "expected an update for {:?}, got {:?}",
+            self.config(),
+            update.config()
+        );
+        for (&target, &value) in self.key.iter().zip(&update.key) {
+            witness.set_target(target, value)?;
+        }
+        witness.set_bool_target(self.is_insert, update.old_value_hash.is_none())?;
+        witness.set_hash_target(
+            self.old_value_hash,
+            update.old_value_hash.unwrap_or(HashOut::ZERO),
+        )?;
+        witness.set_hash_target(self.new_value_hash, update.new_value_hash)?;
+        for (&target, &value) in self.siblings.iter().zip(&update.siblings) {
+            witness.set_hash_target(target, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Native trie write, as produced by `Trie::update`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrieUpdate<F: RichField> {
+    pub key: Vec<F>,
+    /// The value hash stored at `key` before the write, `None` for an insert.
+    pub old_value_hash: Option<HashOut<F>>,
+    pub new_value_hash: HashOut<F>,
+    /// Siblings from the leaf level up to the children of the root.
+    pub siblings: Vec<HashOut<F>>,
+}
+
+impl<F: RichField> TrieUpdate<F> {
+    pub fn config(&self) -> TrieConfig {
+        TrieConfig::new(self.siblings.len(), self.key.len())
+    }
+
+    /// The root before the write.
+    pub fn old_root(&self) -> HashOut<F> {
+        let leaf = match self.old_value_hash {
+            Some(value_hash) => hash_leaf(&self.key, value_hash),
+            None => HashOut::ZERO,
+        };
+        path_root(&self.key, leaf, &self.siblings)
+    }
+
+    /// The root after the write.
+    pub fn new_root(&self) -> HashOut<F> {
+        path_root(
+            &self.key,
+            hash_leaf(&self.key, self.new_value_hash),
+            &self.siblings,
+        )
+    }
+}
+
+/// Native trie inclusion proof, as produced by `Trie::prove`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrieProof<F: RichField> {
+    pub key: Vec<F>,
+    pub value_hash: HashOut<F>,
+    /// Siblings from the leaf level up to the children of the root.
+    pub siblings: Vec<HashOut<F>>,
+}
+
+impl<F: RichField> TrieProof<F> {
+    pub fn config(&self) -> TrieConfig {
+        TrieConfig::new(self.siblings.len(), self.key.len())
+    }
+
+    /// Recomputes the root, like `TrieProofTarget` does in the circuit.
+    pub fn root(&self) -> HashOut<F> {
+        path_root(
+            &self.key,
+            hash_leaf(&self.key, self.value_hash),
+            &self.siblings,
+        )
+    }
+}
+
+/// The root above `leaf` along the path of `key`.
+fn path_root<F: RichField>(key: &[F], leaf: HashOut<F>, siblings: &[HashOut<F>]) -> HashOut<F> {
+    siblings
+        .iter()
+        .zip(key_path(key, siblings.len()))
+        .fold(leaf, |node, (&sibling, bit)| {
+            if bit {
+                hash_pair(sibling, node)
+            } else {
+                hash_pair(node, sibling)
+            }
+        })
+}
+
+/// The low `depth` bits of `key`, i.e. the path from the leaf to the root.
+fn key_path<F: RichField>(key: &[F], depth: usize) -> Vec<bool> {
+    key.iter()
+        .flat_map(|element| {
+            let value = element.to_canonical_u64();
+            (0..64).map(move |i| (value >> i) & 1 == 1)
+        })
+        .take(depth)
+        .collect()
+}
+
+/// Native sparse Merkle trie, storing only the non-empty nodes.
+#[derive(Clone, Debug)]
+pub struct Trie<F: RichField> {
+    config: TrieConfig,
+    /// Key and value hash of every occupied leaf, keyed by its path.
+    leaves: HashMap<Vec<bool>, (Vec<F>, HashOut<F>)>,
+    /// Non-empty nodes, keyed by their path from the root, i.e. the key bits above their height.
+    nodes: HashMap<Vec<bool>, HashOut<F>>,
+    /// Root of an empty subtree of each height, from the leaves (zero) up to the root.
+    empty: Vec<HashOut<F>>,
+}
+
+impl<F: RichField> Trie<F> {
+    pub fn new(config: TrieConfig) -> Self {
+        let mut empty = vec![HashOut::ZERO];
+        for height in 0..config.depth {
+            empty.push(hash_pair(empty[height], empty[height]));
+        }
+        Self {
+            config,
+            leaves: HashMap::new(),
+            nodes: HashMap::new(),
+            empty,
+        }
+    }
+
+    pub fn config(&self) -> TrieConfig {
+        self.config
+    }
+
+    pub fn root(&self) -> HashOut<F> {
+        self.node(self.config.depth, &[])
+    }
+
+    /// Returns the value hash stored at `key`, if any.
+    pub fn get(&self, key: &[F]) -> Option<HashOut<F>> {
+        match self.leaves.get(&key_path(key, self.config.depth)) {
+            Some((stored, value_hash)) if stored == key => Some(*value_hash),
+            _ => None,
+        }
+    }
+
+    /// Sets the value hash at `key` and updates the nodes above it. Fails if another key with
+    /// the same low `depth` bits is already stored.
+    pub fn insert(&mut self, key: &[F], value_hash: HashOut<F>) -> anyhow::Result<()> {
+        ensure!(
+            key.len() == self.config.key_width,
+            "expected a key of {} elements, got {}",
+            self.config.key_width,
+            key.len()
+        );
+        let path = key_path(key, self.config.depth);
+        if let Some((stored, _)) = self.leaves.get(&path) {
+            ensure!(stored == key, "key {key:?} collides with {stored:?}");
+        }
+        self.leaves.insert(path.clone(), (key.to_vec(), value_hash));
+        self.nodes.insert(path.clone(), hash_leaf(key, value_hash));
+        for height in 0..self.config.depth {
+            let mut child = path[height..].to_vec();
+            let node = self.node(height, &child);
+            child[0] = !child[0];
+            let sibling = self.node(height, &child);
+            let parent = if path[height] {
+                hash_pair(sibling, node)
+            } else {
+                hash_pair(node, sibling)
+            };
+            self.nodes.insert(path[height + 1..].to_vec(), parent);
+        }
+        Ok(())
+    }
+
+    /// Returns an inclusion proof for `key`, or `None` if it is not stored.
+    pub fn prove(&self, key: &[F]) -> Option<TrieProof<F>> {
+        let value_hash = self.get(key)?;
+        Some(TrieProof {
+            key: key.to_vec(),
+            value_hash,
+            siblings: self.siblings(key),
+        })
+    }
+
+    /// Sets the value hash at `key` like `insert`, and returns the write with the siblings of
+    /// its path, for `TrieUpdateTarget`.
+    pub fn update(&mut self, key: &[F], value_hash: HashOut<F>) -> anyhow::Result<TrieUpdate<F>> {
+        ensure!(
+            key.len() == self.config.key_width,
+            "expected a key of {} elements, got {}",
+            self.config.key_width,
+            key.len()
+        );
+        let update = TrieUpdate {
+            key: key.to_vec(),
+            old_value_hash: self.get(key),
+            new_value_hash: value_hash,
+            siblings: self.siblings(key),
+        };
+        self.insert(key, value_hash)?;
+        Ok(update)
+    }
+
+    /// Siblings of the path of a key of the right width, from the leaf level up.
+    fn siblings(&self, key: &[F]) -> Vec<HashOut<F>> {
+        let path = key_path(key, self.config.depth);
+        (0..self.config.depth)
+            .map(|height| {
+                let mut sibling = path[height..].to_vec();
+                sibling[0] = !sibling[0];
+                self.node(height, &sibling)
+            })
+            .collect()
+    }
+
+    /// The node at `height` whose path from the root is `path`.
+    fn node(&self, height: usize, path: &[bool]) -> HashOut<F> {
+        self.nodes.get(path).copied().unwrap_or(self.empty[height])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use plonky2::field::goldilocks_field::GoldilocksField;
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::PartialWitness;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = GoldilocksField;
+
+    fn value(x: u64) -> HashOut<F> {
+        PoseidonHash::hash_no_pad(&[F::from_canonical_u64(x)])
+    }
+
+    #[test]
+    fn native_trie() {
+        let mut trie = Trie::<F>::new(TrieConfig::new(8, 2));
+        let empty_root = trie.root();
+        let keys = [[3, 1], [200, 2]].map(|key| key.map(F::from_canonical_u64));
+        for (i, key) in keys.iter().enumerate() {
+            trie.insert(key, value(i as u64)).unwrap();
+        }
+        assert_ne!(trie.root(), empty_root);
+        for (i, key) in keys.iter().enumerate() {
+            let proof = trie.prove(key).unwrap();
+            assert_eq!(proof.value_hash, value(i as u64));
+            assert_eq!(proof.root(), trie.root());
+        }
+        // Only the low `depth` bits select the leaf, and the leaf commits to the full key.
+        let aliased = [3 + 256, 1].map(F::from_canonical_u64);
+        assert!(trie.prove(&aliased).is_none());
+        assert!(trie.insert(&aliased, value(0)).is_err());
+        assert!(trie.insert(&[F::ONE], value(0)).is_err());
+        // Updating a value changes the root.
+        let root = trie.root();
+        trie.insert(&keys[0], value(7)).unwrap();
+        assert_ne!(trie.root(), root);
+        // Writes carry both roots, for inserts and updates alike.
+        for (key, x) in [([9, 0], 8), ([9, 0], 9)] {
+            let root = trie.root();
+            let update = trie
+                .update(&key.map(F::from_canonical_u64), value(x))
+                .unwrap();
+            assert_eq!(update.old_value_hash.is_none(), x == 8);
+            assert_eq!(update.old_root(), root);
+            assert_eq!(update.new_root(), trie.root());
+        }
+    }
+
+    #[test]
+    fn trie_proof_circuit() {
+        let config = TrieConfig::new(80, 2);
+        let mut trie = Trie::<F>::new(config);
+        let key = [0xdead_beef, 0xcafe].map(F::from_canonical_u64);
+        trie.insert(&key, value(1)).unwrap();
+        trie.insert(&[F::NEG_ONE, F::ZERO], value(2)).unwrap();
+        let proof = trie.prove(&key).unwrap();
+
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let target = TrieProofTarget::new(config, &mut builder);
+        let data = builder.build::<C>();
+
+        let mut pw = PartialWitness::new();
+        target.set_witness(&mut pw, &proof).unwrap();
+        let output = data.prove(pw).unwrap();
+        let expected = [&trie.root().elements[..], &key[..], &value(1).elements[..]].concat();
+        assert_eq!(output.public_inputs, expected);
+        data.verify(output).unwrap();
+
+        // A wrong sibling yields a different root.
+        let mut bad = proof.clone();
+        bad.siblings[3] = value(3);
+        let mut pw = PartialWitness::new();
+        target.set_witness(&mut pw, &bad).unwrap();
+        let output = data.prove(pw).unwrap();
+        assert_ne!(output.public_inputs[..4], trie.root().elements);
+    }
+}
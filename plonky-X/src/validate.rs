@@ -0,0 +1,600 @@
+//! Validation of exported JSON against the layout gnark-plonky2-verifier reads, so that exporter
+//! bugs surface here rather than as a rejection by the Go wrapper.
+//!
+//! The files are checked together: the shapes of the verifier data and proof (cap sizes, number
+//! of openings, FRI query rounds and Merkle path lengths) follow from the common circuit data.
+//! Errors name the offending value with a JSON path such as
+//! `$.proof.opening_proof.query_round_proofs[3].steps[0].evals`.
+
+use anyhow::{Context, anyhow, bail, ensure};
+use num_bigint::BigUint;
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::field::types::{Field, Field64};
+use plonky2::fri::oracle::SALT_SIZE;
+use plonky2::plonk::config::GenericConfig;
+use plonky2_bn254_poseidon::arithmetic::modulus;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+use crate::export::{
+    COMMON_CIRCUIT_DATA_FILE, PROOF_WITH_PUBLIC_INPUTS_FILE, VERIFIER_ONLY_CIRCUIT_DATA_FILE,
+};
+use crate::import::packs_hashes;
+
+/// Extension degree supported by gnark-plonky2-verifier.
+const EXTENSION_DEGREE: usize = 2;
+/// Number of Merkle trees opened by the initial FRI query: constants and sigmas, wires, the
+/// permutation argument and the quotient.
+const NUM_INITIAL_TREES: usize = 4;
+/// Gates gnark-plonky2-verifier's `GateInstanceFromId` can parse, by the name their id starts
+/// with. Any other gate, e.g. bn254-poseidon's `Bn254MulGate`, makes the verifier panic.
+const GNARK_GATES: [&str; 14] = [
+    "ArithmeticGate",
+    "ArithmeticExtensionGate",
+    "BaseSumGate",
+    "ConstantGate",
+    "CosetInterpolationGate",
+    "ExponentiationGate",
+    "MulExtensionGate",
+    "NoopGate",
+    "PoseidonGate",
+    "PoseidonMdsGate",
+    "PublicInputGate",
+    "RandomAccessGate",
+    "ReducingGate",
+    "ReducingExtensionGate",
+];
+
+/// How digests are written in the JSON.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashEncoding {
+    /// Goldilocks limbs of a `HashOut` packed into one decimal `Σ limbᵢ·pⁱ`, by `export`.
+    PackedGoldilocks,
+    /// A decimal BN254 scalar, as written by `PoseidonBN128HashOut`. This is the only encoding
+    /// the gnark verifier can use for the outermost proof.
+    Bn254,
+}
+
+impl HashEncoding {
+    /// The encoding `export` uses for the digests of `C`.
+    pub fn of<C: GenericConfig<D>, const D: usize>() -> Self {
+        if packs_hashes::<C, D>() {
+            Self::PackedGoldilocks
+        } else {
+            Self::Bn254
+        }
+    }
+
+    /// Exclusive upper bound of an encoded digest.
+    fn bound(self) -> BigUint {
+        match self {
+            Self::PackedGoldilocks => BigUint::from(GoldilocksField::ORDER).pow(4),
+            Self::Bn254 => modulus(),
+        }
+    }
+}
+
+/// A JSON value and its path from the root of the file.
+struct Node<'a> {
+    value: &'a Value,
+    path: String,
+}
+
+impl<'a> Node<'a> {
+    fn root(value: &'a Value) -> Self {
+        Self {
+            value,
+            path: "$".to_string(),
+        }
+    }
+
+    fn error(&self, message: impl std::fmt::Display) -> anyhow::Error {
+        anyhow!("{}: {message}", self.path)
+    }
+
+    fn field(&self, key: &str) -> anyhow::Result<Node<'a>> {
+        let object = self
+            .value
+            .as_object()
+            .ok_or_else(|| self.error("expected an object"))?;
+        let value = object
+            .get(key)
+            .ok_or_else(|| self.error(format!("missing field {key:?}")))?;
+        Ok(Node {
+            value,
+            path: format!("{}.{key}", self.path),
+        })
+    }
+
+    fn items(&self) -> anyhow::Result<Vec<Node<'a>>> {
+        let array = self
+            .value
+            .as_array()
+            .ok_or_else(|| self.error("expected an array"))?;
+        Ok(array
+            .iter()
+            .enumerate()
+            .map(|(i, value)| Node {
+                value,
+                path: format!("{}[{i}]", self.path),
+            })
+            .collect())
+    }
+
+    fn items_exact(&self, len: usize) -> anyhow::Result<Vec<Node<'a>>> {
+        let items = self.items()?;
+        if items.len() != len {
+            return Err(self.error(format!("expected {len} elements, got {}", items.len())));
+        }
+        Ok(items)
+    }
+
+    fn uint(&self) -> anyhow::Result<usize> {
+        self.value
+            .as_u64()
+            .and_then(|value| usize::try_from(value).ok())
+            .ok_or_else(|| self.error("expected an unsigned integer"))
+    }
+
+    /// `2^bits`, failing instead of overflowing.
+    fn pow2(&self, bits: usize) -> anyhow::Result<usize> {
+        u32::try_from(bits)
+            .ok()
+            .and_then(|bits| 1usize.checked_shl(bits))
+            .ok_or_else(|| self.error(format!("2^{bits} overflows")))
+    }
+
+    fn bool(&self) -> anyhow::Result<bool> {
+        self.value
+            .as_bool()
+            .ok_or_else(|| self.error("expected a boolean"))
+    }
+
+    fn goldilocks(&self) -> anyhow::Result<()> {
+        match self.value.as_u64() {
+            Some(value) if value < GoldilocksField::ORDER => Ok(()),
+            _ => Err(self.error(format!(
+                "expected a canonical Goldilocks element, got {}",
+                self.value
+            ))),
+        }
+    }
+
+    fn goldilocks_array(&self, len: Option<usize>) -> anyhow::Result<()> {
+        let items = match len {
+            Some(len) => self.items_exact(len)?,
+            None => self.items()?,
+        };
+        items.iter().try_for_each(Node::goldilocks)
+    }
+
+    fn extension_array(&self, len: usize) -> anyhow::Result<()> {
+        for element in self.items_exact(len)? {
+            element.goldilocks_array(Some(EXTENSION_DEGREE))?;
+        }
+        Ok(())
+    }
+
+    fn hash(&self, encoding: HashEncoding) -> anyhow::Result<()> {
+        let digits = self
+            .value
+            .as_str()
+            .filter(|s| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()))
+            .ok_or_else(|| self.error(format!("expected a decimal digest, got {}", self.value)))?;
+        let value = BigUint::parse_bytes(digits.as_bytes(), 10).unwrap();
+        if value >= encoding.bound() {
+            return Err(self.error(format!("{digits} is too large for a {encoding:?} digest")));
+        }
+        Ok(())
+    }
+
+    fn cap(&self, encoding: HashEncoding, cap_height: usize) -> anyhow::Result<()> {
+        for hash in self.items_exact(self.pow2(cap_height)?)? {
+            hash.hash(encoding)?;
+        }
+        Ok(())
+    }
+
+    /// A `MerkleProof` with `len` siblings.
+    fn merkle_proof(&self, encoding: HashEncoding, len: usize) -> anyhow::Result<()> {
+        for sibling in self.field("siblings")?.items_exact(len)? {
+            sibling.hash(encoding)?;
+        }
+        Ok(())
+    }
+}
+
+/// The parts of the common circuit data that determine the shape of the other files.
+struct Shape {
+    num_wires: usize,
+    num_routed_wires: usize,
+    num_challenges: usize,
+    cap_height: usize,
+    num_query_rounds: usize,
+    degree_bits: usize,
+    /// `degree_bits + rate_bits`, the height of the first FRI trees.
+    lde_bits: usize,
+    reduction_arity_bits: Vec<usize>,
+    /// Number of evaluations in the leaves of each initial tree, salt included.
+    initial_leaf_lens: [usize; NUM_INITIAL_TREES],
+    num_constants: usize,
+    num_public_inputs: usize,
+    num_partial_products: usize,
+    quotient_degree_factor: usize,
+}
+
+/// Checks a `FriConfig`, returning its rate bits, cap height and number of query rounds.
+fn validate_fri_config(node: &Node) -> anyhow::Result<(usize, usize, usize)> {
+    let rate_bits = node.field("rate_bits")?.uint()?;
+    let cap_height = node.field("cap_height")?.uint()?;
+    node.field("proof_of_work_bits")?.uint()?;
+    let num_query_rounds = node.field("num_query_rounds")?.uint()?;
+    let strategy = node.field("reduction_strategy")?;
+    if !strategy.value.is_object() {
+        return Err(strategy.error("expected an object"));
+    }
+    Ok((rate_bits, cap_height, num_query_rounds))
+}
+
+fn validate_common(json: &Value) -> anyhow::Result<Shape> {
+    let root = Node::root(json);
+
+    let config = root.field("config")?;
+    let num_wires = config.field("num_wires")?.uint()?;
+    let num_routed_wires = config.field("num_routed_wires")?.uint()?;
+    config.field("num_constants")?.uint()?;
+    config.field("use_base_arithmetic_gate")?.bool()?;
+    config.field("security_bits")?.uint()?;
+    let num_challenges = config.field("num_challenges")?.uint()?;
+    let zero_knowledge = config.field("zero_knowledge")?.bool()?;
+    config.field("max_quotient_degree_factor")?.uint()?;
+    let (rate_bits, cap_height, num_query_rounds) =
+        validate_fri_config(&config.field("fri_config")?)?;
+
+    let fri_params = root.field("fri_params")?;
+    let fri_config = fri_params.field("config")?;
+    ensure!(
+        validate_fri_config(&fri_config)? == (rate_bits, cap_height, num_query_rounds),
+        "{}: does not match $.config.fri_config",
+        fri_config.path
+    );
+    let hiding = fri_params.field("hiding")?;
+    if hiding.bool()? != zero_knowledge {
+        return Err(hiding.error("does not match $.config.zero_knowledge"));
+    }
+    let degree_bits = fri_params.field("degree_bits")?.uint()?;
+    // The LDE domain is a multiplicative subgroup, so it is at most as large as the two-adic one.
+    let lde_bits = degree_bits
+        .checked_add(rate_bits)
+        .filter(|&bits| bits <= GoldilocksField::TWO_ADICITY)
+        .ok_or_else(|| {
+            fri_params.error(format!(
+                "degree_bits + rate_bits exceeds the two-adicity {} of Goldilocks",
+                GoldilocksField::TWO_ADICITY
+            ))
+        })?;
+    let reduction_arity_bits = fri_params
+        .field("reduction_arity_bits")?
+        .items()?
+        .iter()
+        .map(Node::uint)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let total_arity_bits = reduction_arity_bits
+        .iter()
+        .try_fold(0usize, |total, &bits| total.checked_add(bits))
+        .filter(|&total| total <= degree_bits)
+        .ok_or_else(|| fri_params.error("FRI reduces below degree 1"))?;
+    if cap_height > lde_bits - total_arity_bits {
+        return Err(fri_params.error("the cap height exceeds the height of the last FRI tree"));
+    }
+
+    let gates = root.field("gates")?.items()?;
+    for gate in &gates {
+        let id = gate
+            .value
+            .as_str()
+            .ok_or_else(|| gate.error("expected a gate id"))?;
+        let name_len = id
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(id.len());
+        if !GNARK_GATES.contains(&&id[..name_len]) {
+            return Err(gate.error(format!("{id} is not supported by gnark-plonky2-verifier")));
+        }
+    }
+    let selectors_info = root.field("selectors_info")?;
+    let selector_indices = selectors_info.field("selector_indices")?;
+    for index in selector_indices.items_exact(gates.len())? {
+        index.uint()?;
+    }
+    for group in selectors_info.field("groups")?.items()? {
+        group.field("start")?.uint()?;
+        group.field("end")?.uint()?;
+    }
+
+    let quotient_degree_factor = root.field("quotient_degree_factor")?.uint()?;
+    root.field("num_gate_constraints")?.uint()?;
+    let num_constants = root.field("num_constants")?.uint()?;
+    let num_public_inputs = root.field("num_public_inputs")?.uint()?;
+    root.field("k_is")?.goldilocks_array(Some(num_routed_wires))?;
+    let num_partial_products = root.field("num_partial_products")?.uint()?;
+    // The constants and sigmas are never salted, the other trees are when hiding.
+    let salt = if zero_knowledge { SALT_SIZE } else { 0 };
+    let too_long = || root.error("the initial FRI leaves are too long");
+    let initial_leaf_lens = [
+        num_constants.checked_add(num_routed_wires).ok_or_else(too_long)?,
+        num_wires.checked_add(salt).ok_or_else(too_long)?,
+        num_partial_products
+            .checked_add(1)
+            .and_then(|len| len.checked_mul(num_challenges))
+            .and_then(|len| len.checked_add(salt))
+            .ok_or_else(too_long)?,
+        num_challenges
+            .checked_mul(quotient_degree_factor)
+            .and_then(|len| len.checked_add(salt))
+            .ok_or_else(too_long)?,
+    ];
+    let luts = root.field("luts")?;
+    if !luts.items()?.is_empty() {
+        return Err(luts.error("lookup tables are not supported by gnark-plonky2-verifier"));
+    }
+
+    Ok(Shape {
+        num_wires,
+        num_routed_wires,
+        num_challenges,
+        cap_height,
+        num_query_rounds,
+        degree_bits,
+        lde_bits,
+        reduction_arity_bits,
+        initial_leaf_lens,
+        num_constants,
+        num_public_inputs,
+        num_partial_products,
+        quotient_degree_factor,
+    })
+}
+
+fn validate_verifier_only(
+    json: &Value,
+    shape: &Shape,
+    encoding: HashEncoding,
+) -> anyhow::Result<()> {
+    let root = Node::root(json);
+    root.field("constants_sigmas_cap")?.cap(encoding, shape.cap_height)?;
+    root.field("circuit_digest")?.hash(encoding)
+}
+
+fn validate_proof(json: &Value, shape: &Shape, encoding: HashEncoding) -> anyhow::Result<()> {
+    let root = Node::root(json);
+    root.field("public_inputs")?.goldilocks_array(Some(shape.num_public_inputs))?;
+
+    let proof = root.field("proof")?;
+    for cap in ["wires_cap", "plonk_zs_partial_products_cap", "quotient_polys_cap"] {
+        proof.field(cap)?.cap(encoding, shape.cap_height)?;
+    }
+
+    let openings = proof.field("openings")?;
+    let num_zs = shape.num_challenges;
+    let opening_lens = [
+        ("constants", shape.num_constants),
+        ("plonk_sigmas", shape.num_routed_wires),
+        ("wires", shape.num_wires),
+        ("plonk_zs", num_zs),
+        ("plonk_zs_next", num_zs),
+        ("partial_products", num_zs * shape.num_partial_products),
+        ("quotient_polys", num_zs * shape.quotient_degree_factor),
+    ];
+    for (name, len) in opening_lens {
+        openings.field(name)?.extension_array(len)?;
+    }
+
+    let fri = proof.field("opening_proof")?;
+    let num_reductions = shape.reduction_arity_bits.len();
+    for cap in fri.field("commit_phase_merkle_caps")?.items_exact(num_reductions)? {
+        cap.cap(encoding, shape.cap_height)?;
+    }
+    let lde_bits = shape.lde_bits;
+    for round in fri.field("query_round_proofs")?.items_exact(shape.num_query_rounds)? {
+        let initial = round.field("initial_trees_proof")?.field("evals_proofs")?;
+        let trees = initial.items_exact(NUM_INITIAL_TREES)?;
+        for (tree, &leaf_len) in trees.iter().zip(&shape.initial_leaf_lens) {
+            // An `(evals, merkle_proof)` pair.
+            let entry = tree.items_exact(2)?;
+            entry[0].goldilocks_array(Some(leaf_len))?;
+            entry[1].merkle_proof(encoding, lde_bits - shape.cap_height)?;
+        }
+        let mut tree_bits = lde_bits;
+        for (step, &arity_bits) in round
+            .field("steps")?
+            .items_exact(num_reductions)?
+            .iter()
+            .zip(&shape.reduction_arity_bits)
+        {
+            tree_bits -= arity_bits;
+            let evals = step.field("evals")?;
+            evals.extension_array(evals.pow2(arity_bits)?)?;
+            step.field("merkle_proof")?
+                .merkle_proof(encoding, tree_bits - shape.cap_height)?;
+        }
+    }
+    let final_poly_bits = shape.degree_bits - shape.reduction_arity_bits.iter().sum::<usize>();
+    let coeffs = fri.field("final_poly")?.field("coeffs")?;
+    coeffs.extension_array(coeffs.pow2(final_poly_bits)?)?;
+    fri.field("pow_witness")?.goldilocks()
+}
+
+/// Checks the contents of `common_circuit_data.json`, `verifier_only_circuit_data.json` and
+/// `proof_with_public_inputs.json` against each other and the gnark verifier's layout.
+pub fn validate_json(
+    common: &Value,
+    verifier_only: &Value,
+    proof: &Value,
+    encoding: HashEncoding,
+) -> anyhow::Result<()> {
+    let shape = validate_common(common).context(COMMON_CIRCUIT_DATA_FILE)?;
+    validate_verifier_only(verifier_only, &shape, encoding)
+        .context(VERIFIER_ONLY_CIRCUIT_DATA_FILE)?;
+    validate_proof(proof, &shape, encoding).context(PROOF_WITH_PUBLIC_INPUTS_FILE)
+}
+
+/// Reads and validates the files written by `save_files` to `dir`.
+pub fn validate_files(dir: impl AsRef<Path>, encoding: HashEncoding) -> anyhow::Result<()> {
+    let dir = dir.as_ref();
+    let read = |name: &str| -> anyhow::Result<Value> {
+        let path = dir.join(name);
+        let contents =
+            fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+        serde_json::from_str(&contents).with_context(|| format!("parsing {}", path.display()))
+    };
+    let common = read(COMMON_CIRCUIT_DATA_FILE)?;
+    let verifier_only = read(VERIFIER_ONLY_CIRCUIT_DATA_FILE)?;
+    let proof = read(PROOF_WITH_PUBLIC_INPUTS_FILE)?;
+    if let Err(err) = validate_json(&common, &verifier_only, &proof, encoding) {
+        bail!("{} does not match gnark-plonky2-verifier's layout: {err:#}", dir.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::to_gnark_json;
+    use plonky2::field::types::Field;
+    use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+    use plonky2::plonk::circuit_builder::CircuitBuilder;
+    use plonky2::plonk::circuit_data::CircuitConfig;
+    use plonky2::plonk::config::PoseidonGoldilocksConfig;
+
+    const D: usize = 2;
+    type C = PoseidonGoldilocksConfig;
+    type F = GoldilocksField;
+
+    fn exported() -> [Value; 3] {
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let input = builder.add_virtual_target();
+        let cube = builder.exp_u64(input, 3);
+        builder.register_public_input(cube);
+        let data = builder.build::<C>();
+        let mut pw = PartialWitness::new();
+        pw.set_target(input, F::from_canonical_u64(3)).unwrap();
+        let proof = data.prove(pw).unwrap();
+        [
+            to_gnark_json(&data.common).unwrap(),
+            to_gnark_json(&data.verifier_only).unwrap(),
+            to_gnark_json(&proof).unwrap(),
+        ]
+    }
+
+    /// Validates the exported files after applying `edit` to the proof, returning the error.
+    fn error_after(edit: impl FnOnce(&mut Value)) -> String {
+        let [common, verifier_only, mut proof] = exported();
+        edit(&mut proof);
+        let err = validate_json(&common, &verifier_only, &proof, HashEncoding::PackedGoldilocks)
+            .unwrap_err();
+        format!("{err:#}")
+    }
+
+    /// Like `error_after`, editing the common circuit data.
+    fn error_in_common(edit: impl FnOnce(&mut Value)) -> String {
+        let [mut common, verifier_only, proof] = exported();
+        edit(&mut common);
+        let err = validate_json(&common, &verifier_only, &proof, HashEncoding::PackedGoldilocks)
+            .unwrap_err();
+        format!("{err:#}")
+    }
+
+    #[test]
+    fn exported_files_are_valid() {
+        let [common, verifier_only, proof] = exported();
+        let encoding = HashEncoding::of::<C, D>();
+        assert_eq!(encoding, HashEncoding::PackedGoldilocks);
+        validate_json(&common, &verifier_only, &proof, encoding).unwrap();
+    }
+
+    #[test]
+    fn errors_name_the_path() {
+        let err = error_after(|proof| {
+            proof["proof"].as_object_mut().unwrap().remove("wires_cap");
+        });
+        assert!(err.contains("$.proof: missing field \"wires_cap\""), "{err}");
+
+        let err = error_after(|proof| proof["public_inputs"][0] = Value::from(F::ORDER));
+        assert!(err.contains("$.public_inputs[0]: expected a canonical"), "{err}");
+
+        let err = error_after(|proof| {
+            let round = &mut proof["proof"]["opening_proof"]["query_round_proofs"][1];
+            let wires = &mut round["initial_trees_proof"]["evals_proofs"][1];
+            wires[1]["siblings"].as_array_mut().unwrap().pop();
+        });
+        let path = "query_round_proofs[1].initial_trees_proof.evals_proofs[1][1].siblings";
+        assert!(err.contains(&format!("$.proof.opening_proof.{path}: expected")), "{err}");
+
+        let err = error_after(|proof| {
+            proof["proof"]["quotient_polys_cap"][0] = Value::from("0x1");
+        });
+        assert!(err.contains("$.proof.quotient_polys_cap[0]: expected a decimal digest"), "{err}");
+    }
+
+    #[test]
+    fn common_data_bounds_the_shapes() {
+        let err = error_in_common(|common| {
+            common["fri_params"]["degree_bits"] = Value::from(u64::MAX);
+        });
+        assert!(err.contains("$.fri_params: degree_bits + rate_bits exceeds"), "{err}");
+        let err = error_in_common(|common| {
+            common["fri_params"]["reduction_arity_bits"] = Value::from(vec![u64::MAX, 1]);
+        });
+        assert!(err.contains("$.fri_params: FRI reduces below degree 1"), "{err}");
+        let err = error_in_common(|common| {
+            common["config"]["fri_config"]["cap_height"] = Value::from(64);
+            common["fri_params"]["config"]["cap_height"] = Value::from(64);
+        });
+        assert!(err.contains("$.fri_params: the cap height exceeds"), "{err}");
+        let err = error_in_common(|common| {
+            common["config"]["zero_knowledge"] = Value::from(true);
+            common["fri_params"]["hiding"] = Value::from(true);
+        });
+        let path = "query_round_proofs[0].initial_trees_proof.evals_proofs[1][0]";
+        assert!(err.contains(&format!("$.proof.opening_proof.{path}: expected")), "{err}");
+    }
+
+    #[test]
+    fn initial_leaves_have_one_eval_per_polynomial() {
+        let err = error_after(|proof| {
+            let round = &mut proof["proof"]["opening_proof"]["query_round_proofs"][0];
+            let quotient = &mut round["initial_trees_proof"]["evals_proofs"][3];
+            quotient[0].as_array_mut().unwrap().push(Value::from(0));
+        });
+        let path = "query_round_proofs[0].initial_trees_proof.evals_proofs[3][0]";
+        assert!(err.contains(&format!("$.proof.opening_proof.{path}: expected")), "{err}");
+    }
+
+    #[test]
+    fn gates_must_be_known_to_gnark() {
+        let err = error_in_common(|common| common["gates"][1] = Value::from("Bn254MulGate"));
+        assert!(
+            err.contains("common_circuit_data.json: $.gates[1]: Bn254MulGate is not supported"),
+            "{err}"
+        );
+        let err = error_in_common(|common| {
+            common["selectors_info"]["selector_indices"]
+                .as_array_mut()
+                .unwrap()
+                .pop();
+        });
+        assert!(err.contains("$.selectors_info.selector_indices: expected"), "{err}");
+    }
+
+    #[test]
+    fn bn254_digests_must_be_scalars() {
+        let [common, mut verifier_only, proof] = exported();
+        for hash in verifier_only["constants_sigmas_cap"].as_array_mut().unwrap() {
+            *hash = Value::from("0");
+        }
+        verifier_only["circuit_digest"] = Value::from(modulus().to_string());
+        let err = validate_json(&common, &verifier_only, &proof, HashEncoding::Bn254).unwrap_err();
+        assert!(format!("{err:#}").contains("$.circuit_digest"), "{err:#}");
+    }
+}
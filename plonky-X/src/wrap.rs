@@ -0,0 +1,266 @@
+//! Recursive wrapping of an arbitrary plonky2 proof into a proof for gnark-plonky2-verifier.
+//!
+//! The pipeline verifies the inner proof in a recursive circuit, shrinks the result with further
+//! recursion layers until it has a fixed small degree, and finally verifies the shrunk proof in
+//! a circuit proven under `PoseidonBN128GoldilocksConfig`, whose proof the Groth16 wrapper
+//! accepts. Public inputs of the inner proof are forwarded through every stage, and the gnark
+//! circuit only depends on the fixed shape of the last shrinking stage.
+
+use anyhow::{Context, ensure};
+use plonky2::field::goldilocks_field::GoldilocksField;
+use plonky2::gates::noop::NoopGate;
+use plonky2::iop::witness::{PartialWitness, WitnessWrite};
+use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData, VerifierCircuitData};
+use plonky2::plonk::config::{AlgebraicHasher, GenericConfig, PoseidonGoldilocksConfig};
+use plonky2::plonk::proof::ProofWithPublicInputs;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::poseidon_bn128::PoseidonBN128GoldilocksConfig;
+
+const D: usize = 2;
+type F = GoldilocksField;
+/// Config of the recursive and shrinking stages.
+type ShrinkC = PoseidonGoldilocksConfig;
+/// Config of the final proof handed to the Groth16 wrapper.
+pub type OuterC = PoseidonBN128GoldilocksConfig;
+
+/// Degree, size and timing of one recursion stage.
+#[derive(Clone, Debug)]
+pub struct StageReport {
+    pub name: String,
+    pub num_gates: usize,
+    pub degree_bits: usize,
+    pub build_time: Duration,
+    pub proving_time: Duration,
+}
+
+impl fmt::Display for StageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<10} gates: {:>6}  degree: 2^{:<3} build: {:>10.2?}  prove: {:>10.2?}",
+            self.name, self.num_gates, self.degree_bits, self.build_time, self.proving_time
+        )
+    }
+}
+
+/// Circuit data and proof of one stage.
+pub struct Stage<C: GenericConfig<D, F = F>> {
+    pub data: CircuitData<F, C, D>,
+    pub proof: ProofWithPublicInputs<F, C, D>,
+    pub report: StageReport,
+}
+
+/// The final BN128-hashed proof together with the reports of every stage, in order.
+pub struct WrappedProof {
+    pub data: CircuitData<F, OuterC, D>,
+    pub proof: ProofWithPublicInputs<F, OuterC, D>,
+    pub reports: Vec<StageReport>,
+}
+
+/// Settings of the wrapping pipeline.
+#[derive(Clone, Debug)]
+pub struct WrapPipeline {
+    /// Circuit config of every stage.
+    pub config: CircuitConfig,
+    /// Degree every shrinking stage is padded to, and that shrinking must reach.
+    pub shrink_degree_bits: usize,
+    /// Upper bound on the number of shrinking stages.
+    pub max_shrink_stages: usize,
+}
+
+impl Default for WrapPipeline {
+    fn default() -> Self {
+        Self {
+            config: CircuitConfig::standard_recursion_config(),
+            shrink_degree_bits: 12,
+            max_shrink_stages: 3,
+        }
+    }
+}
+
+impl WrapPipeline {
+    /// Runs the recursive stage, at least one shrinking stage and the BN128 stage on
+    /// `inner_proof`, verifying each proof as it goes.
+    ///
+    /// Fails without proving anything if `shrink_degree_bits` or `max_shrink_stages` is zero.
+    pub fn wrap<InnerC>(
+        &self,
+        inner: &VerifierCircuitData<F, InnerC, D>,
+        inner_proof: &ProofWithPublicInputs<F, InnerC, D>,
+    ) -> anyhow::Result<WrappedProof>
+    where
+        InnerC: GenericConfig<D, F = F>,
+        InnerC::Hasher: AlgebraicHasher<F>,
+    {
+        ensure!(
+            self.shrink_degree_bits >= 1,
+            "shrink_degree_bits must be at least 1"
+        );
+        ensure!(
+            self.max_shrink_stages >= 1,
+            "max_shrink_stages must be at least 1"
+        );
+        inner
+            .verify(inner_proof.clone())
+            .context("the inner proof does not verify")?;
+        let mut reports = Vec::new();
+
+        let recursive = self.stage::<InnerC, ShrinkC>("recursive", inner, inner_proof, None)?;
+        reports.push(recursive.report.clone());
+
+        let mut shrunk = recursive;
+        for i in 1..=self.max_shrink_stages {
+            let name = format!("shrink {i}");
+            let padding = Some(self.shrink_degree_bits);
+            shrunk = self.stage::<ShrinkC, ShrinkC>(
+                &name,
+                &shrunk.data.verifier_data(),
+                &shrunk.proof,
+                padding,
+            )?;
+            reports.push(shrunk.report.clone());
+            if shrunk.report.degree_bits == self.shrink_degree_bits {
+                break;
+            }
+        }
+        ensure!(
+            shrunk.report.degree_bits == self.shrink_degree_bits,
+            "could not shrink to degree 2^{} in {} stages, got 2^{}",
+            self.shrink_degree_bits,
+            self.max_shrink_stages,
+            shrunk.report.degree_bits
+        );
+
+        let outer = self.stage::<ShrinkC, OuterC>(
+            "bn128",
+            &shrunk.data.verifier_data(),
+            &shrunk.proof,
+            None,
+        )?;
+        reports.push(outer.report);
+        Ok(WrappedProof {
+            data: outer.data,
+            proof: outer.proof,
+            reports,
+        })
+    }
+
+    /// Builds and proves a circuit verifying `proof` against the constant verifier data of
+    /// `inner`, and forwarding its public inputs. With `pad_to_degree_bits`, the circuit is
+    /// padded with no-op gates so that smaller circuits end up at exactly that degree.
+    fn stage<InnerC, C>(
+        &self,
+        name: &str,
+        inner: &VerifierCircuitData<F, InnerC, D>,
+        proof: &ProofWithPublicInputs<F, InnerC, D>,
+        pad_to_degree_bits: Option<usize>,
+    ) -> anyhow::Result<Stage<C>>
+    where
+        InnerC: GenericConfig<D, F = F>,
+        InnerC::Hasher: AlgebraicHasher<F>,
+        C: GenericConfig<D, F = F>,
+    {
+        let start = Instant::now();
+        let mut builder = CircuitBuilder::<F, D>::new(self.config.clone());
+        let proof_target = builder.add_virtual_proof_with_pis(&inner.common);
+        let verifier_target = builder.constant_verifier_data(&inner.verifier_only);
+        builder.verify_proof::<InnerC>(&proof_target, &verifier_target, &inner.common);
+        builder.register_public_inputs(&proof_target.public_inputs);
+        if let Some(degree_bits) = pad_to_degree_bits {
+            // More than half of the rows, so that padding the rest yields exactly this degree.
+            while builder.num_gates() <= 1 << (degree_bits - 1) {
+                builder.add_gate(NoopGate, vec![]);
+            }
+        }
+        let num_gates = builder.num_gates();
+        let data = builder.build::<C>();
+        let build_time = start.elapsed();
+
+        let mut pw = PartialWitness::new();
+        pw.set_proof_with_pis_target(&proof_target, proof)?;
+        let start = Instant::now();
+        let proof = data.prove(pw).with_context(|| format!("proving stage {name}"))?;
+        let proving_time = start.elapsed();
+        data.verify(proof.clone())
+            .with_context(|| format!("verifying stage {name}"))?;
+
+        let report = StageReport {
+            name: name.to_string(),
+            num_gates,
+            degree_bits: data.common.degree_bits(),
+            build_time,
+            proving_time,
+        };
+        Ok(Stage {
+            data,
+            proof,
+            report,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::save_files;
+    use crate::import::verify_files;
+    use plonky2::field::types::Field;
+
+    fn add_proof() -> (CircuitData<F, ShrinkC, D>, ProofWithPublicInputs<F, ShrinkC, D>) {
+        let mut builder = CircuitBuilder::<F, D>::new(CircuitConfig::standard_recursion_config());
+        let input = builder.add_virtual_target();
+        let six = builder.constant(F::from_canonical_u64(6));
+        let sum = builder.add(input, six);
+        builder.register_public_input(sum);
+        let data = builder.build::<ShrinkC>();
+        let mut pw = PartialWitness::new();
+        pw.set_target(input, F::from_canonical_u64(7)).unwrap();
+        let proof = data.prove(pw).unwrap();
+        (data, proof)
+    }
+
+    #[test]
+    fn wrap_add_circuit() {
+        let (data, proof) = add_proof();
+        let pipeline = WrapPipeline::default();
+        let wrapped = pipeline.wrap(&data.verifier_data(), &proof).unwrap();
+        // Verifying the small add proof fits in 2^11 rows, so one padded shrinking stage
+        // reaches 2^12, and the BN128 stage verifying a 2^12 proof stays there.
+        let stages = wrapped
+            .reports
+            .iter()
+            .map(|r| (r.name.as_str(), r.degree_bits))
+            .collect::<Vec<_>>();
+        assert_eq!(stages, [("recursive", 11), ("shrink 1", 12), ("bn128", 12)]);
+        for report in &wrapped.reports {
+            assert!(report.num_gates <= 1 << report.degree_bits, "{report}");
+            assert!(report.num_gates > 1 << (report.degree_bits - 1), "{report}");
+        }
+        assert_eq!(wrapped.data.common.degree_bits(), pipeline.shrink_degree_bits);
+        assert_eq!(wrapped.proof.public_inputs, proof.public_inputs);
+
+        let dir = tempfile::tempdir().unwrap();
+        save_files(&wrapped.data, &wrapped.proof, dir.path()).unwrap();
+        verify_files::<OuterC, D>(dir.path()).unwrap();
+    }
+
+    #[test]
+    fn rejects_zero_settings() {
+        let (data, proof) = add_proof();
+        for pipeline in [
+            WrapPipeline {
+                shrink_degree_bits: 0,
+                ..WrapPipeline::default()
+            },
+            WrapPipeline {
+                max_shrink_stages: 0,
+                ..WrapPipeline::default()
+            },
+        ] {
+            assert!(pipeline.wrap(&data.verifier_data(), &proof).is_err());
+        }
+    }
+}